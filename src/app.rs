@@ -1,8 +1,28 @@
-use serde_json::{Map, Value};
+use crate::diff::{self, Comparison, Diff, Mark};
+use crate::edit::{self, Edits, Op};
+use crate::filter::Filter;
+use crate::history::{Location, NavigationHistory};
+use crate::input_history::{InputHistory, ReverseSearch};
+use crate::jq::JqWorker;
+use crate::jsonpath;
+use crate::keymap::{Action, KeyMap};
+use crate::logs::{self, LogView};
+use crate::marks::Marks;
+use crate::patch::{Patch, Preview};
+use crate::queries::SavedQueries;
+use crate::search::{self, Matcher, SearchEntry, SearchMode, SearchOptions, SearchWorker, Update};
+use crate::source::{self, SourceMap};
+use crate::stitch::Stitch;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 use std::vec;
+use tui::layout::Rect;
 use tui::widgets::ListState;
-use fuzzy_matcher::FuzzyMatcher;
-use fuzzy_matcher::skim::SkimMatcherV2;
 // use rayon::prelude::*;
 // use dashmap::DashMap;
 pub struct TabsState<'a> {
@@ -30,6 +50,9 @@ impl<'a> TabsState<'a> {
 pub struct StatefulList<T> {
     pub state: ListState,
     pub items: Vec<T>,
+    // First visible row. `ListState` keeps its own copy private, so this one
+    // follows the same rules to map mouse rows back to items.
+    pub offset: usize,
 }
 
 impl<T> StatefulList<T> {
@@ -37,79 +60,374 @@ impl<T> StatefulList<T> {
         StatefulList {
             state: ListState::default(),
             items,
+            offset: 0,
         }
     }
 
+    pub fn select(&mut self, index: usize) {
+        if index < self.items.len() {
+            self.state.select(Some(index));
+        }
+    }
+
+    // Scrolls like `List` does for single line items in `height` rows
+    pub fn sync_offset(&mut self, height: usize) {
+        self.offset = scroll_to(self.offset, self.state.selected(), self.items.len(), height);
+    }
+
     pub fn next(&mut self) {
-        let i = match self.state.selected() {
-            Some(i) => {
-                if i >= self.items.len() - 1 {
-                    0
-                } else {
-                    i + 1
-                }
-            }
-            None => 0,
-        };
-        self.state.select(Some(i));
+        if let Some(i) = step(self.state.selected(), self.items.len(), true) {
+            self.state.select(Some(i));
+        }
     }
 
     pub fn previous(&mut self) {
-        let i = match self.state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    self.items.len() - 1
-                } else {
-                    i - 1
-                }
-            }
-            None => 0,
+        if let Some(i) = step(self.state.selected(), self.items.len(), false) {
+            self.state.select(Some(i));
+        }
+    }
+}
+
+/// The children of the level being shown.
+///
+/// Values stay in the document and are looked up only for the rows on screen,
+/// and array indices are not stored at all, so opening an array of a million
+/// items costs nothing. Object keys are cached once per level.
+pub struct Level {
+    pub state: ListState,
+    // First visible row, see `StatefulList::offset`
+    pub offset: usize,
+    // Keys in display order; `None` for arrays
+    keys: Option<Vec<String>>,
+    len: usize,
+}
+
+impl Level {
+    fn new(value: &Value) -> Level {
+        let (keys, len) = match value {
+            Value::Object(map) => (Some(map.keys().cloned().collect::<Vec<_>>()), map.len()),
+            Value::Array(items) => (None, items.len()),
+            _ => (Some(Vec::new()), 0),
         };
-        self.state.select(Some(i));
+        Level {
+            state: ListState::default(),
+            offset: 0,
+            keys,
+            len,
+        }
+    }
+
+    // A level showing only the members with these keys
+    fn only(keys: Vec<String>) -> Level {
+        Level {
+            state: ListState::default(),
+            offset: 0,
+            len: keys.len(),
+            keys: Some(keys),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Key of row `index`: the object key, or the array index as text.
+    pub fn key(&self, index: usize) -> Option<Cow<'_, str>> {
+        if index >= self.len {
+            return None;
+        }
+        match self.keys.as_ref() {
+            Some(keys) => keys.get(index).map(|k| Cow::Borrowed(k.as_str())),
+            None => Some(Cow::Owned(index.to_string())),
+        }
+    }
+
+    pub fn select(&mut self, index: usize) {
+        if index < self.len {
+            self.state.select(Some(index));
+        }
+    }
+
+    pub fn sync_offset(&mut self, height: usize) {
+        self.offset = scroll_to(self.offset, self.state.selected(), self.len, height);
+    }
+
+    pub fn next(&mut self) {
+        if let Some(i) = step(self.state.selected(), self.len, true) {
+            self.state.select(Some(i));
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if let Some(i) = step(self.state.selected(), self.len, false) {
+            self.state.select(Some(i));
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+// The row after (or before) `selected` among `len` rows, wrapping around
+fn step(selected: Option<usize>, len: usize, forward: bool) -> Option<usize> {
+    if len == 0 {
+        return None;
+    }
+    Some(match selected {
+        Some(i) if forward => if i >= len - 1 { 0 } else { i + 1 },
+        Some(i) => if i == 0 { len - 1 } else { i - 1 },
+        None => 0,
+    })
+}
+
+// First visible row once `selected` is kept within `height` rows, the way
+// `List` scrolls single line items
+pub fn scroll_to(offset: usize, selected: Option<usize>, len: usize, height: usize) -> usize {
+    let mut offset = offset.min(len.saturating_sub(1));
+    if let Some(selected) = selected {
+        if height > 0 && selected >= offset + height {
+            offset = selected + 1 - height;
+        } else if selected < offset {
+            offset = selected;
+        }
+    }
+    offset
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Route {
     Search,
     Main,
+    Help,
+    Command,
+    History,
+    Marks,
+    Detail,
+    Jq,
+    Queries,
+    Edit,
+    Confirm,
+    Logs,
 }
+
+impl Route {
+    pub const ALL: &'static [Route] = &[
+        Route::Main,
+        Route::Search,
+        Route::Command,
+        Route::Jq,
+        Route::Edit,
+        Route::Confirm,
+        Route::Logs,
+        Route::History,
+        Route::Marks,
+        Route::Queries,
+        Route::Detail,
+        Route::Help,
+    ];
+
+    /// Name of the route's section in the keymap file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Route::Main => "main",
+            Route::Search => "search",
+            Route::Help => "help",
+            Route::Command => "command",
+            Route::History => "history",
+            Route::Marks => "marks",
+            Route::Queries => "queries",
+            Route::Edit => "edit",
+            Route::Confirm => "confirm",
+            Route::Logs => "logs",
+            Route::Detail => "detail",
+            Route::Jq => "jq",
+        }
+    }
+
+    /// Whether unbound printable keys are typed into a prompt on this route.
+    pub fn takes_text(self) -> bool {
+        matches!(self, Route::Search | Route::Command | Route::Jq | Route::Edit)
+    }
+}
+/// What the editor prompt is changing.
+pub enum Editing {
+    // The string, number, boolean or null at the path
+    Value(Vec<String>),
+    // The key at the index of the object at the path
+    Rename(Vec<String>, usize),
+    // A key to add at the index of the object at the path
+    NewKey(Vec<String>, usize),
+}
+
+/// What the Confirm prompt asks before going ahead.
+pub enum Confirmation {
+    // Deleting the selected member, this many values in all
+    Delete(usize),
+    // Reading the file again over unsaved edits
+    Reload,
+    // Showing pages stitched together in place of a document with unsaved
+    // edits
+    Stitch(Box<(Value, Stitch)>),
+    // Quitting with unsaved edits
+    Quit,
+}
+
+// Deleting more values than this at once asks first
+const LARGE_DELETE: usize = 100;
+
+/// The query a derived document is the result of.
+#[derive(Debug)]
+pub enum Derivation {
+    Jq(String),
+    Sql(String),
+    // Not a query: the document with a patch applied, until it is accepted
+    Patch(Box<Preview>),
+}
+
+impl Derivation {
+    pub fn label(&self) -> String {
+        match self {
+            Derivation::Jq(filter) => format!("jq: {}", filter),
+            Derivation::Sql(query) => format!("sql: {}", query),
+            Derivation::Patch(preview) => format!("patch: {}", preview.file),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Index {
     Key(String),
 }
+// One row of the Output list, borrowing its value from the document
 #[derive(Debug)]
-pub enum Element {
-    Bool(Index, serde_json::Value),
-    String(Index, serde_json::Value),
-    Array(Index, serde_json::Value),
-    Object(Index, serde_json::Value),
-    Number(Index, serde_json::Value),
+pub enum Element<'v> {
+    Bool(Index, &'v Value),
+    String(Index, &'v Value),
+    Array(Index, &'v Value),
+    Object(Index, &'v Value),
+    Number(Index, &'v Value),
     Null(Index),
 }
 
+// Screen areas from the last draw, used to hit-test mouse events
+#[derive(Default)]
+pub struct Areas {
+    pub input: Rect,
+    pub output: Rect,
+    // One rect per breadcrumb, indexed by navigation depth
+    pub breadcrumbs: Vec<Rect>,
+    // The Input and Preview tabs in the title of the left pane
+    pub tabs: Vec<Rect>,
+}
+
+// Which part of the Search route Up and Down move in: the search bar, where
+// they step through earlier queries, or the results
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ActiveBlock {
     Search,
     Output
 }
 pub struct App<'a> {
-    pub data: &'a str,
+    pub data: String,
+    pub source: Option<PathBuf>,
+    // When the source file was last modified as far as we know
+    pub source_modified: Option<SystemTime>,
     pub tabs: TabsState<'a>,
     pub user_input: String,
     pub input_cursor_position: u16,
-    pub json: Option<serde_json::Value>,
+    // Shared with the search worker, which indexes it
+    pub json: Option<Arc<Value>>,
     pub navigation_stack: Vec<String>,
-    pub elements: Option<StatefulList<Element>>,
+    pub elements: Option<Level>,
     pub current_route: Route,
-    pub fuzzy_elements: Option<StatefulList<String>>,
+    // Every searchable entry once the worker has indexed the document, and
+    // the indices of those matching `user_input`
+    pub search_entries: Arc<Vec<SearchEntry>>,
+    pub fuzzy_elements: Option<StatefulList<usize>>,
+    pub search_options: SearchOptions,
+    // The query that produced the results, for highlighting them
+    pub search_matcher: Option<Matcher>,
+    // How many entries the query matched and was run against; only the
+    // best `search::MAX_RESULTS` are listed, scored by `search_scores`
+    pub search_matched: usize,
+    pub search_total: usize,
+    pub search_scores: Vec<i64>,
+    // The normalized path of each JSONPath match, by entry
+    pub search_locations: HashMap<usize, String>,
+    pub search_worker: Option<SearchWorker>,
+    // Generation of the last query asked for and of the results shown
+    pub search_generation: u64,
+    pub search_shown: u64,
+    // The worker is still indexing or still matching
+    pub search_busy: bool,
+    // Why the query can't be run, e.g. an invalid regex
+    pub search_error: Option<String>,
     pub active_block: ActiveBlock,
-
+    pub keymap: KeyMap,
+    // Problems in the keymap and saved queries files, listed atop the help
+    pub config_problems: Vec<String>,
+    pub previous_route: Route,
+    pub help_scroll: u16,
+    pub status: Option<String>,
+    pub areas: Areas,
+    // First line shown in the Input pane
+    pub input_scroll: usize,
+    pub last_click: Option<(Instant, u16, u16)>,
+    pub mouse_capture: bool,
+    pub history: NavigationHistory,
+    pub history_list: ListState,
+    pub command_input: String,
+    pub marks: Marks,
+    pub marks_list: ListState,
+    // Set by actions that wait for one more key, like `m` for the mark letter
+    pub pending: Option<Action>,
+    // Node shown in full on the Detail route
+    pub detail_path: Vec<String>,
+    pub detail_scroll: u16,
+    // The left pane shows the highlighted subtree instead of the raw input
+    pub show_preview: bool,
+    pub preview_path: Vec<String>,
+    pub preview_scroll: u16,
+    // Spans of the raw input, and the node the Input pane last scrolled to
+    pub source_map: Option<SourceMap>,
+    pub source_path: Option<Vec<String>>,
+    pub source_column: usize,
+    // The jq prompt, what the shown document was derived by, and the file's
+    // own document while a derived one is shown in its place
+    pub jq_input: String,
+    pub jq: JqWorker,
+    pub jq_result: Option<Result<Value, String>>,
+    // Enter was pressed before the filter had finished
+    pub jq_waiting: bool,
+    pub derived: Option<Derivation>,
+    pub original: Option<(String, Arc<Value>)>,
+    // What was typed into the prompts before, and a Ctrl-r search through it
+    pub input_history: InputHistory,
+    pub reverse_search: Option<ReverseSearch>,
+    // The editor prompt, the changes made for undo and redo, and the nodes
+    // changed since the document was read or saved
+    pub edit_input: String,
+    pub editing: Editing,
+    pub edits: Edits,
+    pub modified: HashSet<Vec<String>>,
+    // What the Confirm prompt is asking about
+    pub confirming: Option<Confirmation>,
+    // Quitting was confirmed; the event loop ends
+    pub quitting: bool,
+    // Where the elements came from, if pages were stitched into one array
+    pub stitched: Option<Stitch>,
+    // The two files being compared, if the document is their difference
+    pub compare: Option<Comparison>,
+    // The log view of an array of tracing records
+    pub logs: Option<LogView>,
+    // Queries saved in the project file and the picker listing them
+    pub saved_queries: SavedQueries,
+    pub queries_list: ListState,
 }
 
 impl<'a> App<'a> {
-    pub fn init(data: &str) -> App {
+    pub fn init(data: String) -> App<'a> {
         App {
             data,
+            source: None,
+            source_modified: None,
             tabs: TabsState::new(vec!["Tab0", "Tab1"]),
             user_input: String::new(),
             input_cursor_position: 0,
@@ -117,8 +435,62 @@ impl<'a> App<'a> {
             navigation_stack: vec![String::new()],
             elements: None,
             current_route: Route::Main,
+            search_entries: Arc::new(Vec::new()),
             fuzzy_elements: None,
-            active_block : ActiveBlock::Output
+            search_options: SearchOptions::default(),
+            search_matcher: None,
+            search_matched: 0,
+            search_total: 0,
+            search_scores: Vec::new(),
+            search_locations: HashMap::new(),
+            search_worker: None,
+            search_generation: 0,
+            search_shown: 0,
+            search_busy: false,
+            search_error: None,
+            active_block : ActiveBlock::Output,
+            keymap: KeyMap::default(),
+            config_problems: Vec::new(),
+            previous_route: Route::Main,
+            help_scroll: 0,
+            status: None,
+            areas: Areas::default(),
+            input_scroll: 0,
+            last_click: None,
+            mouse_capture: true,
+            history: NavigationHistory::new(),
+            history_list: ListState::default(),
+            command_input: String::new(),
+            marks: Marks::new(),
+            marks_list: ListState::default(),
+            pending: None,
+            detail_path: Vec::new(),
+            detail_scroll: 0,
+            show_preview: false,
+            preview_path: Vec::new(),
+            preview_scroll: 0,
+            source_map: None,
+            source_path: None,
+            source_column: 1,
+            jq_input: String::new(),
+            jq: JqWorker::new(),
+            jq_result: None,
+            jq_waiting: false,
+            derived: None,
+            original: None,
+            input_history: InputHistory::new(),
+            reverse_search: None,
+            edit_input: String::new(),
+            editing: Editing::Value(Vec::new()),
+            edits: Edits::new(),
+            modified: HashSet::new(),
+            confirming: None,
+            quitting: false,
+            logs: None,
+            stitched: None,
+            compare: None,
+            saved_queries: SavedQueries::default(),
+            queries_list: ListState::default(),
         }
     }
     pub fn get_current_navigation_stack(&self) -> String {
@@ -133,274 +505,1071 @@ impl<'a> App<'a> {
     pub fn set_current_route(&mut self , route : Route) -> () {
         self.current_route = route
     }
+    pub fn toggle_help(&mut self) {
+        if self.current_route == Route::Help {
+            self.current_route = self.previous_route;
+        } else {
+            self.previous_route = self.current_route;
+            self.help_scroll = 0;
+            self.current_route = Route::Help;
+        }
+    }
     pub fn set_json(&mut self, js: Option<serde_json::value::Value>) {
+        self.set_shared_json(js.map(Arc::new));
+    }
+    fn set_shared_json(&mut self, js: Option<Arc<Value>>) {
         self.json = js;
+        self.reindex();
+        self.source_map = SourceMap::new(&self.data);
+        self.source_path = None;
     }
-    pub fn set_elements(&mut self) -> () {
-        let mut vec_list = Vec::new();
-        let mut js;
-        if self.navigation_stack.len() > 0 {
-            let s: String = self.navigation_stack.join("/");
-            js = self.json.as_ref().unwrap().pointer(&s);
-        } else {
-            js = self.json.as_ref();
-        }
-
-        // let dashmap: DashMap<String, Value> = DashMap::new();
-        if js.as_ref().unwrap().is_object() {
-            js.as_mut()
-                .unwrap()
-                .as_object()
-                .unwrap()
-                .iter()
-                .for_each(|(f, j)| {
-                    // dashmap.insert(f.clone(), j.clone());
-                    vec_list.push(get_element(f, j))
-                });
-        } else {
-            for (k, j) in js.as_ref().unwrap().as_array().unwrap().iter().enumerate() {
-                // dashmap.insert(k.to_string(), j.clone());
-                vec_list.push(get_element(&k.to_string(), j));
+    // Indexes the document for search again, in the background
+    fn reindex(&mut self) {
+        self.search_entries = Arc::new(Vec::new());
+        if let Some(list) = self.fuzzy_elements.as_mut() {
+            *list = StatefulList::with_items(Vec::new());
+        }
+        // The new worker counts its generations from the start again
+        self.search_scores.clear();
+        self.search_shown = 0;
+        self.search_worker = self.json.clone().map(SearchWorker::new);
+        self.search_busy = self.search_worker.is_some();
+        if self.current_route == Route::Search {
+            self.search_and_set_fuzzy_data();
+        }
+    }
+    // Remembers which file the document came from and loads its marks
+    pub fn set_source(&mut self, source: Option<PathBuf>) {
+        self.marks = match source.as_ref() {
+            Some(path) => Marks::load(path),
+            None => Marks::new(),
+        };
+        self.source_modified = source.as_deref().and_then(modified_time);
+        self.source = source;
+    }
+    // Whether the source file was written by someone else since we read it
+    pub fn source_changed(&self) -> bool {
+        match self.source.as_deref() {
+            Some(path) => modified_time(path) != self.source_modified,
+            None => false,
+        }
+    }
+    // Takes `text`, just written to `path`, as what the document was read
+    // from: the Input pane shows it and the edits so far count as saved
+    pub fn written(&mut self, path: PathBuf, text: String) {
+        if self.source.as_ref() != Some(&path) {
+            self.set_source(Some(path));
+        }
+        self.source_modified = self.source.as_deref().and_then(modified_time);
+        self.data = text;
+        self.source_map = SourceMap::new(&self.data);
+        self.source_path = None;
+        self.edits.mark_saved();
+        self.modified = self.edits.modified();
+    }
+    // Reads the source file again, keeping our place and the marks that still
+    // point somewhere. Returns the letters of the marks that were dropped.
+    pub fn reload(&mut self) -> Result<Vec<char>, String> {
+        let path = self.source.as_ref().ok_or("the document was not read from a file")?;
+        let data = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let json: Value = source::parse(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.source_modified = modified_time(path);
+        let location = self.location();
+        self.data = data;
+        self.set_json(Some(json));
+        // A query over the old contents no longer applies, nor do edits
+        self.original = None;
+        self.derived = None;
+        self.edits = Edits::new();
+        self.modified.clear();
+        self.go_to(&location);
+        self.marks
+            .retain_existing(self.json.as_ref().unwrap())
+            .map_err(|e| format!("could not save marks: {}", e))
+    }
+    // Shows another document, keeping our place where it still exists
+    fn show_document(&mut self, data: String, json: Arc<Value>) {
+        let location = self.location();
+        self.data = data;
+        self.set_shared_json(Some(json));
+        self.go_to(&location);
+    }
+    // Replaces the document with pages stitched together; it has no file of
+    // its own until it is written
+    pub fn show_stitched(&mut self, value: Value, stitch: Stitch) -> Result<String, String> {
+        let summary = stitch.summary();
+        self.data = serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?;
+        self.original = None;
+        self.derived = None;
+        self.edits = Edits::new();
+        self.modified.clear();
+        self.logs = None;
+        self.history = NavigationHistory::new();
+        self.compare = None;
+        self.set_source(None);
+        self.set_json(Some(value));
+        self.go_to(&Location::root());
+        self.stitched = Some(stitch);
+        Ok(summary)
+    }
+    // Shows two files laid over each other, each node marked with how it
+    // differs; the result is for reading, not editing
+    pub fn show_comparison(&mut self, comparison: Comparison) -> Result<(), String> {
+        let value = comparison.diff.value.clone();
+        self.data = serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?;
+        self.original = None;
+        self.derived = None;
+        self.edits = Edits::new();
+        self.modified.clear();
+        self.logs = None;
+        self.stitched = None;
+        self.history = NavigationHistory::new();
+        self.set_source(None);
+        self.compare = Some(comparison);
+        self.set_json(Some(value));
+        self.go_to(&Location::root());
+        Ok(())
+    }
+    // Shows the result of a query in place of the file's own document
+    pub fn show_derived(&mut self, value: Value, derivation: Derivation) -> Result<(), String> {
+        if self.original.is_none() {
+            let data = std::mem::take(&mut self.data);
+            self.original = Some((data, self.json.clone().unwrap()));
+        }
+        let data = serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?;
+        self.show_document(data, Arc::new(value));
+        self.derived = Some(derivation);
+        Ok(())
+    }
+    pub fn show_original(&mut self) {
+        if let Some((data, json)) = self.original.take() {
+            self.show_document(data, json);
+        }
+        self.derived = None;
+    }
+    // Queries always run on the file's own document
+    pub fn original_json(&self) -> Arc<Value> {
+        match self.original.as_ref() {
+            Some((_, json)) => json.clone(),
+            None => self.json.clone().unwrap(),
+        }
+    }
+    // The keys of `action` in the main view, for hints in messages
+    pub fn keys(&self, action: Action) -> String {
+        let keys: Vec<String> = self.keymap.keys_for(Route::Main, action).iter().map(|k| k.to_string()).collect();
+        match keys.is_empty() {
+            true => format!("{} (unbound)", action.name()),
+            false => keys.join("/"),
+        }
+    }
+    // Opens the jq prompt on the filter in use, if any
+    pub fn start_jq(&mut self) {
+        self.jq_input = match self.derived.as_ref() {
+            Some(Derivation::Jq(filter)) => filter.clone(),
+            _ => String::new(),
+        };
+        self.set_current_route(Route::Jq);
+        self.run_jq();
+    }
+    pub fn run_jq(&mut self) {
+        self.jq_result = None;
+        self.jq_waiting = false;
+        if !self.jq_input.trim().is_empty() {
+            self.jq.run(&self.jq_input, self.original_json());
+        }
+    }
+    pub fn poll_jq(&mut self) {
+        let result = match self.jq.try_recv() {
+            Some(result) => result,
+            None => return,
+        };
+        self.jq_result = Some(result);
+        if !self.jq_waiting {
+            return;
+        }
+        let from_prompt = self.current_route == Route::Jq;
+        match self.apply_jq() {
+            Ok(_) if from_prompt => {
+                self.remember_input();
+                self.set_current_route(Route::Main);
             }
+            Ok(_) => {}
+            Err(e) if from_prompt => self.jq_result = Some(Err(e)),
+            Err(e) => self.status = Some(e),
         }
-
-        // dashmap.into_read_only().iter().filter_map(|key, val| )
-        // panic!("The value of dashmap is {:#?}",dashmap);
-        self.elements = Some(StatefulList::with_items(vec_list));
+    }
+    // Shows the result of the jq prompt as the document, or the original
+    // again if the prompt is empty. Gives false if the filter is still
+    // running; its result is shown when it comes.
+    pub fn apply_jq(&mut self) -> Result<bool, String> {
+        if self.jq_input.trim().is_empty() {
+            self.show_original();
+            return Ok(true);
+        }
+        self.jq_waiting = self.jq_result.is_none();
+        let value = match self.jq_result.take() {
+            Some(result) => result?,
+            None => return Ok(false),
+        };
+        self.show_derived(value, Derivation::Jq(self.jq_input.clone()))?;
+        Ok(true)
+    }
+    // Shows the file's document with `patch` applied and what it changes
+    // marked, until the patch is accepted or discarded
+    pub fn preview_patch(&mut self, file: String, patch: Patch) -> Result<String, String> {
+        match self.derived.as_ref() {
+            _ if self.compare.is_some() => {
+                return Err(String::from("A comparison of two files can't be patched; open one of them"))
+            }
+            None | Some(Derivation::Patch(_)) => {}
+            Some(_) => return Err(String::from("Patches go to the original document; go back to it first")),
+        }
+        let json = self.json.as_ref().ok_or("there is no document")?;
+        let original = self.original.as_ref().map_or(json, |(_, json)| json).clone();
+        let (result, problems) = patch.apply(&original);
+        let diff = diff::diff(&original, &result);
+        let (added, removed, changed) = diff.counts();
+        let message = match problems.len() {
+            0 => format!(
+                "{} added, {} removed, {} replaced; {} applies the patch, {} discards it",
+                added,
+                removed,
+                changed,
+                self.keys(Action::AcceptPatch),
+                self.keys(Action::DiscardPatch)
+            ),
+            n => format!(
+                "{} of the operations failed, so the patch can't be applied; {} discards it",
+                n,
+                self.keys(Action::DiscardPatch)
+            ),
+        };
+        let value = diff.value.clone();
+        self.show_derived(value, Derivation::Patch(Box::new(Preview { file, result, problems, diff })))?;
+        Ok(message)
+    }
+    // Makes the patch being previewed an edit of the document, to undo or
+    // write like any other
+    pub fn accept_patch(&mut self) -> Result<String, String> {
+        match self.derived.as_ref() {
+            Some(Derivation::Patch(preview)) if !preview.problems.is_empty() => {
+                return Err(format!(
+                    "{} of the operations failed; fix the patch or {} to discard it",
+                    preview.problems.len(),
+                    self.keys(Action::DiscardPatch)
+                ))
+            }
+            Some(Derivation::Patch(_)) => {}
+            _ => return Err(String::from("There is no patch to apply; :patch <file> previews one")),
+        }
+        let to = self.location();
+        let preview = match self.derived.take() {
+            Some(Derivation::Patch(preview)) => preview,
+            _ => return Ok(String::new()),
+        };
+        self.show_original();
+        if preview.diff.marks.is_empty() {
+            return Ok(format!("{} changes nothing", preview.file));
+        }
+        let from = self.location();
+        let op = self.apply(&[], Op::Set(preview.result))?;
+        self.record(edit::Change {
+            description: format!("Applied {}", preview.file),
+            path: Vec::new(),
+            op,
+            from,
+            to,
+            marked: preview.diff.touched,
+        })
+    }
+    pub fn discard_patch(&mut self) -> Result<String, String> {
+        match self.derived.as_ref() {
+            Some(Derivation::Patch(preview)) => {
+                let message = format!("Discarded {}", preview.file);
+                self.show_original();
+                Ok(message)
+            }
+            _ => Err(String::from("There is no patch to discard")),
+        }
+    }
+    // How the document shown differs from another: a patch preview from the
+    // document, or the newer of two files compared from the older
+    pub fn changes(&self) -> Option<&Diff> {
+        match (self.derived.as_ref(), self.compare.as_ref()) {
+            (Some(Derivation::Patch(preview)), _) => Some(&preview.diff),
+            (None, Some(comparison)) => Some(&comparison.diff),
+            _ => None,
+        }
+    }
+    // Highlights the next or previous node that differs, wherever it is
+    pub fn next_change(&mut self, forward: bool) -> Result<String, String> {
+        let diff = self.changes().ok_or("Nothing is compared here; :patch or json_tui diff compare documents")?;
+        let (number, path) = diff.next(&self.selected_path(), forward).ok_or("There are no differences")?;
+        let (number, total, path) = (number, diff.marks.len(), path.to_vec());
+        let description = match diff.mark(&path) {
+            Some(Mark::Added) => "added",
+            Some(Mark::Removed) => "removed",
+            _ => "changed",
+        };
+        if let Some(location) = self.location_in_parent(&path) {
+            self.jump(&location);
+        }
+        Ok(format!("Difference {} of {}: {} {}", number, total, to_pointer(&path), description))
+    }
+    // Shows only the members that differ in objects and arrays holding
+    // differences, or everything again
+    pub fn toggle_unchanged(&mut self) -> Result<String, String> {
+        let comparison = match (self.derived.as_ref(), self.compare.as_mut()) {
+            (None, Some(comparison)) => comparison,
+            _ => return Err(String::from("Unchanged nodes can only be hidden when comparing two files")),
+        };
+        comparison.hide_unchanged = !comparison.hide_unchanged;
+        let message = match comparison.hide_unchanged {
+            true => "Unchanged nodes hidden",
+            false => "Unchanged nodes shown",
+        };
+        // Stay on the highlighted node if it is still shown
+        let path = self.selected_path();
+        let location = match path.len() >= self.navigation_stack.len() {
+            true => self.location_in_parent(&path),
+            false => None,
+        };
+        let location = location.unwrap_or(Location {
+            path: self.navigation_stack[1..].to_vec(),
+            selected: None,
+        });
+        self.go_to(&location);
+        Ok(String::from(message))
+    }
+    // The keys of the members of the level at `path` that are shown, if
+    // unchanged ones are hidden there: only in a comparison, and only in
+    // levels that hold differences without differing as a whole
+    fn shown_keys(&self, path: &[String]) -> Option<Vec<String>> {
+        let diff = match self.compare.as_ref() {
+            Some(comparison) if comparison.hide_unchanged && self.derived.is_none() => &comparison.diff,
+            _ => return None,
+        };
+        if !diff.differs(path) || diff.marks.contains_key(path) {
+            return None;
+        }
+        let mut member = path.to_vec();
+        let keys = match self.json.as_ref()?.pointer(&to_pointer(path))? {
+            Value::Object(map) => map.keys().cloned().collect(),
+            Value::Array(items) => (0..items.len()).map(|i| i.to_string()).collect::<Vec<_>>(),
+            _ => return None,
+        };
+        Some(
+            keys.into_iter()
+                .filter(|key| {
+                    member.push(key.clone());
+                    let differs = diff.differs(&member);
+                    member.pop();
+                    differs
+                })
+                .collect(),
+        )
+    }
+    // The row of the member `key` in the level at `path`, as it is shown
+    fn row_of(&self, path: &[String], key: &str) -> Option<usize> {
+        if let Some(keys) = self.shown_keys(path) {
+            return keys.iter().position(|k| k == key);
+        }
+        match self.json.as_ref()?.pointer(&to_pointer(path))? {
+            Value::Object(map) => map.keys().position(|k| k == key),
+            Value::Array(items) => key.parse::<usize>().ok().filter(|i| *i < items.len()),
+            _ => None,
+        }
+    }
+    // The location with the node at `path` highlighted in its parent
+    fn location_in_parent(&self, path: &[String]) -> Option<Location> {
+        let (last, parent) = path.split_last()?;
+        Some(Location {
+            path: parent.to_vec(),
+            selected: Some(self.row_of(parent, last)?),
+        })
+    }
+    // Edits go to the file's own document, not to a query's result
+    fn check_editable(&self) -> Result<(), String> {
+        if self.compare.is_some() {
+            return Err(String::from("A comparison of two files can't be edited; open one of them"));
+        }
+        match (self.derived.as_ref(), self.json.as_ref()) {
+            (Some(_), _) => Err(String::from("Edits go to the original document; go back to it first")),
+            (None, None) => Err(String::from("There is no document to edit")),
+            (None, Some(_)) => Ok(()),
+        }
+    }
+    // The path of the object or array being shown, the object or array,
+    // and the highlighted row in it
+    fn current_level(&self) -> Result<(Vec<String>, &Value, Option<usize>), String> {
+        self.check_editable()?;
+        let path = self.navigation_stack[1..].to_vec();
+        let container = self.value_at(&path)?;
+        let selected = self.elements.as_ref().and_then(|l| l.state.selected());
+        Ok((path, container, selected))
+    }
+    // Opens the editor on the highlighted value if it is a scalar
+    pub fn start_edit(&mut self) -> Result<(), String> {
+        self.check_editable()?;
+        let value = match self.selected_value() {
+            Some(v) if !(v.is_object() || v.is_array()) => v,
+            Some(_) => return Err(String::from("Only strings, numbers, booleans and null can be edited")),
+            None => return Ok(()),
+        };
+        self.edit_input = value.to_string();
+        self.editing = Editing::Value(self.selected_path());
+        self.set_current_route(Route::Edit);
+        Ok(())
+    }
+    // Opens the editor on the highlighted key
+    pub fn start_rename(&mut self) -> Result<(), String> {
+        let (path, container, selected) = self.current_level()?;
+        let (index, key) = match (container.as_object(), selected) {
+            (Some(map), Some(i)) => (i, map.keys().nth(i).cloned().unwrap_or_default()),
+            (Some(_), None) => return Ok(()),
+            (None, _) => return Err(String::from("Only keys of objects can be renamed")),
+        };
+        self.edit_input = key;
+        self.editing = Editing::Rename(path, index);
+        self.set_current_route(Route::Edit);
+        Ok(())
+    }
+    // Adds a member next to the highlighted one: an array gets a null right
+    // away, an object asks for the key first
+    pub fn start_insert(&mut self, after: bool) -> Result<String, String> {
+        let (path, container, selected) = self.current_level()?;
+        let index = match selected {
+            Some(i) if after => i + 1,
+            Some(i) => i,
+            None => 0,
+        };
+        match container {
+            Value::Array(_) => {
+                let description = format!("Inserted {}", member_pointer(&path, &index.to_string()));
+                self.change(description, path, Op::Insert(index, String::new(), Value::Null), index)
+            }
+            Value::Object(_) => {
+                self.edit_input = String::new();
+                self.editing = Editing::NewKey(path, index);
+                self.set_current_route(Route::Edit);
+                Ok(String::new())
+            }
+            _ => Err(String::from("There is nothing to insert into")),
+        }
+    }
+    // Applies what was typed into the editor. Invalid input leaves it open
+    // with the problem shown.
+    pub fn confirm_edit(&mut self) -> Result<String, String> {
+        let problem = self.edit_problem();
+        if problem.is_some() {
+            return Ok(String::new());
+        }
+        let input = self.edit_input.clone();
+        self.set_current_route(Route::Main);
+        match std::mem::replace(&mut self.editing, Editing::Value(Vec::new())) {
+            Editing::Value(path) => {
+                let value = self.parse_edit()?;
+                let pointer = to_pointer(&path);
+                let location = self.location();
+                self.change_value(format!("Changed {}", pointer), path, value, location)
+            }
+            Editing::Rename(path, index) => {
+                let old = key_at(self.value_at(&path)?, index).unwrap_or_default();
+                let description =
+                    format!("Renamed {} to {}", member_pointer(&path, &old), member_pointer(&path, &input));
+                self.change(description, path, Op::Rename(index, input), index)
+            }
+            Editing::NewKey(path, index) => {
+                let description = format!("Added {}", member_pointer(&path, &input));
+                let message = self.change(description, path, Op::Insert(index, input, Value::Null), index)?;
+                // Straight on to its value
+                self.start_edit()?;
+                Ok(message)
+            }
+        }
+    }
+    // Why the editor's input can't be applied, if it can't
+    pub fn edit_problem(&self) -> Option<String> {
+        let key_taken = |path: &[String], skip: Option<usize>| {
+            let container = self.json.as_ref()?.pointer(&to_pointer(path))?.as_object()?;
+            let position = container.keys().position(|k| *k == self.edit_input)?;
+            match skip {
+                Some(index) if index == position => None,
+                _ => Some(format!("there is a key {} already", Value::from(self.edit_input.as_str()))),
+            }
+        };
+        match &self.editing {
+            Editing::Value(_) => self.parse_edit().err(),
+            Editing::Rename(path, index) => key_taken(path, Some(*index)),
+            Editing::NewKey(path, _) => key_taken(path, None),
+        }
+    }
+    // The value typed into the editor, if it is valid JSON
+    pub fn parse_edit(&self) -> Result<Value, String> {
+        serde_json::from_str(self.edit_input.trim()).map_err(|e| {
+            // Unquoted text is the usual mistake
+            match self.edit_input.trim().chars().next() {
+                Some(c) if c.is_alphabetic() => String::from("not JSON; put text in double quotes"),
+                _ => e.to_string(),
+            }
+        })
+    }
+    // Deletes the highlighted member, asking first if it is big
+    pub fn confirm(&mut self, confirmation: Confirmation) {
+        self.confirming = Some(confirmation);
+        self.set_current_route(Route::Confirm);
+    }
+    pub fn delete_selected(&mut self, confirmed: bool) -> Result<String, String> {
+        let (path, container, selected) = self.current_level()?;
+        let index = match selected {
+            Some(i) => i,
+            None => return Ok(String::new()),
+        };
+        let member = match container {
+            Value::Object(map) => map.values().nth(index),
+            Value::Array(items) => items.get(index),
+            _ => None,
+        };
+        let size = member.map_or(0, edit::size);
+        if size > LARGE_DELETE && !confirmed {
+            self.confirm(Confirmation::Delete(size));
+            return Ok(String::new());
+        }
+        let description = format!("Deleted {}", member_pointer(&path, &key_at(container, index).unwrap_or_default()));
+        self.change(description, path, Op::Remove(index), index)
+    }
+    // Moves the highlighted member up or down among its siblings
+    pub fn move_selected(&mut self, up: bool) -> Result<String, String> {
+        let (path, container, selected) = self.current_level()?;
+        let index = match selected {
+            Some(i) => i,
+            None => return Ok(String::new()),
+        };
+        let other = match up {
+            true => index.checked_sub(1),
+            false => Some(index + 1).filter(|i| *i < edit::len(container)),
+        };
+        let other = match other {
+            Some(other) => other,
+            None => return Ok(String::new()),
+        };
+        let description = format!(
+            "Moved {} {}",
+            member_pointer(&path, &key_at(container, index).unwrap_or_default()),
+            if up { "up" } else { "down" }
+        );
+        self.change(description, path, Op::Swap(index, other), other)
+    }
+    // Puts a copy of the highlighted member right after it
+    pub fn duplicate_selected(&mut self) -> Result<String, String> {
+        let (path, container, selected) = self.current_level()?;
+        let (index, key, value) = match (container, selected) {
+            (Value::Object(map), Some(i)) => {
+                let (key, value) = map.iter().nth(i).ok_or("Nothing to duplicate")?;
+                (i, edit::copy_key(container, key), value.clone())
+            }
+            (Value::Array(items), Some(i)) => (i, String::new(), items[i].clone()),
+            _ => return Ok(String::new()),
+        };
+        let description = format!(
+            "Duplicated {}",
+            member_pointer(&path, &key_at(container, index).unwrap_or_default())
+        );
+        self.change(description, path, Op::Insert(index + 1, key, value), index + 1)
+    }
+    pub fn undo(&mut self) -> Result<String, String> {
+        self.check_editable()?;
+        let change = self.edits.undo().ok_or("Nothing to undo")?;
+        change.apply(document_mut(&mut self.json, &mut self.search_worker)?)?;
+        let (location, description) = (change.from.clone(), change.description.clone());
+        self.show_edits(&location);
+        Ok(format!("Undone: {}", description))
+    }
+    pub fn redo(&mut self) -> Result<String, String> {
+        self.check_editable()?;
+        let change = self.edits.redo().ok_or("Nothing to redo")?;
+        change.apply(document_mut(&mut self.json, &mut self.search_worker)?)?;
+        let (location, description) = (change.to.clone(), change.description.clone());
+        self.show_edits(&location);
+        Ok(format!("Redone: {}", description))
+    }
+    // Does `op` on the object or array at `path`, highlighting the member at
+    // `select` afterwards, or the last one if it is past the end
+    fn change(&mut self, description: String, path: Vec<String>, op: Op, select: usize) -> Result<String, String> {
+        let from = self.location();
+        let op = self.apply(&path, op)?;
+        let len = edit::len(self.value_at(&path)?);
+        let to = Location {
+            path: path.clone(),
+            selected: Some(select.min(len.saturating_sub(1))).filter(|_| len > 0),
+        };
+        self.changed(description, path, op, from, to)
+    }
+    fn change_value(&mut self, description: String, path: Vec<String>, value: Value, to: Location) -> Result<String, String> {
+        let from = self.location();
+        let op = self.apply(&path, Op::Set(value))?;
+        self.changed(description, path, op, from, to)
+    }
+    // Records an edit just made, `op` being what undoes it
+    fn changed(&mut self, description: String, path: Vec<String>, op: Op, from: Location, to: Location) -> Result<String, String> {
+        let mut marked = to.path.clone();
+        if let Some(key) = to.selected.and_then(|i| key_at(self.value_at(&path).ok()?, i)) {
+            marked.push(key);
+        } else if marked != path {
+            marked = path.clone();
+        }
+        self.record(edit::Change {
+            description,
+            path,
+            op,
+            from,
+            to,
+            marked: vec![marked],
+        })
+    }
+    // Takes a change just made to the document into the undo list
+    fn record(&mut self, change: edit::Change) -> Result<String, String> {
+        // Elements of stitched pages that moved can't be told apart any more
+        let unstitched = change.path.is_empty() && self.stitched.take().is_some();
+        let (description, to) = (change.description.clone(), change.to.clone());
+        self.edits.push(change);
+        self.show_edits(&to);
+        match unstitched {
+            true => Ok(format!("{}, not saved yet; the pages of the elements are forgotten", description)),
+            false => Ok(format!("{}, not saved yet", description)),
+        }
+    }
+    // Does `op` on the value at `path`, giving back the operation that
+    // undoes it
+    fn apply(&mut self, path: &[String], mut op: Op) -> Result<Op, String> {
+        let pointer = to_pointer(path);
+        let json = document_mut(&mut self.json, &mut self.search_worker)?;
+        let target = json.pointer_mut(&pointer).ok_or_else(|| format!("no value at {}", pointer))?;
+        op.apply(target)?;
+        Ok(op)
+    }
+    fn value_at(&self, path: &[String]) -> Result<&Value, String> {
+        let pointer = to_pointer(path);
+        let json = self.json.as_ref().ok_or("there is no document")?;
+        json.pointer(&pointer).ok_or_else(|| format!("no value at {}", pointer))
+    }
+    // Shows the document after an edit, undo or redo
+    fn show_edits(&mut self, location: &Location) {
+        self.modified = self.edits.modified();
+        self.reindex();
+        self.go_to(location);
+    }
+    // Whether the node at `path` or anything below it was edited
+    pub fn is_modified(&self, path: &[String]) -> bool {
+        self.modified.iter().any(|p| p.starts_with(path))
+    }
+    // Opens the log view on the array being shown, or on the whole document
+    // if that is where the records are. The filters are kept if it is the
+    // same array as last time.
+    pub fn show_logs(&mut self) -> Result<(), String> {
+        let json = self.json.as_ref().ok_or("there is no document")?;
+        let mut path = self.navigation_stack[1..].to_vec();
+        while !json.pointer(&to_pointer(&path)).map_or(false, logs::looks_like_logs) {
+            if path.pop().is_none() {
+                return Err(String::from("No tracing JSON records here; they need a level and fields"));
+            }
+        }
+        let records = json.pointer(&to_pointer(&path)).and_then(Value::as_array).cloned().unwrap_or_default();
+        let mut view = LogView::new(path, &records);
+        if let Some(old) = self.logs.take().filter(|old| old.path == view.path) {
+            let selected = old.selected();
+            view.min_level = old.min_level;
+            view.target = old.target;
+            view.group_by = old.group_by;
+            view.expanded = old.expanded;
+            view.refresh();
+            if let Some(record) = selected {
+                view.select(record);
+            }
+        }
+        self.logs = Some(view);
+        self.set_current_route(Route::Logs);
+        Ok(())
+    }
+    // Changes a filter of the log view, opening it if need be
+    pub fn filter_logs(&mut self, change: impl FnOnce(&mut LogView)) -> Result<(), String> {
+        self.show_logs()?;
+        if let Some(view) = self.logs.as_mut() {
+            change(view);
+            view.refresh();
+        }
+        Ok(())
+    }
+    // Leaves the log view on the highlighted record in the tree
+    pub fn show_log_record(&mut self) {
+        let view = match self.logs.as_ref() {
+            Some(view) => view,
+            None => return,
+        };
+        let index = view.selected().map(|i| view.records[i].index);
+        let location = Location {
+            path: view.path.clone(),
+            selected: index,
+        };
+        self.set_current_route(Route::Main);
+        self.jump(&location);
+    }
+    pub fn selected_value(&self) -> Option<&Value> {
+        self.json.as_ref()?.pointer(&to_pointer(&self.selected_path()))
+    }
+    // Opens the Detail route for the highlighted value if it is a scalar
+    pub fn show_detail(&mut self) -> bool {
+        match self.selected_value() {
+            Some(v) if !(v.is_object() || v.is_array()) => {
+                self.detail_path = self.selected_path();
+                self.detail_scroll = 0;
+                self.set_current_route(Route::Detail);
+                true
+            }
+            _ => false,
+        }
+    }
+    // Path of the highlighted node, or of the current level if none is
+    pub fn selected_path(&self) -> Vec<String> {
+        let mut path: Vec<String> = self.navigation_stack.iter().skip(1).cloned().collect();
+        if let Some(level) = self.elements.as_ref() {
+            if let Some(key) = level.state.selected().and_then(|i| level.key(i)) {
+                path.push(key.into_owned());
+            }
+        }
+        path
+    }
+    pub fn set_elements(&mut self) -> () {
+        let s: String = to_pointer(&self.navigation_stack[1..]);
+        let js = self.json.as_ref().unwrap().pointer(&s).unwrap();
+        self.elements = Some(match self.shown_keys(&self.navigation_stack[1..]) {
+            Some(keys) => Level::only(keys),
+            None => Level::new(js),
+        });
+    }
+    // The rows `range` of the current level, for drawing
+    pub fn visible_elements(&self, range: std::ops::Range<usize>) -> Vec<Element<'_>> {
+        let level = match self.elements.as_ref() {
+            Some(level) => level,
+            None => return Vec::new(),
+        };
+        let pointer = to_pointer(&self.navigation_stack[1..]);
+        let js = match self.json.as_ref().and_then(|j| j.pointer(&pointer)) {
+            Some(js) => js,
+            None => return Vec::new(),
+        };
+        range
+            .filter_map(|i| {
+                let key = level.key(i)?;
+                let value = match js {
+                    Value::Object(map) => map.get(key.as_ref())?,
+                    Value::Array(items) => items.get(key.parse::<usize>().ok()?)?,
+                    _ => return None,
+                };
+                Some(get_element(key.into_owned(), value))
+            })
+            .collect()
     }
     pub fn set_route(&mut self) -> () {
-        let list_state = &self.elements.as_ref().unwrap().state;
-        let selected = list_state.selected().unwrap();
-        let element = self.elements.as_ref().unwrap().items.get(selected);
-
-        // panic!("Selected Element is {:#?}",element);
-
-        match element {
-            Some(e) => match e {
-                Element::Array(k, _v) => match k {
-                    Index::Key(key) => {
-                        self.navigation_stack.push(key.to_owned());
-                        // panic!("Selected Vec is {:#?}",self.navigation_stack);
-                    }
-                },
-                Element::Object(k, _v) => match k {
-                    Index::Key(key) => self.navigation_stack.push(key.to_owned()),
-                },
-                _ => {}
-            },
+        let path = self.selected_path();
+        if path.len() < self.navigation_stack.len() {
+            return;
+        }
+        match self.json.as_ref().unwrap().pointer(&to_pointer(&path)) {
+            Some(v) if v.is_object() || v.is_array() => {
+                self.navigation_stack.push(path.last().unwrap().to_owned());
+            }
             _ => {}
         }
     }
     pub fn pop_route(&mut self) -> () {
         self.navigation_stack.pop();
     }
-    // Prepares data for fuzzy search
+    // Goes back to the level of breadcrumb `depth`, the root being 0
+    pub fn truncate_route(&mut self, depth: usize) {
+        self.navigation_stack.truncate(depth + 1);
+    }
+    // Where we are now, for the navigation history
+    pub fn location(&self) -> Location {
+        Location {
+            path: self.navigation_stack.iter().skip(1).cloned().collect(),
+            selected: self.elements.as_ref().and_then(|e| e.state.selected()),
+        }
+    }
+    // Shows `location` without recording it in the history. If the document
+    // changed underneath it, the deepest level that still exists is shown.
+    pub fn go_to(&mut self, location: &Location) {
+        let json = self.json.as_ref().unwrap();
+        let mut path = location.path.clone();
+        while !path.is_empty()
+            && !json.pointer(&to_pointer(&path)).map_or(false, |v| v.is_object() || v.is_array())
+        {
+            path.pop();
+        }
+        self.navigation_stack = vec![String::new()];
+        self.navigation_stack.extend(path);
+        self.set_elements();
+        if let Some(selected) = location.selected {
+            self.elements.as_mut().unwrap().select(selected);
+        }
+    }
+    // Records a move from `from` to wherever we are now, if we moved at all
+    pub fn record_jump(&mut self, from: Location) {
+        let to = self.location();
+        if to.path != from.path {
+            self.history.record(from, to);
+        }
+    }
+    // Jumps to `location`, recording it in the history
+    pub fn jump(&mut self, location: &Location) {
+        let from = self.location();
+        self.go_to(location);
+        self.history.record(from, self.location());
+    }
+    // The location that shows the node at `path`: its own level for objects
+    // and arrays, its parent level with it selected for anything else
+    pub fn location_of(&self, path: &[String]) -> Option<Location> {
+        let value = self.json.as_ref()?.pointer(&to_pointer(path))?;
+        if value.is_object() || value.is_array() {
+            return Some(Location {
+                path: path.to_vec(),
+                selected: None,
+            });
+        }
+        let (last, parent) = path.split_last()?;
+        Some(Location {
+            path: parent.to_vec(),
+            selected: self.row_of(parent, last),
+        })
+    }
+    // Starts a search; the document is indexed already or being indexed
     pub fn set_fuzzy_elements(&mut self) {
-        // borrows json
-        // sets josn as 'pointer : item'
-        // ex 'x/0/whatever : orange'
-
-        let mut vec: String = String::new();
-        let mut fuzzy_data: Vec<String> = Vec::new();
-
-        // For Object
-        if self.json.as_ref().unwrap().is_object() {
-            get_pointer_object(
-                self.json.as_ref().unwrap().as_object().unwrap(),
-                &mut vec,
-                &mut fuzzy_data,
-            );
-        } else if self.json.as_ref().unwrap().is_array() {
-            // For Array
-            get_pointer_array(
-                self.json.as_ref().unwrap().as_array().unwrap(),
-                &mut vec,
-                &mut fuzzy_data,
-            );
-        }
-        self.fuzzy_elements = Some(StatefulList::with_items(fuzzy_data) );
-
+        self.fuzzy_elements = Some(StatefulList::with_items(Vec::new()));
+        self.search_and_set_fuzzy_data();
     }
+    // Hands the query to the search worker. The current results stay up
+    // until the worker's first results for it arrive in `poll_search`.
     pub fn search_and_set_fuzzy_data(&mut self) -> () {
-
-
-        let matcher = SkimMatcherV2::default();
-
-        let searched_items=  self.fuzzy_elements
-        .as_ref()
-        .unwrap()
-        .items
-        .iter()
-        .filter(|i| matcher.fuzzy_match(i, &self.user_input).is_some() ).map(|s| s.to_string()).collect::<Vec<String>>();
-
-        
-        self.fuzzy_elements = Some(StatefulList::with_items(searched_items));
+        // Structured and JSONPath queries have nothing to highlight
+        let matcher = if self.search_options.mode == SearchMode::JsonPath {
+            if self.user_input.trim().is_empty() {
+                Ok(None)
+            } else {
+                jsonpath::parse(&self.user_input).map(|_| None)
+            }
+        } else {
+            match Filter::parse(&self.user_input, self.search_options) {
+                Ok(Some(_)) => Ok(None),
+                Ok(None) => Matcher::new(&self.user_input, self.search_options).map(Some),
+                Err(e) => Err(e),
+            }
+        };
+        let matcher = match matcher {
+            Ok(matcher) => matcher,
+            Err(e) => {
+                // Keep the last good results while the query is being typed
+                self.search_error = Some(e);
+                return;
+            }
+        };
+        self.search_error = None;
+        self.search_matcher = matcher;
+        let subtree = if self.search_options.subtree { &self.navigation_stack[1..] } else { &[] };
+        if let Some(worker) = self.search_worker.as_ref() {
+            self.search_generation = worker.query(&self.user_input, self.search_options, subtree);
+            self.search_busy = true;
+        }
+    }
+    // Takes whatever the search worker has sent since the last call
+    pub fn poll_search(&mut self) {
+        let worker = match self.search_worker.as_ref() {
+            Some(worker) => worker,
+            None => return,
+        };
+        while let Some(update) = worker.try_recv() {
+            match update {
+                Update::Ready(entries) => self.search_entries = entries,
+                Update::Results {
+                    generation,
+                    added,
+                    locations,
+                    matched,
+                    total,
+                    done,
+                } if generation == self.search_generation => {
+                    let list = self.fuzzy_elements.get_or_insert_with(|| StatefulList::with_items(Vec::new()));
+                    // A new query starts at the top; more of the same keeps the selection
+                    if generation != self.search_shown {
+                        *list = StatefulList::with_items(Vec::new());
+                        self.search_scores.clear();
+                        self.search_locations.clear();
+                        self.search_shown = generation;
+                    }
+                    self.search_locations.extend(added.iter().map(|&(_, index)| index).zip(locations));
+                    search::merge(&mut list.items, &mut self.search_scores, added);
+                    if list.state.selected().map_or(false, |i| i >= list.items.len()) {
+                        list.state.select(None);
+                    }
+                    self.search_matched = matched;
+                    self.search_total = total;
+                    self.search_busy = !done;
+                }
+                Update::Results { .. } => {}
+            }
+        }
     }
     pub fn search_after_pop(&mut self)  {
-        self.set_fuzzy_elements();
-        let matcher = SkimMatcherV2::default();
-
-        let searched_items=  self.fuzzy_elements
-        .as_ref()
-        .unwrap()
-        .items
-        .iter()
-        .filter(|i| matcher.fuzzy_match(i, &self.user_input).is_some() ).map(|s| s.to_string()).collect::<Vec<String>>();
-
-        self.fuzzy_elements = Some(StatefulList::with_items(searched_items));
-
+        self.search_and_set_fuzzy_data();
+    }
+    // The text of the prompt on the current route
+    pub fn prompt_input(&self) -> &str {
+        match self.current_route {
+            Route::Command => &self.command_input,
+            Route::Jq => &self.jq_input,
+            _ => &self.user_input,
+        }
+    }
+    // Replaces the text of the prompt and runs what follows it as you type
+    pub fn set_prompt_input(&mut self, text: String) {
+        match self.current_route {
+            Route::Command => self.command_input = text,
+            Route::Jq => {
+                self.jq_input = text;
+                self.run_jq();
+            }
+            _ => {
+                self.user_input = text;
+                self.active_block = ActiveBlock::Search;
+                self.search_and_set_fuzzy_data();
+            }
+        }
+    }
+    // Adds what is in the prompt to its history
+    pub fn remember_input(&mut self) {
+        let line = self.prompt_input().to_owned();
+        if let Err(e) = self.input_history.add(self.current_route, &line) {
+            self.status = Some(format!("could not save history: {}", e));
+        }
+    }
+    pub fn recall_older(&mut self) {
+        let current = self.prompt_input().to_owned();
+        if let Some(line) = self.input_history.older(self.current_route, &current) {
+            self.set_prompt_input(line);
+        }
+    }
+    // Returns false when the history is not being stepped through
+    pub fn recall_newer(&mut self) -> bool {
+        match self.input_history.newer(self.current_route) {
+            Some(line) => {
+                self.set_prompt_input(line);
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn show_queries(&mut self) {
+        let first = if self.saved_queries.queries.is_empty() { None } else { Some(0) };
+        self.queries_list.select(first);
+        self.set_current_route(Route::Queries);
+    }
+    pub fn start_reverse_search(&mut self) {
+        self.input_history.stop_browsing();
+        self.reverse_search = Some(ReverseSearch {
+            query: String::new(),
+            found: None,
+            original: self.prompt_input().to_owned(),
+        });
+    }
+    // Looks for the query again from the newest line, after it was edited
+    pub fn update_reverse_search(&mut self) {
+        if let Some(search) = self.reverse_search.as_mut() {
+            search.found = self.input_history.find(self.current_route, &search.query, usize::MAX);
+        }
+    }
+    // Ctrl-r again: the next older line with the query
+    pub fn reverse_search_older(&mut self) {
+        if let Some(search) = self.reverse_search.as_mut() {
+            let before = search.found.unwrap_or(usize::MAX);
+            if let Some(found) = self.input_history.find(self.current_route, &search.query, before) {
+                search.found = Some(found);
+            }
+        }
+    }
+    // Puts the line that was found into the prompt, or what was there before
+    pub fn finish_reverse_search(&mut self, accept: bool) {
+        let search = match self.reverse_search.take() {
+            Some(search) => search,
+            None => return,
+        };
+        let line = match (accept, search.found) {
+            (true, Some(found)) => self.input_history.lines(self.current_route)[found].to_owned(),
+            _ => search.original,
+        };
+        self.set_prompt_input(line);
     }
 }
-fn get_element(f: &String, j: &Value) -> Element {
+fn get_element(f: String, j: &Value) -> Element<'_> {
     if j.is_array() {
-        Element::Array(Index::Key(String::from(f.to_string())), j.to_owned())
+        Element::Array(Index::Key(f), j)
     } else if j.is_object() {
-        Element::Object(Index::Key(String::from(f.to_string())), j.to_owned())
+        Element::Object(Index::Key(f), j)
     } else if j.is_boolean() {
-        Element::Bool(Index::Key(String::from(f.to_string())), j.to_owned())
+        Element::Bool(Index::Key(f), j)
     } else if j.is_string() {
-        Element::String(Index::Key(String::from(f.to_string())), j.to_owned())
+        Element::String(Index::Key(f), j)
     } else if j.is_number() {
-        Element::Number(Index::Key(String::from(f.to_string())), j.to_owned())
+        Element::Number(Index::Key(f), j)
     } else {
-        Element::Null(Index::Key(String::from(f.to_string())))
+        Element::Null(Index::Key(f))
     }
 }
 
-fn get_pointer_object(val: &Map<String, Value>, vec: &mut String, fuzzy_data: &mut Vec<String>) {
-    let mut original_vec: String = String::new();
-    // println!("Outer Map is {:#?}", val);
-    // println!("Outer vec is {:#?}", vec);
-    // println!("Fuzzy vec is {:#?}", fuzzy_data);
-    val.iter().for_each(|item| {
-        // println!("Inner Map is {:#?}", item);
-        // println!("Inner vec is {:#?}", vec);
-        // println!("Fuzzy vec is {:#?}", fuzzy_data);
-        match item.1 {
-            Value::Object(map) => {
-                original_vec = vec.to_string();
-                vec.push('/');
-                vec.push_str(item.0);
-                get_pointer_object(map, vec, fuzzy_data);
-                vec.clear();
-                vec.push_str(&original_vec)
-            }
-            Value::Array(i) => {
-                original_vec = vec.to_string();
-                vec.push('/');
-                vec.push_str(item.0);
-                get_pointer_array(i, vec, fuzzy_data);
-                vec.clear();
-                vec.push_str(&original_vec)
-            }
-            Value::Null => {
-                vec.push_str("/");
-                vec.push_str(item.0);
-                vec.push_str(" : NULL");
-                fuzzy_data.push(vec.to_owned());
-                vec.clear();
-                vec.push_str(&original_vec)
-            }
-            Value::Bool(i) => {
-                original_vec = vec.to_string();
-                vec.push_str("/");
-                vec.push_str(item.0);
-                vec.push_str(" : ");
-                vec.push_str(&i.to_string());
-                fuzzy_data.push(vec.to_owned());
-                vec.clear();
-                vec.push_str(&original_vec)
-            }
-            Value::Number(i) => {
-                original_vec = vec.to_string();
-                vec.push_str("/");
-                vec.push_str(item.0);
-                vec.push_str(" : ");
-                vec.push_str(&i.to_string());
-                fuzzy_data.push(vec.to_owned());
-                vec.clear();
-                vec.push_str(&original_vec)
-            }
-            Value::String(i) => {
-                original_vec = vec.to_string();
-                vec.push_str("/");
-                vec.push_str(item.0);
-                vec.push_str(" : ");
-                vec.push_str(&i.to_string());
-                fuzzy_data.push(vec.to_owned());
-                vec.clear();
-                vec.push_str(&original_vec)
-            }
-        }
-    });
+// Builds a JSON pointer from path tokens, escaping `~` and `/`
+pub fn to_pointer(path: &[String]) -> String {
+    path.iter()
+        .map(|t| format!("/{}", t.replace('~', "~0").replace('/', "~1")))
+        .collect()
 }
 
-fn get_pointer_array(val: &Vec<Value>, vec: &mut String, fuzzy_data: &mut Vec<String>) {
-    let mut original_vec: String = String::new();
-    // println!("Outer Array is {:#?}", val);
-    // println!("Outer Array vec is {:#?}", vec);
-    // println!("Fuzzy Array vec is {:#?}", fuzzy_data);
-
-    val.iter().enumerate().for_each(|(k, v)| {
-        // println!("Inner Array is {:#?}", (k, v));
-        // println!("Inner Array Vec is {:#?}", vec);
-        // println!("Fuzzy Array vec is {:#?}", fuzzy_data);
-        match v {
-            Value::Array(item) => {
-                original_vec = vec.to_string();
-                vec.push('/');
-                vec.push_str(&k.to_string());
-                get_pointer_array(item, vec, fuzzy_data);
-                vec.clear();
-                vec.push_str(&original_vec)
-            }
-            Value::Object(map) => {
-                original_vec = vec.to_string();
-                vec.push('/');
-                vec.push_str(&k.to_string());
-                get_pointer_object(map, vec, fuzzy_data);
-                vec.clear();
-                vec.push_str(&original_vec)
-            }
-            Value::Null => {
-                original_vec = vec.to_string();
-                vec.push_str("/");
-                vec.push_str(&k.to_string());
-                vec.push_str(" : NULL");
-                fuzzy_data.push(vec.to_owned());
-                vec.clear();
-                vec.push_str(&original_vec)
-            }
-            Value::Bool(i) => {
-                original_vec = vec.to_string();
-                vec.push_str("/");
-                vec.push_str(&k.to_string());
-                vec.push_str(" : ");
-                vec.push_str(&i.to_string());
-                fuzzy_data.push(vec.to_owned());
-                vec.clear();
-                vec.push_str(&original_vec)
-            }
-            Value::Number(i) => {
-                original_vec = vec.to_string();
-                vec.push_str("/");
-                vec.push_str(&k.to_string());
-                vec.push_str(" : ");
-                vec.push_str(&i.to_string());
-                fuzzy_data.push(vec.to_owned());
-                vec.clear();
-                vec.push_str(&original_vec)
-            }
-            Value::String(i) => {
-                original_vec = vec.to_string();
-                vec.push_str("/");
-                vec.push_str(&k.to_string());
-                vec.push_str(" : ");
-                vec.push_str(&i.to_string());
-                fuzzy_data.push(vec.to_owned());
-                vec.clear();
-                vec.push_str(&original_vec)
-            }
-        }
-    });
+// Splits a JSON pointer into path tokens, the inverse of `to_pointer`
+pub fn from_pointer(pointer: &str) -> Vec<String> {
+    if pointer.is_empty() {
+        return Vec::new();
+    }
+    pointer
+        .strip_prefix('/')
+        .unwrap_or(pointer)
+        .split('/')
+        .map(|t| t.replace("~1", "/").replace("~0", "~"))
+        .collect()
+}
+
+// The key of the member at `index` of an object or array
+fn key_at(container: &Value, index: usize) -> Option<String> {
+    match container {
+        Value::Object(map) => map.keys().nth(index).cloned(),
+        Value::Array(items) => Some(index.to_string()).filter(|_| index < items.len()),
+        _ => None,
+    }
+}
+
+// The document, to be changed in place. The search worker shares it;
+// stopped, it lets go of it, so it is copied only if the worker was still
+// indexing. The edit starts a new worker anyway.
+fn document_mut<'j>(json: &'j mut Option<Arc<Value>>, worker: &mut Option<SearchWorker>) -> Result<&'j mut Value, String> {
+    if let Some(worker) = worker.take() {
+        worker.stop();
+    }
+    Ok(Arc::make_mut(json.as_mut().ok_or("there is no document")?))
+}
+
+// The pointer to the member `key` of the container at `path`
+fn member_pointer(path: &[String], key: &str) -> String {
+    let mut member = path.to_vec();
+    member.push(key.to_owned());
+    to_pointer(&member)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
 }
@@ -0,0 +1,232 @@
+use crate::app::{from_pointer, ActiveBlock, App, Confirmation, Derivation, Route};
+use crate::keymap::Action;
+use crate::logs::Level;
+use crate::patch::Patch;
+use crate::queries::QueryKind;
+use crate::sql;
+use crate::stitch;
+use crate::write::{self, Layout};
+use std::fs;
+
+// Runs one line typed into the `:` command line
+pub fn run_command(line: &str, app: &mut App) -> Result<(), String> {
+    let line = line.trim();
+    let (name, arg) = match line.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (line, ""),
+    };
+    match name {
+        "" => Ok(()),
+        "goto" | "g" => goto(arg, app),
+        "export" => export(arg, app),
+        "w" | "write" => write(arg, false, app),
+        "w!" | "write!" => write(arg, true, app),
+        "sql" => run_sql(arg, app),
+        "stitch" => stitch_pages(arg, app),
+        "patch" => preview_patch(arg, app),
+        "query" if arg.is_empty() => {
+            app.show_queries();
+            Ok(())
+        }
+        "query" => run_saved_query(arg, app),
+        "level" => {
+            let level = match arg {
+                "" => Level::Trace,
+                name => Level::parse(name).ok_or_else(|| format!("`{}` is not a level; try info or warn", name))?,
+            };
+            app.filter_logs(|view| view.min_level = level)
+        }
+        "target" => app.filter_logs(|view| view.target = Some(arg.to_owned()).filter(|t| !t.is_empty())),
+        "group" => app.filter_logs(|view| view.group_by = Some(arg.to_owned()).filter(|f| !f.is_empty())),
+        _ => Err(format!("unknown command `{}`", name)),
+    }
+}
+
+// `goto /items/3/id` or `goto .items[3].id`
+fn goto(arg: &str, app: &mut App) -> Result<(), String> {
+    let path = parse_path(arg)?;
+    let location = app
+        .location_of(&path)
+        .ok_or_else(|| format!("no value at `{}`", arg))?;
+    app.jump(&location);
+    Ok(())
+}
+
+// `export out.json` writes the document being shown, e.g. a jq result
+fn export(arg: &str, app: &mut App) -> Result<(), String> {
+    if arg.is_empty() {
+        return Err(String::from("export needs a file name"));
+    }
+    let json = app.json.as_ref().ok_or("there is no document")?;
+    let mut text = serde_json::to_string_pretty(json.as_ref()).map_err(|e| e.to_string())?;
+    text.push('\n');
+    fs::write(arg, &text).map_err(|e| format!("{}: {}", arg, e))?;
+    app.status = Some(format!("Exported {} bytes to {}", text.len(), arg));
+    Ok(())
+}
+
+// `w` writes the document back to its file, `w out.json` to another one,
+// laid out like the input unless `--pretty=N` or `--minify` says otherwise.
+// `w!` writes over a file that changed on disk or already exists.
+fn write(arg: &str, force: bool, app: &mut App) -> Result<(), String> {
+    match app.derived {
+        Some(Derivation::Patch(_)) => {
+            return Err(format!("{} applies the patch first, then w writes it", app.keys(Action::AcceptPatch)))
+        }
+        Some(_) => {
+            return Err(String::from("This is a query result; export writes it, or go back to the document to write it"))
+        }
+        None if app.compare.is_some() => {
+            return Err(String::from("A comparison isn't a document of its own; export writes what is shown"))
+        }
+        None => {}
+    }
+    let (options, path) = write::parse_args(arg);
+    let path = match path.or_else(|| app.source.clone()) {
+        Some(path) => path,
+        None => {
+            // Nothing to write back to: ask for a path on the command line
+            app.command_input = format!("{} ", if force { "w!" } else { "w" });
+            app.set_current_route(Route::Command);
+            return Err(String::from("The document didn't come from a file; type a path to write it to"));
+        }
+    };
+    let mut layout = Layout::of(&app.data);
+    for option in options {
+        layout = Layout::parse(option, &app.data)?;
+    }
+    let to_source = app.source.as_ref() == Some(&path);
+    if !force && to_source && app.source_changed() {
+        return Err(format!(
+            "{} changed on disk since it was read; w! writes over it, {} reloads it",
+            path.display(),
+            app.keys(Action::Reload)
+        ));
+    }
+    if !force && !to_source && path.exists() {
+        return Err(format!("{} exists; w! writes over it", path.display()));
+    }
+    let json = app.json.as_ref().ok_or("there is no document")?;
+    let text = write::render(json, &layout)?;
+    write::write_atomic(&path, &text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    app.status = Some(format!("Wrote {} bytes to {}", text.len(), path.display()));
+    // Writing somewhere else keeps the file we came from, as in vim; the
+    // first write of stdin gives the document its file
+    if to_source || app.source.is_none() {
+        app.written(path, text);
+    }
+    Ok(())
+}
+
+// `sql SELECT ... FROM .items ...` shows the result in place of the document;
+// `sql` alone goes back to the original
+fn run_sql(arg: &str, app: &mut App) -> Result<(), String> {
+    if arg.is_empty() {
+        app.show_original();
+        return Ok(());
+    }
+    let result = sql::run(arg, &app.original_json())?;
+    let rows = result.as_array().map_or(0, Vec::len);
+    app.show_derived(result, Derivation::Sql(arg.to_owned()))?;
+    app.status = Some(format!("{} rows", rows));
+    Ok(())
+}
+
+// `stitch items page*.json` joins the `items` arrays of the pages into one
+// document; without files it takes the pages named like the open file
+fn stitch_pages(arg: &str, app: &mut App) -> Result<(), String> {
+    let mut args = arg.split_whitespace();
+    let items = args.next().ok_or("stitch needs the path of the array in each page, e.g. stitch items")?;
+    let mut files = Vec::new();
+    for pattern in args {
+        files.extend(stitch::glob(pattern)?);
+    }
+    if files.is_empty() {
+        let source = app.source.as_ref().ok_or("list the pages to stitch, e.g. stitch items page*.json")?;
+        files = stitch::siblings(source)?;
+    }
+    let stitched = stitch::stitch(&files, items)?;
+    if !app.modified.is_empty() {
+        app.confirm(Confirmation::Stitch(Box::new(stitched)));
+        return Ok(());
+    }
+    let (value, stitch) = stitched;
+    app.status = Some(app.show_stitched(value, stitch)?);
+    Ok(())
+}
+
+// `patch fix.json` previews a JSON Patch or merge patch on the document
+fn preview_patch(arg: &str, app: &mut App) -> Result<(), String> {
+    if arg.is_empty() {
+        return Err(String::from("patch needs a file, e.g. patch fix.json"));
+    }
+    let data = fs::read_to_string(arg).map_err(|e| format!("{}: {}", arg, e))?;
+    let value = serde_json::from_str(&data).map_err(|e| format!("{}: {}", arg, e))?;
+    let patch = Patch::parse(value).map_err(|e| format!("{}: {}", arg, e))?;
+    let message = app.preview_patch(arg.to_owned(), patch)?;
+    app.status = Some(message);
+    Ok(())
+}
+
+/// Runs a query saved in the project file, the way its own prompt would.
+pub fn run_saved_query(name: &str, app: &mut App) -> Result<(), String> {
+    let kind = match app.saved_queries.get(name) {
+        Some(query) => query.kind.clone(),
+        None => return Err(format!("no saved query `{}`", name)),
+    };
+    match kind {
+        QueryKind::Search(text, mode) => {
+            app.search_options.mode = mode;
+            app.user_input = text;
+            app.active_block = ActiveBlock::Search;
+            app.set_current_route(Route::Search);
+            app.set_fuzzy_elements();
+            Ok(())
+        }
+        QueryKind::Jq(filter) => {
+            app.jq_input = filter;
+            app.run_jq();
+            app.apply_jq().map(|_| ())
+        }
+        QueryKind::Sql(query) => run_sql(&query, app),
+        QueryKind::Goto(path) => goto(&path, app),
+    }
+}
+
+/// Parses a path to a node. Accepts a JSON pointer (`/items/3/id`) or the
+/// dotted form (`.items[3].id`, `items.3.id`).
+pub fn parse_path(path: &str) -> Result<Vec<String>, String> {
+    let path = path.trim();
+    if path.is_empty() || path == "." || path == "/" {
+        return Ok(Vec::new());
+    }
+    if path.starts_with('/') {
+        return Ok(from_pointer(path));
+    }
+
+    let mut tokens = Vec::new();
+    let mut rest = path.strip_prefix('.').unwrap_or(path);
+    while !rest.is_empty() {
+        if let Some(inner) = rest.strip_prefix('[') {
+            let end = inner
+                .find(']')
+                .ok_or_else(|| format!("missing `]` in `{}`", path))?;
+            let token = inner[..end].trim();
+            let token = token
+                .strip_prefix('"')
+                .and_then(|t| t.strip_suffix('"'))
+                .unwrap_or(token);
+            tokens.push(token.to_owned());
+            rest = &inner[end + 1..];
+        } else {
+            let end = rest.find(|c| c == '.' || c == '[').unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("empty key in `{}`", path));
+            }
+            tokens.push(rest[..end].to_owned());
+            rest = &rest[end..];
+        }
+        rest = rest.strip_prefix('.').unwrap_or(rest);
+    }
+    Ok(tokens)
+}
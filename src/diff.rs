@@ -0,0 +1,442 @@
+// Comparing two documents node by node. Objects are compared by key, so
+// keys in another order are no difference; arrays are lined up on the items
+// they have in common.
+
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+
+// Arrays with more pairs of differing items than this are compared item by
+// item instead of lined up
+const MAX_ALIGN: usize = 1_000_000;
+
+/// How a node of the newer document differs from the older one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mark {
+    Added,
+    Removed,
+    // Replaced by another value, which was this
+    Changed(Value),
+}
+
+/// Two documents laid over each other: the newer one, with what was removed
+/// from the older one put back where it was so it can be shown.
+#[derive(Debug, Default)]
+pub struct Diff {
+    pub value: Value,
+    // By path in `value`; nodes below an added or removed one have no mark
+    // of their own
+    pub marks: HashMap<Vec<String>, Mark>,
+    // The paths in the newer document of the added and changed nodes, and
+    // of the objects and arrays something was removed from
+    pub touched: Vec<Vec<String>>,
+    // The marked nodes and everything holding them
+    differing: HashSet<Vec<String>>,
+    // The marked nodes in document order, with the position of each step of
+    // their path among its siblings
+    order: Vec<(Vec<usize>, Vec<String>)>,
+}
+
+impl Diff {
+    /// The mark of the node at `path`, or of the added or removed node it is
+    /// part of.
+    pub fn mark(&self, path: &[String]) -> Option<&Mark> {
+        if let Some(mark) = self.marks.get(path) {
+            return Some(mark);
+        }
+        (0..path.len())
+            .rev()
+            .filter_map(|depth| self.marks.get(&path[..depth]))
+            .find(|mark| !matches!(mark, Mark::Changed(_)))
+    }
+
+    /// Whether the node at `path` or anything below it is marked.
+    pub fn differs(&self, path: &[String]) -> bool {
+        self.differing.contains(path)
+    }
+
+    /// How many nodes were added, removed and changed.
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let count = |f: fn(&Mark) -> bool| self.marks.values().filter(|m| f(m)).count();
+        (
+            count(|m| *m == Mark::Added),
+            count(|m| *m == Mark::Removed),
+            count(|m| matches!(m, Mark::Changed(_))),
+        )
+    }
+
+    /// The marked node after (or before) the one at `path` in document
+    /// order, wrapping around, with its number counting from 1.
+    pub fn next(&self, path: &[String], forward: bool) -> Option<(usize, &[String])> {
+        let at = positions(&self.value, path);
+        let index = match forward {
+            true => self.order.iter().position(|(p, _)| *p > at).unwrap_or(0),
+            false => self.order.iter().rposition(|(p, _)| *p < at).unwrap_or(self.order.len().checked_sub(1)?),
+        };
+        self.order.get(index).map(|(_, path)| (index + 1, path.as_slice()))
+    }
+
+    fn index(&mut self) {
+        for path in self.marks.keys() {
+            for depth in 0..=path.len() {
+                self.differing.insert(path[..depth].to_vec());
+            }
+        }
+        let mut order = Vec::new();
+        self.collect(&self.value, &mut Vec::new(), &mut Vec::new(), &mut order);
+        self.order = order;
+    }
+
+    fn collect(&self, value: &Value, at: &mut Vec<usize>, path: &mut Vec<String>, order: &mut Vec<(Vec<usize>, Vec<String>)>) {
+        if self.marks.contains_key(path.as_slice()) {
+            order.push((at.clone(), path.clone()));
+            return;
+        }
+        if !self.differs(path) {
+            return;
+        }
+        let members: Box<dyn Iterator<Item = (String, &Value)>> = match value {
+            Value::Object(map) => Box::new(map.iter().map(|(k, v)| (k.clone(), v))),
+            Value::Array(items) => Box::new(items.iter().enumerate().map(|(i, v)| (i.to_string(), v))),
+            _ => return,
+        };
+        for (i, (key, member)) in members.enumerate() {
+            at.push(i);
+            path.push(key);
+            self.collect(member, at, path, order);
+            at.pop();
+            path.pop();
+        }
+    }
+}
+
+// The position of each step of `path` among its siblings
+fn positions(value: &Value, path: &[String]) -> Vec<usize> {
+    let mut at = Vec::new();
+    let mut value = value;
+    for key in path {
+        let (position, member) = match value {
+            Value::Object(map) => match map.iter().position(|(k, _)| k == key) {
+                Some(i) => (i, &map[key]),
+                None => break,
+            },
+            Value::Array(items) => match key.parse::<usize>().ok().filter(|i| *i < items.len()) {
+                Some(i) => (i, &items[i]),
+                None => break,
+            },
+            _ => break,
+        };
+        at.push(position);
+        value = member;
+    }
+    at
+}
+
+/// Two files compared with `json_tui diff`.
+pub struct Comparison {
+    pub old: String,
+    pub new: String,
+    pub diff: Diff,
+    // Whether objects and arrays holding changes show only the members that
+    // differ
+    pub hide_unchanged: bool,
+}
+
+impl Comparison {
+    /// What differs, with the keys that go to the next difference and hide
+    /// the rest.
+    pub fn summary(&self, next: &str, hide: &str) -> String {
+        match self.diff.counts() {
+            (0, 0, 0) => format!("{} and {} are the same", self.old, self.new),
+            (added, removed, changed) => format!(
+                "{} added, {} removed, {} changed; {} goes to the next difference, {} hides the rest",
+                added, removed, changed, next, hide
+            ),
+        }
+    }
+}
+
+/// Compares `before` with `after`.
+pub fn diff(before: &Value, after: &Value) -> Diff {
+    let mut walker = Walker {
+        diff: Diff::default(),
+        shown: Vec::new(),
+        real: Vec::new(),
+    };
+    walker.diff.value = walker.walk(before, after);
+    walker.diff.index();
+    walker.diff
+}
+
+struct Walker {
+    diff: Diff,
+    // The path of the pair of nodes being compared in the shown value, and
+    // in `after`
+    shown: Vec<String>,
+    real: Vec<String>,
+}
+
+impl Walker {
+    // The shown value for a pair of nodes
+    fn walk(&mut self, before: &Value, after: &Value) -> Value {
+        match (before, after) {
+            (Value::Object(old), Value::Object(new)) => Value::Object(self.objects(old, new)),
+            (Value::Array(old), Value::Array(new)) => Value::Array(self.arrays(old, new)),
+            _ if same(before, after) => after.clone(),
+            _ => {
+                self.mark(Mark::Changed(before.clone()));
+                self.diff.touched.push(self.real.clone());
+                after.clone()
+            }
+        }
+    }
+
+    fn objects(&mut self, old: &Map<String, Value>, new: &Map<String, Value>) -> Map<String, Value> {
+        let mut members: Vec<(String, Value)> = Vec::new();
+        for (key, value) in new {
+            self.shown.push(key.clone());
+            self.real.push(key.clone());
+            let value = match old.get(key) {
+                Some(before) => self.walk(before, value),
+                None => self.added(value),
+            };
+            self.shown.pop();
+            self.real.pop();
+            members.push((key.clone(), value));
+        }
+        // Removed keys go back after the key they followed
+        let mut at = 0;
+        for (key, value) in old {
+            if new.contains_key(key) {
+                at = members.iter().position(|(k, _)| k == key).map_or(at, |i| i + 1);
+                continue;
+            }
+            self.shown.push(key.clone());
+            self.mark(Mark::Removed);
+            self.shown.pop();
+            members.insert(at, (key.clone(), value.clone()));
+            at += 1;
+        }
+        if members.len() > new.len() {
+            self.diff.touched.push(self.real.clone());
+        }
+        members.into_iter().collect()
+    }
+
+    fn arrays(&mut self, old: &[Value], new: &[Value]) -> Vec<Value> {
+        let mut items = Vec::new();
+        let mut removed = Vec::new();
+        let mut added = Vec::new();
+        for step in align(old, new) {
+            match step {
+                Step::Removed(i) => removed.push(&old[i]),
+                Step::Added(j) => added.push(j),
+                Step::Same(i, j) => {
+                    self.run(&mut removed, &mut added, new, &mut items);
+                    self.item(items.len(), j, |walker| walker.walk(&old[i], &new[j]), &mut items);
+                }
+            }
+        }
+        self.run(&mut removed, &mut added, new, &mut items);
+        items
+    }
+
+    // Shows a run of removed and added items between two in common: as many
+    // as there are on both sides as changed, then the rest
+    fn run(&mut self, removed: &mut Vec<&Value>, added: &mut Vec<usize>, new: &[Value], items: &mut Vec<Value>) {
+        let paired = removed.len().min(added.len());
+        for (before, j) in removed.iter().zip(added.iter()) {
+            self.item(items.len(), *j, |walker| walker.walk(before, &new[*j]), items);
+        }
+        if removed.len() > paired {
+            self.diff.touched.push(self.real.clone());
+        }
+        for before in removed.drain(..).skip(paired) {
+            self.shown.push(items.len().to_string());
+            self.mark(Mark::Removed);
+            self.shown.pop();
+            items.push(before.clone());
+        }
+        for j in added.drain(..).skip(paired) {
+            self.item(items.len(), j, |walker| walker.added(&new[j]), items);
+        }
+    }
+
+    // Adds the shown item at `index`, from item `j` of `after`
+    fn item(&mut self, index: usize, j: usize, value: impl FnOnce(&mut Walker) -> Value, items: &mut Vec<Value>) {
+        self.shown.push(index.to_string());
+        self.real.push(j.to_string());
+        let value = value(self);
+        self.shown.pop();
+        self.real.pop();
+        items.push(value);
+    }
+
+    fn added(&mut self, value: &Value) -> Value {
+        self.mark(Mark::Added);
+        self.diff.touched.push(self.real.clone());
+        value.clone()
+    }
+
+    fn mark(&mut self, mark: Mark) {
+        self.diff.marks.insert(self.shown.clone(), mark);
+    }
+}
+
+enum Step {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+// Lines up two arrays on their longest run of items in common, after the
+// items they start and end with alike
+fn align(old: &[Value], new: &[Value]) -> Vec<Step> {
+    let start = old.iter().zip(new).take_while(|(a, b)| same(a, b)).count();
+    let end = old[start..]
+        .iter()
+        .rev()
+        .zip(new[start..].iter().rev())
+        .take_while(|(a, b)| same(a, b))
+        .count();
+    let (a, b) = (&old[start..old.len() - end], &new[start..new.len() - end]);
+
+    let mut steps: Vec<Step> = (0..start).map(|i| Step::Same(i, i)).collect();
+    if a.len().saturating_mul(b.len()) > MAX_ALIGN {
+        steps.extend((0..a.len()).map(|i| Step::Removed(start + i)));
+        steps.extend((0..b.len()).map(|j| Step::Added(start + j)));
+    } else {
+        // lengths[i][j]: the longest common run of a[i..] and b[j..]
+        let mut lengths = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lengths[i][j] = match same(&a[i], &b[j]) {
+                    true => lengths[i + 1][j + 1] + 1,
+                    false => lengths[i + 1][j].max(lengths[i][j + 1]),
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && same(&a[i], &b[j]) {
+                steps.push(Step::Same(start + i, start + j));
+                i += 1;
+                j += 1;
+            } else if j == b.len() || (i < a.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+                steps.push(Step::Removed(start + i));
+                i += 1;
+            } else {
+                steps.push(Step::Added(start + j));
+                j += 1;
+            }
+        }
+    }
+    let (old_end, new_end) = (old.len() - end, new.len() - end);
+    steps.extend((0..end).map(|k| Step::Same(old_end + k, new_end + k)));
+    steps
+}
+
+// Equal as JSON: keys in any order, and numbers by value, so `1.50` is `1.5`
+pub fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x == y || same_number(&x.to_string(), &y.to_string()),
+        (Value::Array(x), Value::Array(y)) => x.len() == y.len() && x.iter().zip(y).all(|(x, y)| same(x, y)),
+        (Value::Object(x), Value::Object(y)) => {
+            x.len() == y.len() && x.iter().all(|(k, v)| y.get(k).map_or(false, |w| same(v, w)))
+        }
+        _ => a == b,
+    }
+}
+
+// Integers, which have one spelling but for `-0`, are compared as written so
+// big ones stay apart; only a fraction or exponent on either side makes it a
+// comparison of floats
+fn same_number(x: &str, y: &str) -> bool {
+    let float = |n: &str| n.contains(['.', 'e', 'E']);
+    let zero = |n: &str| n.trim_start_matches('-') == "0";
+    match float(x) || float(y) {
+        true => matches!((x.parse::<f64>(), y.parse::<f64>()), (Ok(x), Ok(y)) if x == y),
+        false => x == y || (zero(x) && zero(y)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn number(text: &str) -> Value {
+        serde_json::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn numbers_are_compared_by_value() {
+        assert!(same(&number("1.50"), &number("1.5")));
+        assert!(same(&number("1e2"), &number("100")));
+        assert!(same(&number("-0"), &number("0")));
+        assert!(!same(&number("1"), &number("2")));
+    }
+
+    #[test]
+    fn big_integers_stay_apart() {
+        assert!(!same(&number("9007199254740993"), &number("9007199254740992")));
+        assert!(same(&number("123456789012345678901234567890"), &number("123456789012345678901234567890")));
+        assert!(!same(&json!([number("18446744073709551617")]), &json!([number("18446744073709551616")])));
+    }
+
+    // The steps lining up two arrays of numbers, as `=i,j`, `-i` and `+j`
+    fn steps(old: &[i32], new: &[i32]) -> String {
+        let values = |items: &[i32]| items.iter().map(|i| json!(i)).collect::<Vec<_>>();
+        align(&values(old), &values(new))
+            .iter()
+            .map(|step| match step {
+                Step::Same(i, j) => format!("={},{}", i, j),
+                Step::Removed(i) => format!("-{}", i),
+                Step::Added(j) => format!("+{}", j),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn arrays_line_up_on_what_they_share() {
+        assert_eq!(steps(&[1, 2], &[1, 2]), "=0,0 =1,1");
+        assert_eq!(steps(&[], &[1]), "+0");
+        assert_eq!(steps(&[1], &[0, 1]), "+0 =0,1");
+        assert_eq!(steps(&[1, 2], &[1]), "=0,0 -1");
+        // Between the common start and end, the longest common run
+        assert_eq!(steps(&[0, 1, 2, 3, 9], &[0, 2, 3, 4, 9]), "=0,0 -1 =2,1 =3,2 +3 =4,4");
+        assert_eq!(steps(&[1, 2, 3, 4], &[1, 5, 4]), "=0,0 -1 -2 +1 =3,2");
+    }
+
+    #[test]
+    fn big_arrays_are_not_lined_up() {
+        let old: Vec<i32> = (0..1001).collect();
+        let new: Vec<i32> = (0..1001).map(|i| i + 5000).collect();
+        let steps = steps(&old, &new);
+        assert!(steps.starts_with("-0 -1 "));
+        assert!(steps.contains("-1000 +0 +1 "));
+        assert!(!steps.contains('='));
+    }
+
+    #[test]
+    fn removed_keys_go_back_where_they_were() {
+        let diff = diff(&json!({"a": 1, "b": 2, "c": 3}), &json!({"a": 1, "c": 4, "d": 5}));
+        assert_eq!(diff.value.to_string(), r#"{"a":1,"b":2,"c":4,"d":5}"#);
+        assert_eq!(diff.counts(), (1, 1, 1));
+        assert_eq!(diff.mark(&[String::from("b")]), Some(&Mark::Removed));
+        assert_eq!(diff.mark(&[String::from("c")]), Some(&Mark::Changed(json!(3))));
+        assert_eq!(diff.touched, [vec![String::from("c")], vec![String::from("d")], vec![]]);
+        let next = diff.next(&[String::from("a")], true);
+        assert_eq!(next, Some((1, [String::from("b")].as_slice())));
+    }
+
+    #[test]
+    fn changed_items_pair_up() {
+        let diff = diff(&json!([1, {"x": 1}, 3]), &json!([1, {"x": 2}, 3, 4]));
+        assert_eq!(diff.counts(), (1, 0, 1));
+        assert_eq!(diff.mark(&[String::from("1"), String::from("x")]), Some(&Mark::Changed(json!(1))));
+        assert_eq!(diff.mark(&[String::from("3")]), Some(&Mark::Added));
+        assert!(diff.differs(&[String::from("1")]));
+        assert!(!diff.differs(&[String::from("0")]));
+    }
+}
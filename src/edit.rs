@@ -0,0 +1,249 @@
+use crate::app::to_pointer;
+use crate::history::Location;
+use serde_json::Value;
+use std::collections::HashSet;
+
+// Changes kept for undo; the oldest are dropped
+const MAX_CHANGES: usize = 200;
+
+/// One edit of the document, made in place on the value at `path`. Only the
+/// operation that takes it back is kept: undoing it turns it into the one
+/// that redoes it, and back, so no copy of the object or array is held.
+pub struct Change {
+    pub description: String,
+    pub path: Vec<String>,
+    pub op: Op,
+    // Where the cursor was before, and goes to after
+    pub from: Location,
+    pub to: Location,
+    // The nodes to show as modified
+    pub marked: Vec<Vec<String>>,
+}
+
+impl Change {
+    /// Undoes the change on `doc` if it is applied, redoes it if not.
+    pub fn apply(&mut self, doc: &mut Value) -> Result<(), String> {
+        let pointer = to_pointer(&self.path);
+        let target = doc.pointer_mut(&pointer).ok_or_else(|| format!("no value at {}", pointer))?;
+        self.op.apply(target)
+    }
+}
+
+/// What an edit does to the value at its path. Members are by index.
+#[derive(Debug)]
+pub enum Op {
+    // The value replaced as a whole
+    Set(Value),
+    // A member added, under the key if it is an object
+    Insert(usize, String, Value),
+    Remove(usize),
+    // The key of an object's member changed, in the same position
+    Rename(usize, String),
+    Swap(usize, usize),
+}
+
+impl Op {
+    /// Does the operation on `target`, in place, and becomes the one that
+    /// undoes it. Nothing changes if it doesn't fit `target`.
+    pub fn apply(&mut self, target: &mut Value) -> Result<(), String> {
+        let len = len(target);
+        let fits = match self {
+            Op::Set(_) => true,
+            Op::Insert(i, ..) => *i <= len && (target.is_array() || target.is_object()),
+            Op::Remove(i) | Op::Rename(i, _) => *i < len,
+            Op::Swap(a, b) => *a < len && *b < len,
+        };
+        if !fits {
+            return Err(String::from("the edit no longer fits the document"));
+        }
+        match (&mut *self, target) {
+            (Op::Set(value), target) => std::mem::swap(value, target),
+            (Op::Insert(i, _, value), Value::Array(items)) => {
+                items.insert(*i, std::mem::take(value));
+                *self = Op::Remove(*i);
+            }
+            (Op::Insert(i, key, value), Value::Object(map)) => {
+                map.shift_insert(*i, std::mem::take(key), std::mem::take(value));
+                *self = Op::Remove(*i);
+            }
+            (Op::Remove(i), Value::Array(items)) => {
+                let value = items.remove(*i);
+                *self = Op::Insert(*i, String::new(), value);
+            }
+            (Op::Remove(i), Value::Object(map)) => {
+                let (key, value) = remove_at(map, *i);
+                *self = Op::Insert(*i, key, value);
+            }
+            (Op::Rename(i, key), Value::Object(map)) => {
+                let (old, value) = remove_at(map, *i);
+                map.shift_insert(*i, std::mem::replace(key, old), value);
+            }
+            (Op::Swap(a, b), Value::Array(items)) => items.swap(*a, *b),
+            (Op::Swap(a, b), Value::Object(map)) => {
+                let (a, b) = ((*a).min(*b), (*a).max(*b));
+                if a != b {
+                    // The later one goes to the front, pushing the earlier one
+                    // a step down, from where it goes to the back
+                    let (key, value) = remove_at(map, b);
+                    map.shift_insert(a, key, value);
+                    let (key, value) = remove_at(map, a + 1);
+                    map.shift_insert(b, key, value);
+                }
+            }
+            _ => return Err(String::from("only keys of objects can be renamed")),
+        }
+        Ok(())
+    }
+}
+
+// Takes the member at `index` out of an object, keeping the others in order
+fn remove_at(map: &mut serde_json::Map<String, Value>, index: usize) -> (String, Value) {
+    let key = map.keys().nth(index).cloned().unwrap_or_default();
+    map.shift_remove_entry(&key).unwrap_or_default()
+}
+
+/// The changes made to the document, for undo and redo, and which of them
+/// are not saved yet.
+#[derive(Default)]
+pub struct Edits {
+    changes: Vec<Change>,
+    // How many of the changes are in the document; the rest were undone
+    applied: usize,
+    // How many were applied when the document was last saved, if that
+    // point is still in `changes`
+    saved: Option<usize>,
+    // Nodes changed by edits that are gone from the list but not saved
+    lost: HashSet<Vec<String>>,
+}
+
+impl Edits {
+    pub fn new() -> Edits {
+        Edits {
+            saved: Some(0),
+            ..Edits::default()
+        }
+    }
+
+    /// Records a change that was just made, dropping the undone ones.
+    pub fn push(&mut self, change: Change) {
+        if let Some(saved) = self.saved.filter(|saved| *saved > self.applied) {
+            self.lose(self.applied, saved);
+        }
+        self.changes.truncate(self.applied);
+        self.changes.push(change);
+        if self.changes.len() > MAX_CHANGES {
+            if self.saved.map_or(true, |saved| saved == 0) {
+                self.lose(0, 1);
+            }
+            self.changes.remove(0);
+            self.saved = self.saved.and_then(|saved| saved.checked_sub(1));
+        }
+        self.applied = self.changes.len();
+    }
+
+    /// The change to undo, now counted as undone.
+    pub fn undo(&mut self) -> Option<&mut Change> {
+        self.applied = self.applied.checked_sub(1)?;
+        self.changes.get_mut(self.applied)
+    }
+
+    /// The change to redo, now counted as applied again.
+    pub fn redo(&mut self) -> Option<&mut Change> {
+        let change = self.changes.get_mut(self.applied)?;
+        self.applied += 1;
+        Some(change)
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.applied);
+        self.lost.clear();
+    }
+
+    /// The nodes that differ from the saved document.
+    pub fn modified(&self) -> HashSet<Vec<String>> {
+        let (from, to) = match self.saved {
+            Some(saved) => (saved.min(self.applied), saved.max(self.applied)),
+            None => (0, self.applied),
+        };
+        let mut modified = self.lost.clone();
+        modified.extend(self.changes[from..to].iter().flat_map(|c| c.marked.iter().cloned()));
+        modified
+    }
+
+    fn lose(&mut self, from: usize, to: usize) {
+        self.lost.extend(self.changes[from..to].iter().flat_map(|c| c.marked.iter().cloned()));
+        self.saved = None;
+    }
+}
+
+/// How many nodes a value is made of, to ask before deleting many.
+pub fn size(value: &Value) -> usize {
+    match value {
+        Value::Object(map) => 1 + map.values().map(size).sum::<usize>(),
+        Value::Array(items) => 1 + items.iter().map(size).sum::<usize>(),
+        _ => 1,
+    }
+}
+
+/// A key for a copy of `key` that `object` doesn't have yet.
+pub fn copy_key(object: &Value, key: &str) -> String {
+    let mut copy = format!("{} copy", key);
+    let mut n = 2;
+    while object.get(&copy).is_some() {
+        copy = format!("{} copy {}", key, n);
+        n += 1;
+    }
+    copy
+}
+
+/// How many members an object or array has.
+pub fn len(container: &Value) -> usize {
+    match container {
+        Value::Object(map) => map.len(),
+        Value::Array(items) => items.len(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Applies `op`, checks the result, and checks its inverse restores `value`
+    fn round_trip(value: Value, mut op: Op, expected: Value) {
+        let mut target = value.clone();
+        op.apply(&mut target).unwrap();
+        assert_eq!(serde_json::to_string(&target).unwrap(), serde_json::to_string(&expected).unwrap());
+        op.apply(&mut target).unwrap();
+        assert_eq!(serde_json::to_string(&target).unwrap(), serde_json::to_string(&value).unwrap());
+    }
+
+    #[test]
+    fn object_members_keep_their_order() {
+        let object = json!({"a": 1, "b": 2, "c": 3});
+        round_trip(object.clone(), Op::Remove(1), json!({"a": 1, "c": 3}));
+        round_trip(object.clone(), Op::Insert(1, String::from("x"), json!(0)), json!({"a": 1, "x": 0, "b": 2, "c": 3}));
+        round_trip(object.clone(), Op::Rename(1, String::from("y")), json!({"a": 1, "y": 2, "c": 3}));
+        round_trip(object.clone(), Op::Swap(0, 2), json!({"c": 3, "b": 2, "a": 1}));
+        round_trip(object, Op::Swap(2, 1), json!({"a": 1, "c": 3, "b": 2}));
+    }
+
+    #[test]
+    fn array_items() {
+        let array = json!([1, 2, 3]);
+        round_trip(array.clone(), Op::Remove(0), json!([2, 3]));
+        round_trip(array.clone(), Op::Insert(3, String::new(), json!(4)), json!([1, 2, 3, 4]));
+        round_trip(array.clone(), Op::Swap(0, 1), json!([2, 1, 3]));
+        round_trip(array, Op::Set(json!(null)), json!(null));
+    }
+
+    #[test]
+    fn ops_that_do_not_fit_change_nothing() {
+        let mut array = json!([1]);
+        assert!(Op::Remove(1).apply(&mut array).is_err());
+        assert!(Op::Rename(0, String::from("k")).apply(&mut array).is_err());
+        assert!(Op::Insert(0, String::new(), json!(2)).apply(&mut json!(3)).is_err());
+        assert_eq!(array, json!([1]));
+    }
+}
@@ -1,12 +1,11 @@
-use crate::event::Key;
+use crate::event::{Key, Mouse};
 use crossterm::event;
 use std::{sync::mpsc, thread, time::Duration};
 
 #[derive(Debug, Clone, Copy)]
-/// Configuration for event handling.
+/// Configuration for event handling. Which key quits the application is
+/// decided by the keymap, not here.
 pub struct EventConfig {
-  /// The key that is used to exit the application.
-  pub exit_key: Key,
   /// The tick rate at which the application will sent an tick event.
   pub tick_rate: Duration,
 }
@@ -14,7 +13,6 @@ pub struct EventConfig {
 impl Default for EventConfig {
   fn default() -> EventConfig {
     EventConfig {
-      exit_key: Key::Ctrl('c'),
       tick_rate: Duration::from_millis(250),
     }
   }
@@ -24,11 +22,13 @@ impl Default for EventConfig {
 pub enum Event<I> {
   /// An input event occurred.
   Input(I),
+  /// A mouse event occurred.
+  Mouse(Mouse),
   /// An tick event occurred.
   Tick,
 }
 
-/// A small event handler that wrap crossterm input and tick event. Each event
+/// A small event handler that wrap crossterm input, mouse and tick event. Each event
 /// type is handled in its own thread and returned to a common `Receiver`
 pub struct Events {
   rx: mpsc::Receiver<Event<Key>>,
@@ -54,10 +54,17 @@ impl Events {
       loop {
         // poll for tick rate duration, if no event, sent tick event.
         if event::poll(config.tick_rate).unwrap() {
-          if let event::Event::Key(key) = event::read().unwrap() {
-            let key = Key::from(key);
+          match event::read().unwrap() {
+            event::Event::Key(key) => {
+              let key = Key::from(key);
 
-            event_tx.send(Event::Input(key)).unwrap();
+              event_tx.send(Event::Input(key)).unwrap();
+            }
+            event::Event::Mouse(mouse) => match Mouse::from(mouse) {
+              Mouse::Unknown => {}
+              mouse => event_tx.send(Event::Mouse(mouse)).unwrap(),
+            },
+            _ => {}
           }
         }
 
@@ -1,5 +1,5 @@
 use crossterm::event;
-use std::fmt;
+use std::{fmt, str::FromStr};
 
 /// Represents an key.
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
@@ -21,6 +21,10 @@ pub enum Key {
   Up,
   /// Down arrow
   Down,
+  /// Left arrow with Alt held
+  AltLeft,
+  /// Right arrow with Alt held
+  AltRight,
 
   /// Insert key
   Ins,
@@ -105,6 +109,8 @@ impl fmt::Display for Key {
       Key::Ctrl(c) => write!(f, "<Ctrl+{}>", c),
       Key::Char(c) => write!(f, "{}", c),
       Key::Left | Key::Right | Key::Up | Key::Down => write!(f, "<{:?} Arrow Key>", self),
+      Key::AltLeft => write!(f, "<Alt+Left>"),
+      Key::AltRight => write!(f, "<Alt+Right>"),
       Key::Enter
       | Key::Tab
       | Key::Backspace
@@ -120,6 +126,79 @@ impl fmt::Display for Key {
   }
 }
 
+/// Parses key names as written in the keymap file.
+///
+/// Accepts named keys (`enter`, `esc`, `pagedown`, `f5`, `space`, ...), a
+/// single character (`j`, `/`), and modified characters written as `ctrl-n`,
+/// `c-n`, `alt-x` or `m-x`. The `<Ctrl+n>` form produced by `Display` is
+/// accepted as well.
+impl FromStr for Key {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let trimmed = s.trim();
+    let name = trimmed
+      .strip_prefix('<')
+      .and_then(|n| n.strip_suffix('>'))
+      .unwrap_or(trimmed);
+
+    let single_char = |rest: &str| -> Option<char> {
+      let mut chars = rest.chars();
+      match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ if rest.eq_ignore_ascii_case("space") => Some(' '),
+        _ => None,
+      }
+    };
+
+    if let Some(c) = single_char(name) {
+      return Ok(Key::Char(c));
+    }
+
+    let lower = name.to_ascii_lowercase();
+    match lower.as_str() {
+      "alt-left" | "alt+left" | "m-left" => return Ok(Key::AltLeft),
+      "alt-right" | "alt+right" | "m-right" => return Ok(Key::AltRight),
+      _ => {}
+    }
+    for (prefixes, make) in [
+      (&["ctrl-", "ctrl+", "c-"][..], Key::Ctrl as fn(char) -> Key),
+      (&["alt-", "alt+", "m-", "meta-"][..], Key::Alt as fn(char) -> Key),
+    ] {
+      for prefix in prefixes {
+        if lower.starts_with(prefix) {
+          return single_char(&name[prefix.len()..])
+            .map(|c| make(c.to_ascii_lowercase()))
+            .ok_or_else(|| format!("invalid key `{}`", s));
+        }
+      }
+    }
+
+    let key = match lower.as_str() {
+      "enter" | "return" => Key::Enter,
+      "tab" => Key::Tab,
+      "backspace" | "bs" => Key::Backspace,
+      "esc" | "escape" => Key::Esc,
+      "left" => Key::Left,
+      "right" => Key::Right,
+      "up" => Key::Up,
+      "down" => Key::Down,
+      "ins" | "insert" => Key::Ins,
+      "del" | "delete" => Key::Delete,
+      "home" => Key::Home,
+      "end" => Key::End,
+      "pageup" | "pgup" => Key::PageUp,
+      "pagedown" | "pgdn" => Key::PageDown,
+      f if f.starts_with('f') => match f[1..].parse::<u8>() {
+        Ok(n) if n <= 12 => Key::from_f(n),
+        _ => return Err(format!("invalid key `{}`", s)),
+      },
+      _ => return Err(format!("invalid key `{}`", s)),
+    };
+    Ok(key)
+  }
+}
+
 impl From<event::KeyEvent> for Key {
   fn from(key_event: event::KeyEvent) -> Self {
     match key_event {
@@ -131,6 +210,16 @@ impl From<event::KeyEvent> for Key {
         code: event::KeyCode::Backspace,
         ..
       } => Key::Backspace,
+      event::KeyEvent {
+        code: event::KeyCode::Left,
+        modifiers: event::KeyModifiers::ALT,
+        ..
+      } => Key::AltLeft,
+      event::KeyEvent {
+        code: event::KeyCode::Right,
+        modifiers: event::KeyModifiers::ALT,
+        ..
+      } => Key::AltRight,
       event::KeyEvent {
         code: event::KeyCode::Left,
         ..
@@ -1,7 +1,9 @@
 mod events;
 mod key;
+mod mouse;
 
 pub use self::{
   events::{Event, Events},
   key::Key,
+  mouse::Mouse,
 };
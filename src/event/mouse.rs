@@ -0,0 +1,26 @@
+use crossterm::event;
+
+/// Represents a mouse action, reduced to the ones the app reacts to. Every
+/// variant carries the `(column, row)` the pointer was at.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Mouse {
+  /// Left button pressed
+  Click(u16, u16),
+  /// Wheel scrolled away from the user
+  ScrollUp(u16, u16),
+  /// Wheel scrolled towards the user
+  ScrollDown(u16, u16),
+  Unknown,
+}
+
+impl From<event::MouseEvent> for Mouse {
+  fn from(mouse_event: event::MouseEvent) -> Self {
+    let (column, row) = (mouse_event.column, mouse_event.row);
+    match mouse_event.kind {
+      event::MouseEventKind::Down(event::MouseButton::Left) => Mouse::Click(column, row),
+      event::MouseEventKind::ScrollUp => Mouse::ScrollUp(column, row),
+      event::MouseEventKind::ScrollDown => Mouse::ScrollDown(column, row),
+      _ => Mouse::Unknown,
+    }
+  }
+}
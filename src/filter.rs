@@ -0,0 +1,349 @@
+// Structured search queries such as `key:status value:failed`,
+// `type:array len>100`, `price>=10.5` or `path:/items/*/tags`
+
+use crate::app::from_pointer;
+use crate::search::{Matcher, SearchMode, SearchOptions};
+use serde_json::Value;
+
+/// A query of space separated conditions that must all hold for a node.
+pub struct Filter {
+    conditions: Vec<Condition>,
+}
+
+struct Condition {
+    negated: bool,
+    term: Term,
+}
+
+enum Term {
+    Key(Matcher),
+    Value(Matcher),
+    Type(Kind),
+    Len(Comparison, f64),
+    Field(String, Comparison, Operand),
+    Path(Vec<String>),
+    // A plain word, matched like a normal search
+    Text(Matcher),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Null,
+    Bool,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+#[derive(Clone, Copy)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+enum Operand {
+    Number(f64),
+    Text(String),
+}
+
+/// The node a filter is asked about.
+pub struct Node<'a> {
+    pub path: &'a [String],
+    pub value: &'a Value,
+    // What plain words are matched against, see `SearchEntry::haystack`
+    pub text: &'a str,
+}
+
+impl Filter {
+    /// Parses `query`, or returns `None` when it has no operators and should
+    /// be searched as plain text.
+    pub fn parse(query: &str, options: SearchOptions) -> Result<Option<Filter>, String> {
+        let words = split(query)?;
+        if !words.iter().any(|w| is_operator(w.strip_prefix('-').unwrap_or(w))) {
+            return Ok(None);
+        }
+        // Fuzzy matching is too loose to pick fields by; use substrings then
+        let field_options = SearchOptions {
+            mode: if options.mode == SearchMode::Fuzzy { SearchMode::Substring } else { options.mode },
+            ..options
+        };
+        let conditions = words
+            .iter()
+            .map(|word| {
+                let (negated, word) = match word.strip_prefix('-') {
+                    Some(rest) if !rest.is_empty() => (true, rest),
+                    _ => (false, word.as_str()),
+                };
+                let term = term(word, options, field_options).map_err(|e| format!("{}: {}", word, e))?;
+                Ok(Condition { negated, term })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Some(Filter { conditions }))
+    }
+
+    pub fn matches(&self, node: &Node) -> bool {
+        self.conditions.iter().all(|c| c.term.holds(node) != c.negated)
+    }
+
+    /// Sum of the fuzzy scores of the plain words, to rank results by.
+    pub fn score(&self, node: &Node) -> i64 {
+        self.conditions
+            .iter()
+            .filter(|c| !c.negated)
+            .filter_map(|c| match &c.term {
+                Term::Text(matcher) => matcher.score(node.text),
+                _ => None,
+            })
+            .sum()
+    }
+}
+
+// Splits on spaces, keeping double quoted parts such as `value:"not found"`
+// together
+fn split(query: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if quoted {
+        return Err(String::from("unclosed quote"));
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+const PREFIXES: &[&str] = &["key:", "value:", "type:", "path:"];
+
+fn is_operator(word: &str) -> bool {
+    PREFIXES.iter().any(|p| word.starts_with(p)) || comparison(word).is_some()
+}
+
+// `field>=10` as its three parts
+fn comparison(word: &str) -> Option<(&str, Comparison, &str)> {
+    let at = word.find(|c| matches!(c, '<' | '>' | '=' | '!'))?;
+    let (field, rest) = word.split_at(at);
+    let (op, len) = match rest.get(..2) {
+        Some("<=") => (Comparison::LessOrEqual, 2),
+        Some(">=") => (Comparison::GreaterOrEqual, 2),
+        Some("!=") => (Comparison::NotEqual, 2),
+        Some("==") => (Comparison::Equal, 2),
+        _ => match &rest[..1] {
+            "<" => (Comparison::Less, 1),
+            ">" => (Comparison::Greater, 1),
+            "=" => (Comparison::Equal, 1),
+            _ => return None,
+        },
+    };
+    if field.is_empty() || field.contains(':') {
+        return None;
+    }
+    Some((field, op, &rest[len..]))
+}
+
+fn term(word: &str, options: SearchOptions, field_options: SearchOptions) -> Result<Term, String> {
+    if let Some(pattern) = word.strip_prefix("key:") {
+        return Ok(Term::Key(Matcher::new(pattern, field_options)?));
+    }
+    if let Some(pattern) = word.strip_prefix("value:") {
+        return Ok(Term::Value(Matcher::new(pattern, field_options)?));
+    }
+    if let Some(kind) = word.strip_prefix("type:") {
+        return Ok(Term::Type(match kind {
+            "null" => Kind::Null,
+            "bool" | "boolean" => Kind::Bool,
+            "number" => Kind::Number,
+            "string" => Kind::String,
+            "array" => Kind::Array,
+            "object" => Kind::Object,
+            _ => return Err(String::from("types are null, bool, number, string, array and object")),
+        }));
+    }
+    if let Some(pattern) = word.strip_prefix("path:") {
+        let pattern = if pattern.starts_with('/') { pattern.to_owned() } else { format!("/{}", pattern) };
+        return Ok(Term::Path(from_pointer(&pattern)));
+    }
+    if let Some((field, op, operand)) = comparison(word) {
+        let number = operand.parse::<f64>().ok();
+        if field == "len" {
+            return Ok(Term::Len(op, number.ok_or("len is compared with a number")?));
+        }
+        let operand = match (number, op) {
+            (Some(n), _) => Operand::Number(n),
+            (None, Comparison::Equal | Comparison::NotEqual) => Operand::Text(operand.to_owned()),
+            (None, _) => return Err(String::from("only numbers can be ordered")),
+        };
+        return Ok(Term::Field(field.to_owned(), op, operand));
+    }
+    Ok(Term::Text(Matcher::new(word, options)?))
+}
+
+impl Term {
+    fn holds(&self, node: &Node) -> bool {
+        let key = node.path.last().map_or("", |k| k.as_str());
+        match self {
+            Term::Key(matcher) => matcher.score(key).is_some(),
+            Term::Value(matcher) => match node.value {
+                Value::String(s) => matcher.score(s).is_some(),
+                Value::Array(_) | Value::Object(_) => false,
+                other => matcher.score(&other.to_string()).is_some(),
+            },
+            Term::Type(kind) => kind_of(node.value) == *kind,
+            Term::Len(op, n) => match node.value {
+                Value::String(s) => compare(s.chars().count() as f64, *op, *n),
+                Value::Array(items) => compare(items.len() as f64, *op, *n),
+                Value::Object(map) => compare(map.len() as f64, *op, *n),
+                _ => false,
+            },
+            Term::Field(field, op, operand) => {
+                key == field
+                    && match (operand, node.value) {
+                        (Operand::Number(n), Value::Number(v)) => v.as_f64().map_or(false, |v| compare(v, *op, *n)),
+                        (Operand::Text(text), value) => {
+                            let equal = match value {
+                                Value::String(s) => s == text,
+                                Value::Array(_) | Value::Object(_) => false,
+                                other => other.to_string() == *text,
+                            };
+                            equal == matches!(op, Comparison::Equal)
+                        }
+                        _ => false,
+                    }
+            }
+            Term::Path(pattern) => path_matches(pattern, node.path),
+            Term::Text(matcher) => matcher.score(node.text).is_some(),
+        }
+    }
+}
+
+fn kind_of(value: &Value) -> Kind {
+    match value {
+        Value::Null => Kind::Null,
+        Value::Bool(_) => Kind::Bool,
+        Value::Number(_) => Kind::Number,
+        Value::String(_) => Kind::String,
+        Value::Array(_) => Kind::Array,
+        Value::Object(_) => Kind::Object,
+    }
+}
+
+fn compare(left: f64, op: Comparison, right: f64) -> bool {
+    match op {
+        Comparison::Less => left < right,
+        Comparison::LessOrEqual => left <= right,
+        Comparison::Greater => left > right,
+        Comparison::GreaterOrEqual => left >= right,
+        Comparison::Equal => left == right,
+        Comparison::NotEqual => left != right,
+    }
+}
+
+// `*` stands for any one token and `**` for any number of them
+fn path_matches(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => (0..=path.len()).any(|skip| path_matches(rest, &path[skip..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((token, path)) => (first == "*" || first == token) && path_matches(rest, path),
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Whether `query` holds for the node at `path` with `value`
+    fn holds(query: &str, path: &str, value: Value) -> bool {
+        let filter = Filter::parse(query, SearchOptions::default()).unwrap().unwrap();
+        let path: Vec<String> = path.split('/').filter(|t| !t.is_empty()).map(str::to_owned).collect();
+        let text = format!("{} : {}", path.last().map_or("", |k| k.as_str()), value);
+        filter.matches(&Node {
+            path: &path,
+            value: &value,
+            text: &text,
+        })
+    }
+
+    fn error(query: &str) -> String {
+        Filter::parse(query, SearchOptions::default()).err().unwrap()
+    }
+
+    #[test]
+    fn plain_words_are_not_a_filter() {
+        assert!(Filter::parse("timeout error", SearchOptions::default()).unwrap().is_none());
+        assert!(Filter::parse("timeout key:error", SearchOptions::default()).unwrap().is_some());
+    }
+
+    #[test]
+    fn keys_and_values() {
+        assert!(holds("key:stat", "/items/0/status", json!("failed")));
+        // Fuzzy matching gives way to substrings for fields
+        assert!(!holds("key:sts", "/items/0/status", json!("failed")));
+        assert!(holds("value:fail", "/items/0/status", json!("failed")));
+        assert!(holds("value:42", "/count", json!(42)));
+        assert!(!holds("value:fail", "/items", json!(["failed"])));
+    }
+
+    #[test]
+    fn quoting() {
+        assert!(holds(r#"value:"not found""#, "/error", json!("file not found")));
+        assert!(!holds(r#"value:"not found""#, "/error", json!("not-found")));
+        assert_eq!(error(r#"value:"not found"#), "unclosed quote");
+    }
+
+    #[test]
+    fn types_and_lengths() {
+        assert!(holds("type:array len>2", "/tags", json!([1, 2, 3])));
+        assert!(!holds("type:array len>2", "/tags", json!([1, 2])));
+        assert!(holds("len<=2", "/name", json!("ab")));
+        assert!(!holds("len>0", "/count", json!(7)));
+        assert!(holds("-type:null", "/name", json!("ab")));
+        assert_eq!(error("type:list"), "type:list: types are null, bool, number, string, array and object");
+        assert_eq!(error("len>many"), "len>many: len is compared with a number");
+    }
+
+    #[test]
+    fn field_comparisons() {
+        assert!(holds("price>=10.5", "/items/3/price", json!(10.5)));
+        assert!(!holds("price>10.5", "/items/3/price", json!(10.5)));
+        assert!(!holds("price>=10.5", "/items/3/cost", json!(11)));
+        assert!(holds("status=failed", "/status", json!("failed")));
+        assert!(holds("status!=failed", "/status", json!("done")));
+        assert!(holds("ok==true", "/ok", json!(true)));
+        assert_eq!(error("status<failed"), "status<failed: only numbers can be ordered");
+    }
+
+    #[test]
+    fn paths() {
+        assert!(holds("path:/items/*/tags", "/items/0/tags", json!([])));
+        assert!(!holds("path:/items/*/tags", "/items/0/x/tags", json!([])));
+        assert!(holds("path:**/id", "/items/0/owner/id", json!(1)));
+        assert!(holds("path:items/**", "/items/0/owner", json!({})));
+    }
+
+    #[test]
+    fn every_condition_must_hold() {
+        assert!(holds("key:status value:failed", "/status", json!("failed")));
+        assert!(!holds("key:status value:failed", "/state", json!("failed")));
+        assert!(!holds("key:status -value:failed", "/status", json!("failed")));
+    }
+}
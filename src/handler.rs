@@ -0,0 +1,537 @@
+use crate::app::{to_pointer, ActiveBlock, App, Confirmation, Route};
+use crate::command::{run_command, run_saved_query};
+use crate::event::{Key, Mouse};
+use crate::keymap::Action;
+use std::time::{Duration, Instant};
+use tui::layout::Rect;
+
+// Two clicks on the same row within this time count as a double-click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+// Runs an action looked up in the keymap for the current route
+pub fn handle_action(action: Action, app: &mut App) {
+    let route = app.get_current_route();
+    // A Ctrl-r search takes over the prompt until it is accepted or cancelled;
+    // other keys accept it and then do what they always do
+    if app.reverse_search.is_some() && route.takes_text() {
+        match action {
+            Action::SearchInputHistory => app.reverse_search_older(),
+            Action::Confirm => app.finish_reverse_search(true),
+            Action::Cancel | Action::ExitSearch => app.finish_reverse_search(false),
+            _ => {
+                app.finish_reverse_search(true);
+                handle_action(action, app);
+            }
+        }
+        return;
+    }
+    match (route, action) {
+        (_, Action::ToggleHelp) => app.toggle_help(),
+        (_, Action::ToggleMouse) => {
+            app.mouse_capture = !app.mouse_capture;
+            app.status = Some(String::from(if app.mouse_capture {
+                "Mouse captured"
+            } else {
+                "Mouse released, terminal text selection works again"
+            }));
+        }
+        (Route::Main, Action::NextItem) => app.elements.as_mut().unwrap().next(),
+        (Route::Main, Action::PreviousItem) => app.elements.as_mut().unwrap().previous(),
+        (Route::Main, Action::DrillIn) => {
+            if app.show_detail() {
+                return;
+            }
+            let from = app.location();
+            app.set_route();
+            app.set_elements();
+            app.record_jump(from);
+        }
+        (Route::Main, Action::GoBack) => {
+            if app.navigation_stack.len() > 1 {
+                let from = app.location();
+                app.pop_route();
+                app.set_elements();
+                app.record_jump(from);
+            }
+        }
+        (Route::Main, Action::HistoryBack) => {
+            if let Some(location) = app.history.back(app.location()) {
+                app.go_to(&location);
+            }
+        }
+        (Route::Main, Action::HistoryForward) => {
+            if let Some(location) = app.history.forward(app.location()) {
+                app.go_to(&location);
+            }
+        }
+        (Route::Main, Action::ShowHistory) => {
+            app.history_list.select(Some(app.history.cursor()));
+            app.set_current_route(Route::History);
+        }
+        // Marks are paths in the file's own document
+        (Route::Main, Action::SetMark | Action::JumpToMark | Action::ShowMarks) if app.derived.is_some() => {
+            app.status = Some(String::from("Marks belong to the original document; go back to it first"));
+        }
+        (Route::Main, Action::SetMark) => {
+            app.pending = Some(Action::SetMark);
+            app.status = Some(String::from("Set mark: press a letter"));
+        }
+        (Route::Main, Action::JumpToMark) => {
+            app.pending = Some(Action::JumpToMark);
+            app.status = Some(String::from("Jump to mark: press a letter"));
+        }
+        (Route::Main, Action::ShowMarks) => {
+            app.marks_list.select(if app.marks.len() > 0 { Some(0) } else { None });
+            app.set_current_route(Route::Marks);
+        }
+        (Route::Main, Action::Reload) if !app.modified.is_empty() => app.confirm(Confirmation::Reload),
+        (Route::Main, Action::Reload) => reload(app),
+        (Route::Main, Action::TogglePreview) => app.show_preview = !app.show_preview,
+        (Route::Main | Route::Logs, Action::StartCommand) => {
+            // Commands run from the log view go back to it
+            app.previous_route = app.current_route;
+            app.command_input.clear();
+            app.input_history.stop_browsing();
+            app.set_current_route(Route::Command);
+        }
+        (Route::Main, Action::StartSearch) => {
+            app.input_history.stop_browsing();
+            app.active_block = ActiveBlock::Search;
+            app.set_current_route(Route::Search);
+            app.set_fuzzy_elements();
+        }
+        (Route::Search | Route::Command | Route::Jq, Action::SearchInputHistory) => app.start_reverse_search(),
+        // In the search bar Down goes through newer queries and then on into
+        // the results; Up on the first result goes back to the bar
+        (Route::Search, Action::NextItem) => match app.active_block {
+            ActiveBlock::Search => {
+                if app.recall_newer() {
+                    return;
+                }
+                let list = app.fuzzy_elements.as_mut().unwrap();
+                if !list.items.is_empty() {
+                    list.select(0);
+                    app.active_block = ActiveBlock::Output;
+                }
+            }
+            ActiveBlock::Output => app.fuzzy_elements.as_mut().unwrap().next(),
+        },
+        (Route::Search, Action::PreviousItem) => match app.active_block {
+            ActiveBlock::Search => app.recall_older(),
+            ActiveBlock::Output => {
+                let list = app.fuzzy_elements.as_mut().unwrap();
+                if list.state.selected().map_or(true, |i| i == 0) {
+                    list.state.select(None);
+                    app.active_block = ActiveBlock::Search;
+                } else {
+                    list.previous();
+                }
+            }
+        },
+        (Route::Command | Route::Jq, Action::NextItem) => {
+            app.recall_newer();
+        }
+        (Route::Command | Route::Jq, Action::PreviousItem) => app.recall_older(),
+        (Route::Search, Action::CycleSearchMode) => {
+            app.search_options.mode = app.search_options.mode.next();
+            app.search_and_set_fuzzy_data();
+        }
+        (Route::Search, Action::CycleSearchCase) => {
+            app.search_options.case = app.search_options.case.next();
+            app.search_and_set_fuzzy_data();
+        }
+        (Route::Search, Action::CycleSearchScope) => {
+            app.search_options.scope = app.search_options.scope.next();
+            app.search_and_set_fuzzy_data();
+        }
+        (Route::Search, Action::ToggleSearchSubtree) => {
+            app.search_options.subtree = !app.search_options.subtree;
+            app.search_and_set_fuzzy_data();
+        }
+        (Route::Search, Action::ExitSearch) => {
+            app.remember_input();
+            app.user_input.clear();
+            app.set_current_route(Route::Main)
+        }
+        (Route::Search, Action::Confirm) => {
+            let list = app.fuzzy_elements.as_ref().unwrap();
+            let path = match list.state.selected().and_then(|i| list.items.get(i)) {
+                Some(&index) => match app.search_entries.get(index) {
+                    Some(entry) => entry.path.clone(),
+                    None => return,
+                },
+                None => return,
+            };
+            if let Some(location) = app.location_of(&path) {
+                app.remember_input();
+                app.user_input.clear();
+                app.set_current_route(Route::Main);
+                app.jump(&location);
+            }
+        }
+        (Route::Main, Action::StartJq) => {
+            app.input_history.stop_browsing();
+            app.start_jq();
+        }
+        (Route::Jq, Action::Confirm) => match app.apply_jq() {
+            Ok(true) => {
+                app.remember_input();
+                app.set_current_route(Route::Main);
+            }
+            Ok(false) => {}
+            Err(e) => app.jq_result = Some(Err(e)),
+        },
+        (Route::Jq, Action::Cancel) => {
+            app.jq_waiting = false;
+            app.set_current_route(Route::Main);
+        }
+        (Route::Command, Action::Confirm) => {
+            app.remember_input();
+            let line = std::mem::take(&mut app.command_input);
+            app.set_current_route(app.previous_route);
+            if let Err(e) = run_command(&line, app) {
+                app.status = Some(e);
+            }
+        }
+        (Route::Command, Action::Cancel) => {
+            app.command_input.clear();
+            app.set_current_route(app.previous_route);
+        }
+        (Route::History, Action::NextItem) => {
+            let i = app.history_list.selected().map_or(0, |i| i + 1);
+            app.history_list.select(Some(i.min(app.history.entries().len() - 1)));
+        }
+        (Route::History, Action::PreviousItem) => {
+            let i = app.history_list.selected().unwrap_or(0);
+            app.history_list.select(Some(i.saturating_sub(1)));
+        }
+        (Route::History, Action::Confirm) => {
+            let index = app.history_list.selected().unwrap_or(app.history.cursor());
+            if let Some(location) = app.history.go_to(index, app.location()) {
+                app.go_to(&location);
+            }
+            app.set_current_route(Route::Main);
+        }
+        (Route::History, Action::Cancel) => app.set_current_route(Route::Main),
+        (Route::Marks, Action::NextItem) => {
+            let i = app.marks_list.selected().map_or(0, |i| i + 1);
+            app.marks_list.select(Some(i.min(app.marks.len().saturating_sub(1))));
+        }
+        (Route::Marks, Action::PreviousItem) => {
+            let i = app.marks_list.selected().unwrap_or(0);
+            app.marks_list.select(Some(i.saturating_sub(1)));
+        }
+        (Route::Marks, Action::Confirm) => {
+            let letter = app.marks_list.selected().and_then(|i| app.marks.iter().nth(i)).map(|(l, _)| *l);
+            if let Some(letter) = letter {
+                app.set_current_route(Route::Main);
+                jump_to_mark(letter, app);
+            }
+        }
+        (Route::Marks, Action::Delete) => {
+            let letter = app.marks_list.selected().and_then(|i| app.marks.iter().nth(i)).map(|(l, _)| *l);
+            if let Some(letter) = letter {
+                if let Err(e) = app.marks.remove(letter) {
+                    app.status = Some(format!("could not save marks: {}", e));
+                }
+                let last = app.marks.len().checked_sub(1);
+                app.marks_list.select(app.marks_list.selected().and_then(|i| last.map(|l| i.min(l))));
+            }
+        }
+        (Route::Marks, Action::Cancel) => app.set_current_route(Route::Main),
+        (Route::Main, Action::ShowQueries) => app.show_queries(),
+        (Route::Main, Action::EditValue) => {
+            if let Err(e) = app.start_edit() {
+                app.status = Some(e);
+            }
+        }
+        (Route::Main, Action::RenameKey) => {
+            if let Err(e) = app.start_rename() {
+                app.status = Some(e);
+            }
+        }
+        (Route::Main, Action::InsertAfter) => report(app.start_insert(true), app),
+        (Route::Main, Action::InsertBefore) => report(app.start_insert(false), app),
+        (Route::Main, Action::DeleteItem) => report(app.delete_selected(false), app),
+        (Route::Main, Action::MoveUp) => report(app.move_selected(true), app),
+        (Route::Main, Action::MoveDown) => report(app.move_selected(false), app),
+        (Route::Main, Action::Duplicate) => report(app.duplicate_selected(), app),
+        (Route::Main, Action::Undo) => report(app.undo(), app),
+        (Route::Main, Action::ShowLogs) => {
+            if let Err(e) = app.show_logs() {
+                app.status = Some(e);
+            }
+        }
+        (Route::Logs, Action::NextItem) => app.logs.as_mut().unwrap().step(true),
+        (Route::Logs, Action::PreviousItem) => app.logs.as_mut().unwrap().step(false),
+        (Route::Logs, Action::Confirm) => app.logs.as_mut().unwrap().toggle_expanded(),
+        (Route::Logs, Action::Cancel) => app.set_current_route(Route::Main),
+        (Route::Logs, Action::CycleLevel) => {
+            let view = app.logs.as_mut().unwrap();
+            view.min_level = view.min_level.next();
+            view.refresh();
+            app.status = Some(format!("Showing {} and above", view.min_level.name()));
+        }
+        (Route::Logs, Action::ShowInTree) => app.show_log_record(),
+        (Route::Main, Action::Redo) => report(app.redo(), app),
+        (Route::Main, Action::AcceptPatch) => report(app.accept_patch(), app),
+        (Route::Main, Action::DiscardPatch) => report(app.discard_patch(), app),
+        (Route::Main, Action::NextChange) => report(app.next_change(true), app),
+        (Route::Main, Action::PreviousChange) => report(app.next_change(false), app),
+        (Route::Main, Action::ToggleUnchanged) => report(app.toggle_unchanged(), app),
+        // Invalid input keeps the editor open with the problem shown
+        (Route::Edit, Action::Confirm) => report(app.confirm_edit(), app),
+        (Route::Edit, Action::Cancel) => app.set_current_route(Route::Main),
+        (Route::Confirm, Action::Confirm) => {
+            app.set_current_route(Route::Main);
+            match app.confirming.take() {
+                Some(Confirmation::Delete(_)) => report(app.delete_selected(true), app),
+                Some(Confirmation::Reload) => reload(app),
+                Some(Confirmation::Quit) => app.quitting = true,
+                Some(Confirmation::Stitch(stitched)) => {
+                    let (value, stitch) = *stitched;
+                    report(app.show_stitched(value, stitch), app);
+                }
+                None => {}
+            }
+        }
+        (Route::Confirm, Action::Cancel) => {
+            app.confirming = None;
+            app.set_current_route(Route::Main);
+        }
+        (Route::Queries, Action::NextItem) => {
+            let i = app.queries_list.selected().map_or(0, |i| i + 1);
+            app.queries_list.select(Some(i.min(app.saved_queries.queries.len().saturating_sub(1))));
+        }
+        (Route::Queries, Action::PreviousItem) => {
+            let i = app.queries_list.selected().unwrap_or(0);
+            app.queries_list.select(Some(i.saturating_sub(1)));
+        }
+        (Route::Queries, Action::Confirm) => {
+            let name = match app.queries_list.selected().and_then(|i| app.saved_queries.queries.get(i)) {
+                Some(query) => query.name.clone(),
+                None => return,
+            };
+            app.set_current_route(Route::Main);
+            if let Err(e) = run_saved_query(&name, app) {
+                app.status = Some(e);
+            }
+        }
+        (Route::Queries, Action::Cancel) => app.set_current_route(Route::Main),
+        (Route::Detail, Action::NextItem) => app.detail_scroll = app.detail_scroll.saturating_add(1),
+        (Route::Detail, Action::PreviousItem) => app.detail_scroll = app.detail_scroll.saturating_sub(1),
+        (Route::Detail, Action::PageDown) => {
+            app.detail_scroll = app.detail_scroll.saturating_add(page_height(app))
+        }
+        (Route::Detail, Action::PageUp) => {
+            app.detail_scroll = app.detail_scroll.saturating_sub(page_height(app))
+        }
+        (Route::Detail, Action::Cancel) => app.set_current_route(Route::Main),
+        (Route::Help, Action::NextItem) => app.help_scroll = app.help_scroll.saturating_add(1),
+        (Route::Help, Action::PreviousItem) => app.help_scroll = app.help_scroll.saturating_sub(1),
+        _ => {}
+    }
+}
+
+// Completes an action that was waiting for one more key
+pub fn handle_pending(action: Action, key: Key, app: &mut App) {
+    let letter = match key {
+        Key::Char(c) if c.is_ascii_alphabetic() => c,
+        _ => return,
+    };
+    match action {
+        Action::SetMark => {
+            let path = app.selected_path();
+            let pointer = to_pointer(&path);
+            app.status = Some(match app.marks.set(letter, path) {
+                Ok(()) => format!("Mark '{}' set at {}", letter, if pointer.is_empty() { "/" } else { &pointer }),
+                Err(e) => format!("Mark '{}' set, but could not be saved: {}", letter, e),
+            });
+        }
+        Action::JumpToMark => jump_to_mark(letter, app),
+        _ => {}
+    }
+}
+
+// Shows the outcome of an edit in the status line, if there is one to show
+fn report(result: Result<String, String>, app: &mut App) {
+    match result {
+        Ok(message) if message.is_empty() => {}
+        Ok(message) | Err(message) => app.status = Some(message),
+    }
+}
+
+fn reload(app: &mut App) {
+    app.status = Some(match app.reload() {
+        Ok(dropped) if dropped.is_empty() => String::from("Reloaded"),
+        Ok(dropped) => format!(
+            "Reloaded; dropped marks whose path is gone: {}",
+            dropped.iter().collect::<String>()
+        ),
+        Err(e) => e,
+    });
+}
+
+fn jump_to_mark(letter: char, app: &mut App) {
+    if app.derived.is_some() {
+        app.status = Some(String::from("Marks belong to the original document; go back to it first"));
+        return;
+    }
+    let path = match app.marks.get(letter) {
+        Some(path) => path.clone(),
+        None => {
+            app.status = Some(format!("Mark '{}' is not set", letter));
+            return;
+        }
+    };
+    match app.location_of(&path) {
+        Some(location) => app.jump(&location),
+        None => app.status = Some(format!("Mark '{}' points to {}, which no longer exists", letter, to_pointer(&path))),
+    }
+}
+
+// Hit-tests a mouse event against the areas recorded by the last draw
+pub fn handle_mouse(mouse: Mouse, app: &mut App) {
+    let route = app.get_current_route();
+    match mouse {
+        Mouse::ScrollUp(column, row) | Mouse::ScrollDown(column, row) => {
+            let up = matches!(mouse, Mouse::ScrollUp(..));
+            if contains(app.areas.input, column, row) {
+                if app.show_preview {
+                    let scroll = &mut app.preview_scroll;
+                    *scroll = if up { scroll.saturating_sub(3) } else { scroll.saturating_add(3) };
+                } else {
+                    let scroll = &mut app.input_scroll;
+                    *scroll = if up { scroll.saturating_sub(3) } else { scroll.saturating_add(3) };
+                }
+            } else if route == Route::Search && contains(app.areas.output, column, row) {
+                // The wheel moves through results, never the query history
+                app.active_block = ActiveBlock::Output;
+                let list = app.fuzzy_elements.as_mut().unwrap();
+                if up {
+                    list.previous();
+                } else {
+                    list.next();
+                }
+            } else if contains(app.areas.output, column, row) {
+                let action = if up { Action::PreviousItem } else { Action::NextItem };
+                handle_action(action, app);
+            }
+        }
+        Mouse::Click(column, row) => {
+            if let Some(tab) = app.areas.tabs.iter().position(|r| contains(*r, column, row)) {
+                app.show_preview = tab == 1;
+                return;
+            }
+            if let Some(depth) = app.areas.breadcrumbs.iter().position(|r| contains(*r, column, row)) {
+                if route == Route::Main {
+                    let from = app.location();
+                    app.truncate_route(depth);
+                    app.set_elements();
+                    app.record_jump(from);
+                }
+                return;
+            }
+            if !contains(app.areas.output, column, row) {
+                return;
+            }
+            // Rows start below the top border of the Output block
+            let line = match row.checked_sub(app.areas.output.y + 1) {
+                Some(line) if row + 1 < app.areas.output.bottom() => line as usize,
+                _ => return,
+            };
+            let double = match app.last_click {
+                Some((at, c, r)) => r == row && c.abs_diff(column) < 3 && at.elapsed() < DOUBLE_CLICK,
+                None => false,
+            };
+            app.last_click = Some((Instant::now(), column, row));
+            match route {
+                Route::Main => {
+                    let list = app.elements.as_mut().unwrap();
+                    let index = list.offset + line;
+                    if index >= list.len() {
+                        return;
+                    }
+                    list.select(index);
+                    if double {
+                        app.last_click = None;
+                        handle_action(Action::DrillIn, app);
+                    }
+                }
+                Route::Search => {
+                    let list = app.fuzzy_elements.as_mut().unwrap();
+                    let index = list.offset + line;
+                    if index >= list.items.len() {
+                        return;
+                    }
+                    list.select(index);
+                    app.active_block = ActiveBlock::Output;
+                }
+                Route::Help | Route::Command | Route::Jq | Route::Edit | Route::Confirm | Route::Logs | Route::History | Route::Marks | Route::Queries | Route::Detail => {}
+            }
+        }
+        Mouse::Unknown => {}
+    }
+}
+
+// Rows inside the bordered Output area, for paging
+fn page_height(app: &App) -> u16 {
+    app.areas.output.height.saturating_sub(2).max(1)
+}
+
+fn contains(area: Rect, column: u16, row: u16) -> bool {
+    column >= area.x && column < area.right() && row >= area.y && row < area.bottom()
+}
+
+// Handles keys that are not bound to an action on a route that takes text
+pub fn handle_input(key: Key, app: &mut App) {
+    //Set input
+    //Fuzzy match
+    //Set data
+    if let Some(search) = app.reverse_search.as_mut() {
+        match key {
+            Key::Char(c) => search.query.push(c),
+            Key::Backspace => {
+                search.query.pop();
+            }
+            _ => return,
+        }
+        app.update_reverse_search();
+        return;
+    }
+    // Editing the line ends stepping through the history
+    if matches!(key, Key::Char(_) | Key::Backspace) {
+        app.input_history.stop_browsing();
+    }
+    match (app.get_current_route(), key) {
+        (Route::Search, Key::Char(charac)) => {
+            app.active_block = ActiveBlock::Search;
+            app.user_input.push(charac);
+            app.search_and_set_fuzzy_data();
+        }
+        (Route::Search, Key::Backspace) => {
+            app.active_block = ActiveBlock::Search;
+            app.user_input.pop();
+            app.search_after_pop();
+        }
+        (Route::Jq, Key::Char(charac)) => {
+            app.jq_input.push(charac);
+            app.run_jq();
+        }
+        (Route::Jq, Key::Backspace) => {
+            app.jq_input.pop();
+            app.run_jq();
+        }
+        (Route::Edit, Key::Char(charac)) => app.edit_input.push(charac),
+        (Route::Edit, Key::Backspace) => {
+            app.edit_input.pop();
+        }
+        (Route::Command, Key::Char(charac)) => app.command_input.push(charac),
+        (Route::Command, Key::Backspace) => {
+            if app.command_input.pop().is_none() {
+                app.set_current_route(app.previous_route);
+            }
+        }
+        _ => {}
+    }
+}
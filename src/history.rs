@@ -0,0 +1,91 @@
+// Browser-style back/forward history of where the user has been
+
+// Keeps memory bounded when jumping around for a long time
+const MAX_ENTRIES: usize = 200;
+
+/// A place in the document: the path of the level being shown and the row
+/// that was selected there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub path: Vec<String>,
+    pub selected: Option<usize>,
+}
+
+impl Location {
+    pub fn root() -> Location {
+        Location {
+            path: Vec::new(),
+            selected: None,
+        }
+    }
+
+    /// The level as a JSON pointer, e.g. `/items/3`.
+    pub fn pointer(&self) -> String {
+        crate::app::to_pointer(&self.path)
+    }
+}
+
+pub struct NavigationHistory {
+    entries: Vec<Location>,
+    cursor: usize,
+}
+
+impl NavigationHistory {
+    pub fn new() -> NavigationHistory {
+        NavigationHistory {
+            entries: vec![Location::root()],
+            cursor: 0,
+        }
+    }
+
+    pub fn entries(&self) -> &[Location] {
+        &self.entries
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Records a jump from `from` to `to`. Like a browser, anything forward of
+    /// the current entry is dropped.
+    pub fn record(&mut self, from: Location, to: Location) {
+        self.entries.truncate(self.cursor + 1);
+        // Remember where the selection was when we left
+        self.entries[self.cursor] = from;
+        self.entries.push(to);
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.cursor = self.entries.len() - 1;
+    }
+
+    /// Moves one entry back. `current` replaces the entry being left, so coming
+    /// forward again restores the selection too.
+    pub fn back(&mut self, current: Location) -> Option<Location> {
+        if self.cursor == 0 {
+            return None;
+        }
+        self.entries[self.cursor] = current;
+        self.cursor -= 1;
+        Some(self.entries[self.cursor].clone())
+    }
+
+    pub fn forward(&mut self, current: Location) -> Option<Location> {
+        if self.cursor + 1 >= self.entries.len() {
+            return None;
+        }
+        self.entries[self.cursor] = current;
+        self.cursor += 1;
+        Some(self.entries[self.cursor].clone())
+    }
+
+    /// Moves straight to entry `index`, as picked from the history list.
+    pub fn go_to(&mut self, index: usize, current: Location) -> Option<Location> {
+        if index >= self.entries.len() {
+            return None;
+        }
+        self.entries[self.cursor] = current;
+        self.cursor = index;
+        Some(self.entries[index].clone())
+    }
+}
@@ -0,0 +1,114 @@
+use crate::app::Route;
+use crate::write;
+use serde_json::{Map, Value};
+use std::io;
+
+// Lines kept per prompt; older ones are dropped
+const MAX_LINES: usize = 500;
+// Where the history of every prompt is stored, in the state directory
+const STATE_FILE: &str = "history.json";
+
+/// What was typed into the search bar and the prompts, newest last.
+///
+/// Lines are kept per route in a small JSON state file, without duplicates,
+/// so they survive restarts. Up and Down step through them like in a shell.
+pub struct InputHistory {
+    lines: Map<String, Value>,
+    // While stepping through the lines: where we are, and what was typed
+    // before we started, to come back to
+    browsing: Option<(usize, String)>,
+}
+
+/// A Ctrl-r search through the history of one prompt.
+pub struct ReverseSearch {
+    pub query: String,
+    // The line that matches, by index
+    pub found: Option<usize>,
+    // The prompt as it was, restored if the search is cancelled
+    pub original: String,
+}
+
+impl InputHistory {
+    pub fn new() -> InputHistory {
+        InputHistory {
+            lines: Map::new(),
+            browsing: None,
+        }
+    }
+
+    pub fn load() -> InputHistory {
+        InputHistory {
+            lines: write::read_state(STATE_FILE).unwrap_or_default(),
+            browsing: None,
+        }
+    }
+
+    pub fn lines(&self, route: Route) -> Vec<&str> {
+        match self.lines.get(route.name()) {
+            Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Records `line` as the newest of `route`, dropping an earlier copy.
+    /// Lines added by other sessions meanwhile are picked up on the way.
+    pub fn add(&mut self, route: Route, line: &str) -> io::Result<()> {
+        self.browsing = None;
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        if let Some(saved) = write::read_state(STATE_FILE) {
+            self.lines = saved;
+        }
+        let mut lines: Vec<&str> = self.lines(route).into_iter().filter(|l| *l != line).collect();
+        lines.push(line);
+        let skip = lines.len().saturating_sub(MAX_LINES);
+        let lines = lines[skip..].iter().map(|l| Value::from(*l)).collect();
+        self.lines.insert(route.name().to_owned(), Value::Array(lines));
+        self.save()
+    }
+
+    /// The line before the one shown, starting from the newest. `current` is
+    /// what is in the prompt, given back once Down goes past the newest line.
+    pub fn older(&mut self, route: Route, current: &str) -> Option<String> {
+        let lines = self.lines(route);
+        let index = match self.browsing.as_ref() {
+            Some((0, _)) => return None,
+            Some((i, _)) => i - 1,
+            None => lines.len().checked_sub(1)?,
+        };
+        let line = lines[index].to_owned();
+        let typed = self.browsing.take().map_or(current.to_owned(), |(_, typed)| typed);
+        self.browsing = Some((index, typed));
+        Some(line)
+    }
+
+    /// The line after the one shown, or what was typed before browsing.
+    /// `None` when not browsing at all.
+    pub fn newer(&mut self, route: Route) -> Option<String> {
+        let (index, typed) = self.browsing.take()?;
+        let lines = self.lines(route);
+        match lines.get(index + 1) {
+            Some(line) => {
+                let line = line.to_string();
+                self.browsing = Some((index + 1, typed));
+                Some(line)
+            }
+            None => Some(typed),
+        }
+    }
+
+    pub fn stop_browsing(&mut self) {
+        self.browsing = None;
+    }
+
+    /// The newest line before `before` that contains `query`.
+    pub fn find(&self, route: Route, query: &str, before: usize) -> Option<usize> {
+        let lines = self.lines(route);
+        lines[..before.min(lines.len())].iter().rposition(|line| line.contains(query))
+    }
+
+    fn save(&self) -> io::Result<()> {
+        write::write_state(STATE_FILE, &self.lines)
+    }
+}
@@ -0,0 +1,185 @@
+// jq filters over the document, run with jaq
+
+use jaq_core::load::{self, Arena, File, Loader};
+use jaq_core::{Compiler, Ctx, RcIter};
+use jaq_json::Val;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Weak};
+use std::thread;
+
+// Outputs past which a filter is stopped, so `repeat(1)` can't run forever,
+// and the values all its outputs may hold together
+const MAX_OUTPUTS: usize = 10_000;
+const MAX_VALUES: usize = 2_000_000;
+
+// Runs `filter` on `input`. One output is returned as is; no outputs or
+// several are collected into an array, like `jq -n '[...]'` would. Gives up,
+// with no result, once `stale` says a newer filter is waiting.
+fn run(filter: &str, input: Val, stale: &dyn Fn() -> bool) -> Option<Result<Value, String>> {
+    let program = File { code: filter, path: () };
+    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+    let arena = Arena::default();
+    let modules = match loader.load(&arena, program) {
+        Ok(modules) => modules,
+        Err(errors) => return Some(Err(load_error(filter, errors))),
+    };
+    let filter = Compiler::default()
+        .with_funs(jaq_std::funs().chain(jaq_json::funs()))
+        .compile(modules)
+        .map_err(|errors| {
+            let names: Vec<String> = errors
+                .into_iter()
+                .flat_map(|(_, undefined)| undefined)
+                .map(|(name, kind)| format!("undefined {} `{}`", kind.as_str(), name))
+                .collect();
+            names.join(", ")
+        });
+    let filter = match filter {
+        Ok(filter) => filter,
+        Err(e) => return Some(Err(e)),
+    };
+
+    let inputs = RcIter::new(core::iter::empty());
+    let mut outputs = Vec::new();
+    let mut values = 0;
+    for output in filter.run((Ctx::new([], &inputs), input)) {
+        if stale() {
+            return None;
+        }
+        let output = match output {
+            Ok(output) => output,
+            Err(e) => return Some(Err(e.to_string())),
+        };
+        if outputs.len() == MAX_OUTPUTS {
+            return Some(Err(format!("the filter gives more than {} outputs", MAX_OUTPUTS)));
+        }
+        // Counted before converting, which would take as long again
+        values += size(&output, MAX_VALUES - values);
+        if values > MAX_VALUES {
+            return Some(Err(format!("the result holds more than {} values", MAX_VALUES)));
+        }
+        outputs.push(Value::from(output));
+    }
+    Some(Ok(match outputs.len() {
+        1 => outputs.pop().unwrap(),
+        _ => Value::Array(outputs),
+    }))
+}
+
+// How many values `value` is made of, itself included, counting no further
+// than just past `limit`
+fn size(value: &Val, limit: usize) -> usize {
+    let mut count = 1;
+    let children: Box<dyn Iterator<Item = &Val>> = match value {
+        Val::Arr(items) => Box::new(items.iter()),
+        Val::Obj(map) => Box::new(map.values()),
+        _ => return count,
+    };
+    for child in children {
+        if count > limit {
+            break;
+        }
+        count += size(child, limit - count);
+    }
+    count
+}
+
+// The first syntax error, with the column where it was found
+fn load_error(code: &str, errors: load::Errors<&str, ()>) -> String {
+    let column = |at: &str| load::span(code, at).start + 1;
+    match errors.into_iter().next().map(|(_, e)| e) {
+        Some(load::Error::Lex(errors)) => match errors.first() {
+            Some((expected, at)) => format!("column {}: expected {}", column(at), expected.as_str()),
+            None => String::from("invalid filter"),
+        },
+        Some(load::Error::Parse(errors)) => match errors.first() {
+            Some((expected, "")) => format!("expected {} at the end", expected.as_str()),
+            Some((expected, at)) => format!("column {}: expected {}", column(at), expected.as_str()),
+            None => String::from("invalid filter"),
+        },
+        Some(load::Error::Io(errors)) => errors.into_iter().map(|(_, e)| e).collect::<Vec<_>>().join(", "),
+        None => String::from("invalid filter"),
+    }
+}
+
+/// Runs filters on a thread of its own so typing never waits for a slow
+/// one. A filter still running when a newer one is asked for is given up,
+/// and only the result of the newest is reported.
+///
+/// Filters are only given up between outputs: jaq can't be stopped while it
+/// builds one, so a single huge output such as `[range(1e9)]` keeps the
+/// worker busy until it is built, and only then fails the size cap.
+pub struct JqWorker {
+    requests: mpsc::Sender<Request>,
+    results: mpsc::Receiver<(u64, Result<Value, String>)>,
+    latest: Arc<AtomicU64>,
+}
+
+struct Request {
+    generation: u64,
+    filter: String,
+    input: Arc<Value>,
+}
+
+impl JqWorker {
+    pub fn new() -> JqWorker {
+        let (requests, queue) = mpsc::channel::<Request>();
+        let (sender, results) = mpsc::channel();
+        let latest = Arc::new(AtomicU64::new(0));
+        let current = latest.clone();
+        thread::spawn(move || {
+            // The last document converted for jaq. Held weakly, so the app
+            // can still change its document in place.
+            let mut converted: Option<(Weak<Value>, Val)> = None;
+            while let Ok(mut request) = queue.recv() {
+                // Only the newest filter is worth running
+                while let Ok(newer) = queue.try_recv() {
+                    request = newer;
+                }
+                let input = match converted.as_ref() {
+                    Some((json, input)) if Weak::ptr_eq(json, &Arc::downgrade(&request.input)) => input.clone(),
+                    _ => {
+                        let input = Val::from((*request.input).clone());
+                        converted = Some((Arc::downgrade(&request.input), input.clone()));
+                        input
+                    }
+                };
+                drop(request.input);
+                let generation = request.generation;
+                let stale = || current.load(Ordering::SeqCst) != generation;
+                if let Some(result) = run(&request.filter, input, &stale) {
+                    if sender.send((generation, result)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        JqWorker {
+            requests,
+            results,
+            latest,
+        }
+    }
+
+    pub fn run(&mut self, filter: &str, input: Arc<Value>) {
+        let generation = self.latest.fetch_add(1, Ordering::SeqCst) + 1;
+        let _ = self.requests.send(Request {
+            generation,
+            filter: filter.to_owned(),
+            input,
+        });
+    }
+
+    /// The newest finished result, if the newest filter has finished.
+    pub fn try_recv(&self) -> Option<Result<Value, String>> {
+        let latest = self.latest.load(Ordering::SeqCst);
+        let mut newest = None;
+        while let Ok((generation, result)) = self.results.try_recv() {
+            if generation == latest {
+                newest = Some(result);
+            }
+        }
+        newest
+    }
+}
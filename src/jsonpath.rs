@@ -0,0 +1,20 @@
+// JSONPath (RFC 9535) queries, as API gateways and other tools write them
+
+use serde_json::Value;
+use serde_json_path::{JsonPath, PathElement};
+
+pub fn parse(expression: &str) -> Result<JsonPath, String> {
+    JsonPath::parse(expression).map_err(|e| format!("column {}: {}", e.position(), e.message()))
+}
+
+/// The path of every node `path` selects, with its normalized path
+/// (e.g. `$['items'][0]['id']`), in the order the query gives them.
+pub fn locate(path: &JsonPath, json: &Value) -> Vec<(Vec<String>, String)> {
+    path.query_located(json)
+        .iter()
+        .map(|node| {
+            let location = node.location();
+            (location.iter().map(PathElement::to_string).collect(), location.to_string())
+        })
+        .collect()
+}
@@ -0,0 +1,645 @@
+use crate::app::Route;
+use crate::event::Key;
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+/// Something the user can ask the app to do. Keys are never matched directly
+/// by the handlers; they are looked up in the active `KeyMap` for the current
+/// route and turned into one of these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    NextItem,
+    PreviousItem,
+    DrillIn,
+    GoBack,
+    StartSearch,
+    ExitSearch,
+    ToggleHelp,
+    ToggleMouse,
+    HistoryBack,
+    HistoryForward,
+    ShowHistory,
+    StartCommand,
+    Confirm,
+    Cancel,
+    SetMark,
+    JumpToMark,
+    ShowMarks,
+    Reload,
+    Delete,
+    PageDown,
+    PageUp,
+    TogglePreview,
+    CycleSearchMode,
+    CycleSearchCase,
+    CycleSearchScope,
+    ToggleSearchSubtree,
+    StartJq,
+    SearchInputHistory,
+    ShowQueries,
+    EditValue,
+    DeleteItem,
+    RenameKey,
+    InsertAfter,
+    InsertBefore,
+    MoveUp,
+    MoveDown,
+    Duplicate,
+    Undo,
+    Redo,
+    ShowLogs,
+    CycleLevel,
+    ShowInTree,
+    AcceptPatch,
+    DiscardPatch,
+    NextChange,
+    PreviousChange,
+    ToggleUnchanged,
+}
+
+impl Action {
+    pub const ALL: &'static [Action] = &[
+        Action::Quit,
+        Action::NextItem,
+        Action::PreviousItem,
+        Action::DrillIn,
+        Action::GoBack,
+        Action::StartSearch,
+        Action::ExitSearch,
+        Action::ToggleHelp,
+        Action::ToggleMouse,
+        Action::HistoryBack,
+        Action::HistoryForward,
+        Action::ShowHistory,
+        Action::StartCommand,
+        Action::Confirm,
+        Action::Cancel,
+        Action::SetMark,
+        Action::JumpToMark,
+        Action::ShowMarks,
+        Action::Reload,
+        Action::Delete,
+        Action::PageDown,
+        Action::PageUp,
+        Action::TogglePreview,
+        Action::CycleSearchMode,
+        Action::CycleSearchCase,
+        Action::CycleSearchScope,
+        Action::ToggleSearchSubtree,
+        Action::StartJq,
+        Action::SearchInputHistory,
+        Action::ShowQueries,
+        Action::EditValue,
+        Action::DeleteItem,
+        Action::RenameKey,
+        Action::InsertAfter,
+        Action::InsertBefore,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Duplicate,
+        Action::Undo,
+        Action::Redo,
+        Action::ShowLogs,
+        Action::CycleLevel,
+        Action::ShowInTree,
+        Action::AcceptPatch,
+        Action::DiscardPatch,
+        Action::NextChange,
+        Action::PreviousChange,
+        Action::ToggleUnchanged,
+    ];
+
+    /// The name used for the action in the keymap file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::NextItem => "next_item",
+            Action::PreviousItem => "previous_item",
+            Action::DrillIn => "drill_in",
+            Action::GoBack => "go_back",
+            Action::StartSearch => "start_search",
+            Action::ExitSearch => "exit_search",
+            Action::ToggleHelp => "toggle_help",
+            Action::ToggleMouse => "toggle_mouse",
+            Action::HistoryBack => "history_back",
+            Action::HistoryForward => "history_forward",
+            Action::ShowHistory => "show_history",
+            Action::StartCommand => "start_command",
+            Action::Confirm => "confirm",
+            Action::Cancel => "cancel",
+            Action::SetMark => "set_mark",
+            Action::JumpToMark => "jump_to_mark",
+            Action::ShowMarks => "show_marks",
+            Action::Reload => "reload",
+            Action::Delete => "delete",
+            Action::PageDown => "page_down",
+            Action::PageUp => "page_up",
+            Action::TogglePreview => "toggle_preview",
+            Action::CycleSearchMode => "search_mode",
+            Action::CycleSearchCase => "search_case",
+            Action::CycleSearchScope => "search_scope",
+            Action::ToggleSearchSubtree => "search_subtree",
+            Action::StartJq => "start_jq",
+            Action::SearchInputHistory => "search_input_history",
+            Action::ShowQueries => "show_queries",
+            Action::EditValue => "edit_value",
+            Action::DeleteItem => "delete_item",
+            Action::RenameKey => "rename_key",
+            Action::InsertAfter => "insert_after",
+            Action::InsertBefore => "insert_before",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::Duplicate => "duplicate",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::ShowLogs => "show_logs",
+            Action::CycleLevel => "cycle_level",
+            Action::ShowInTree => "show_in_tree",
+            Action::AcceptPatch => "accept_patch",
+            Action::DiscardPatch => "discard_patch",
+            Action::NextChange => "next_change",
+            Action::PreviousChange => "previous_change",
+            Action::ToggleUnchanged => "toggle_unchanged",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit json_tui",
+            Action::NextItem => "Move the selection down",
+            Action::PreviousItem => "Move the selection up",
+            Action::DrillIn => "Enter the selected object or array, or show a value in full",
+            Action::GoBack => "Go up one level",
+            Action::StartSearch => "Search the whole document",
+            Action::ExitSearch => "Clear the search and return to the tree",
+            Action::ToggleHelp => "Show or hide this help",
+            Action::ToggleMouse => "Release the mouse for text selection, or take it back",
+            Action::HistoryBack => "Go back to the previous location",
+            Action::HistoryForward => "Go forward again after going back",
+            Action::ShowHistory => "List every visited location",
+            Action::StartCommand => "Open the command line (goto <path>, w [<file>], export <file>, sql <query>, query <name>, level, target, group, stitch <items>, patch <file>)",
+            Action::Confirm => "Run the prompt or open the highlighted entry",
+            Action::Cancel => "Close the prompt or popup",
+            Action::SetMark => "Set a mark on the highlighted node (then a letter)",
+            Action::JumpToMark => "Jump to a mark (then its letter)",
+            Action::ShowMarks => "List all marks with their current values",
+            Action::Reload => "Read the file again, keeping marks that still exist",
+            Action::Delete => "Delete the highlighted entry",
+            Action::PageDown => "Move or scroll one page down",
+            Action::PageUp => "Move or scroll one page up",
+            Action::TogglePreview => "Show the highlighted subtree instead of the raw input",
+            Action::CycleSearchMode => "Switch between fuzzy, substring, regex and JSONPath search",
+            Action::CycleSearchCase => "Switch between smart case, case sensitive and ignoring case",
+            Action::CycleSearchScope => "Search keys and values, only keys or only values",
+            Action::ToggleSearchSubtree => "Search only below the current level, or everywhere",
+            Action::StartJq => "Filter the document with jq; an empty filter shows the original",
+            Action::SearchInputHistory => "Search what was typed here before, like Ctrl-r in a shell",
+            Action::ShowQueries => "List the queries saved in the project's .json_tui.toml",
+            Action::EditValue => "Edit the highlighted string, number, boolean or null as JSON",
+            Action::DeleteItem => "Delete the highlighted key or item",
+            Action::RenameKey => "Rename the highlighted key, keeping its position",
+            Action::InsertAfter => "Add a key or item after the highlighted one",
+            Action::InsertBefore => "Add a key or item before the highlighted one",
+            Action::MoveUp => "Move the highlighted key or item up",
+            Action::MoveDown => "Move the highlighted key or item down",
+            Action::Duplicate => "Put a copy of the highlighted key or item after it",
+            Action::Undo => "Undo the last change to the document",
+            Action::Redo => "Redo the last change that was undone",
+            Action::ShowLogs => "Show an array of tracing JSON records as a log view",
+            Action::CycleLevel => "Show only records of the next stricter level and above",
+            Action::ShowInTree => "Leave the log view on the highlighted record in the tree",
+            Action::AcceptPatch => "Apply the patch being previewed to the document",
+            Action::DiscardPatch => "Drop the patch being previewed",
+            Action::NextChange => "Go to the next difference in a comparison or patch preview",
+            Action::PreviousChange => "Go to the previous difference",
+            Action::ToggleUnchanged => "Hide or show what two compared files have in common",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|a| a.name() == name)
+    }
+}
+
+type Preset = &'static [(Route, Action, &'static [&'static str])];
+
+const DEFAULT_PRESET: Preset = &[
+    (Route::Main, Action::Quit, &["ctrl-c"]),
+    (Route::Main, Action::NextItem, &["down"]),
+    (Route::Main, Action::PreviousItem, &["up"]),
+    (Route::Main, Action::DrillIn, &["enter"]),
+    (Route::Main, Action::GoBack, &["esc"]),
+    (Route::Main, Action::StartSearch, &["/"]),
+    (Route::Main, Action::ToggleHelp, &["?", "f1"]),
+    (Route::Main, Action::ToggleMouse, &["f2"]),
+    // Ctrl-i arrives as Tab in most terminals
+    (Route::Main, Action::HistoryBack, &["alt-left", "ctrl-o"]),
+    (Route::Main, Action::HistoryForward, &["alt-right", "tab"]),
+    (Route::Main, Action::ShowHistory, &["H"]),
+    (Route::Main, Action::StartCommand, &[":"]),
+    (Route::Main, Action::SetMark, &["m"]),
+    (Route::Main, Action::JumpToMark, &["'"]),
+    (Route::Main, Action::ShowMarks, &["M"]),
+    (Route::Main, Action::Reload, &["R"]),
+    (Route::Main, Action::TogglePreview, &["p"]),
+    (Route::Main, Action::StartJq, &["|"]),
+    (Route::Main, Action::ShowQueries, &["Q"]),
+    (Route::Main, Action::EditValue, &["e"]),
+    (Route::Main, Action::DeleteItem, &["x"]),
+    (Route::Main, Action::RenameKey, &["r"]),
+    (Route::Main, Action::InsertAfter, &["o"]),
+    (Route::Main, Action::InsertBefore, &["O"]),
+    (Route::Main, Action::MoveUp, &["K"]),
+    (Route::Main, Action::MoveDown, &["J"]),
+    (Route::Main, Action::Duplicate, &["c"]),
+    (Route::Main, Action::Undo, &["u"]),
+    (Route::Main, Action::Redo, &["ctrl-r"]),
+    (Route::Main, Action::ShowLogs, &["L"]),
+    (Route::Main, Action::AcceptPatch, &["A"]),
+    (Route::Main, Action::DiscardPatch, &["D"]),
+    (Route::Main, Action::NextChange, &["n"]),
+    (Route::Main, Action::PreviousChange, &["N"]),
+    (Route::Main, Action::ToggleUnchanged, &["z"]),
+    (Route::Search, Action::Quit, &["ctrl-c"]),
+    (Route::Search, Action::NextItem, &["down"]),
+    (Route::Search, Action::PreviousItem, &["up"]),
+    (Route::Search, Action::ExitSearch, &["ctrl-q"]),
+    (Route::Search, Action::ToggleHelp, &["f1"]),
+    (Route::Search, Action::ToggleMouse, &["f2"]),
+    (Route::Search, Action::Confirm, &["enter"]),
+    (Route::Search, Action::CycleSearchMode, &["alt-m"]),
+    (Route::Search, Action::CycleSearchCase, &["alt-c"]),
+    (Route::Search, Action::CycleSearchScope, &["alt-k"]),
+    (Route::Search, Action::ToggleSearchSubtree, &["alt-s"]),
+    (Route::Search, Action::SearchInputHistory, &["ctrl-r"]),
+    (Route::Help, Action::Quit, &["ctrl-c"]),
+    (Route::Help, Action::NextItem, &["down"]),
+    (Route::Help, Action::PreviousItem, &["up"]),
+    (Route::Help, Action::ToggleHelp, &["?", "f1", "esc", "q"]),
+    (Route::Command, Action::Quit, &["ctrl-c"]),
+    (Route::Command, Action::Confirm, &["enter"]),
+    (Route::Command, Action::Cancel, &["esc", "ctrl-q"]),
+    (Route::Command, Action::NextItem, &["down"]),
+    (Route::Command, Action::PreviousItem, &["up"]),
+    (Route::Command, Action::SearchInputHistory, &["ctrl-r"]),
+    (Route::Jq, Action::Quit, &["ctrl-c"]),
+    (Route::Jq, Action::Confirm, &["enter"]),
+    (Route::Jq, Action::Cancel, &["esc", "ctrl-q"]),
+    (Route::Jq, Action::NextItem, &["down"]),
+    (Route::Jq, Action::PreviousItem, &["up"]),
+    (Route::Jq, Action::SearchInputHistory, &["ctrl-r"]),
+    (Route::Logs, Action::Quit, &["ctrl-c"]),
+    (Route::Logs, Action::NextItem, &["down"]),
+    (Route::Logs, Action::PreviousItem, &["up"]),
+    (Route::Logs, Action::Confirm, &["enter"]),
+    (Route::Logs, Action::Cancel, &["esc", "L"]),
+    (Route::Logs, Action::CycleLevel, &["v"]),
+    (Route::Logs, Action::ShowInTree, &["t"]),
+    (Route::Logs, Action::StartCommand, &[":"]),
+    (Route::Logs, Action::ToggleHelp, &["?", "f1"]),
+    (Route::Confirm, Action::Quit, &["ctrl-c"]),
+    (Route::Confirm, Action::Confirm, &["y", "enter"]),
+    (Route::Confirm, Action::Cancel, &["n", "esc"]),
+    (Route::Edit, Action::Quit, &["ctrl-c"]),
+    (Route::Edit, Action::Confirm, &["enter"]),
+    (Route::Edit, Action::Cancel, &["esc", "ctrl-q"]),
+    (Route::History, Action::Quit, &["ctrl-c"]),
+    (Route::History, Action::NextItem, &["down"]),
+    (Route::History, Action::PreviousItem, &["up"]),
+    (Route::History, Action::Confirm, &["enter"]),
+    (Route::History, Action::Cancel, &["esc", "q", "H"]),
+    (Route::Marks, Action::Quit, &["ctrl-c"]),
+    (Route::Marks, Action::NextItem, &["down"]),
+    (Route::Marks, Action::PreviousItem, &["up"]),
+    (Route::Marks, Action::Confirm, &["enter"]),
+    (Route::Marks, Action::Delete, &["d", "delete"]),
+    (Route::Marks, Action::Cancel, &["esc", "q", "M"]),
+    (Route::Queries, Action::Quit, &["ctrl-c"]),
+    (Route::Queries, Action::NextItem, &["down"]),
+    (Route::Queries, Action::PreviousItem, &["up"]),
+    (Route::Queries, Action::Confirm, &["enter"]),
+    (Route::Queries, Action::Cancel, &["esc", "q", "Q"]),
+    (Route::Detail, Action::Quit, &["ctrl-c"]),
+    (Route::Detail, Action::NextItem, &["down"]),
+    (Route::Detail, Action::PreviousItem, &["up"]),
+    (Route::Detail, Action::PageDown, &["pagedown", "space"]),
+    (Route::Detail, Action::PageUp, &["pageup"]),
+    (Route::Detail, Action::Cancel, &["esc", "q", "enter"]),
+];
+
+const EMACS_PRESET: Preset = &[
+    (Route::Main, Action::Quit, &["ctrl-c"]),
+    (Route::Main, Action::NextItem, &["ctrl-n", "down"]),
+    (Route::Main, Action::PreviousItem, &["ctrl-p", "up"]),
+    (Route::Main, Action::DrillIn, &["ctrl-f", "right", "enter"]),
+    (Route::Main, Action::GoBack, &["ctrl-b", "left", "esc"]),
+    (Route::Main, Action::StartSearch, &["ctrl-s", "/"]),
+    (Route::Main, Action::ToggleHelp, &["?", "f1"]),
+    (Route::Main, Action::ToggleMouse, &["f2"]),
+    (Route::Main, Action::HistoryBack, &["alt-left", "ctrl-o"]),
+    (Route::Main, Action::HistoryForward, &["alt-right", "tab"]),
+    (Route::Main, Action::ShowHistory, &["H"]),
+    (Route::Main, Action::StartCommand, &["alt-x", ":"]),
+    (Route::Main, Action::SetMark, &["m"]),
+    (Route::Main, Action::JumpToMark, &["'"]),
+    (Route::Main, Action::ShowMarks, &["M"]),
+    (Route::Main, Action::Reload, &["R"]),
+    (Route::Main, Action::TogglePreview, &["p"]),
+    (Route::Main, Action::StartJq, &["|"]),
+    (Route::Main, Action::ShowQueries, &["Q"]),
+    (Route::Main, Action::EditValue, &["e"]),
+    (Route::Main, Action::DeleteItem, &["x"]),
+    (Route::Main, Action::RenameKey, &["r"]),
+    (Route::Main, Action::InsertAfter, &["o"]),
+    (Route::Main, Action::InsertBefore, &["O"]),
+    (Route::Main, Action::MoveUp, &["K"]),
+    (Route::Main, Action::MoveDown, &["J"]),
+    (Route::Main, Action::Duplicate, &["c"]),
+    (Route::Main, Action::Undo, &["u"]),
+    (Route::Main, Action::Redo, &["ctrl-r"]),
+    (Route::Main, Action::ShowLogs, &["L"]),
+    (Route::Main, Action::AcceptPatch, &["A"]),
+    (Route::Main, Action::DiscardPatch, &["D"]),
+    (Route::Main, Action::NextChange, &["n"]),
+    (Route::Main, Action::PreviousChange, &["N"]),
+    (Route::Main, Action::ToggleUnchanged, &["z"]),
+    (Route::Search, Action::Quit, &["ctrl-c"]),
+    (Route::Search, Action::NextItem, &["ctrl-n", "down"]),
+    (Route::Search, Action::PreviousItem, &["ctrl-p", "up"]),
+    (Route::Search, Action::ExitSearch, &["ctrl-g", "ctrl-q"]),
+    (Route::Search, Action::ToggleHelp, &["f1"]),
+    (Route::Search, Action::ToggleMouse, &["f2"]),
+    (Route::Search, Action::Confirm, &["enter"]),
+    (Route::Search, Action::CycleSearchMode, &["alt-m"]),
+    (Route::Search, Action::CycleSearchCase, &["alt-c"]),
+    (Route::Search, Action::CycleSearchScope, &["alt-k"]),
+    (Route::Search, Action::ToggleSearchSubtree, &["alt-s"]),
+    (Route::Search, Action::SearchInputHistory, &["ctrl-r"]),
+    (Route::Help, Action::Quit, &["ctrl-c"]),
+    (Route::Help, Action::NextItem, &["ctrl-n", "down"]),
+    (Route::Help, Action::PreviousItem, &["ctrl-p", "up"]),
+    (Route::Help, Action::ToggleHelp, &["ctrl-g", "?", "f1", "esc", "q"]),
+    (Route::Command, Action::Quit, &["ctrl-c"]),
+    (Route::Command, Action::Confirm, &["enter"]),
+    (Route::Command, Action::Cancel, &["ctrl-g", "esc"]),
+    (Route::Command, Action::NextItem, &["ctrl-n", "down"]),
+    (Route::Command, Action::PreviousItem, &["ctrl-p", "up"]),
+    (Route::Command, Action::SearchInputHistory, &["ctrl-r"]),
+    (Route::Jq, Action::Quit, &["ctrl-c"]),
+    (Route::Jq, Action::Confirm, &["enter"]),
+    (Route::Jq, Action::Cancel, &["ctrl-g", "esc"]),
+    (Route::Jq, Action::NextItem, &["ctrl-n", "down"]),
+    (Route::Jq, Action::PreviousItem, &["ctrl-p", "up"]),
+    (Route::Jq, Action::SearchInputHistory, &["ctrl-r"]),
+    (Route::Logs, Action::Quit, &["ctrl-c"]),
+    (Route::Logs, Action::NextItem, &["ctrl-n", "down"]),
+    (Route::Logs, Action::PreviousItem, &["ctrl-p", "up"]),
+    (Route::Logs, Action::Confirm, &["enter"]),
+    (Route::Logs, Action::Cancel, &["ctrl-g", "esc", "L"]),
+    (Route::Logs, Action::CycleLevel, &["v"]),
+    (Route::Logs, Action::ShowInTree, &["t"]),
+    (Route::Logs, Action::StartCommand, &["alt-x", ":"]),
+    (Route::Logs, Action::ToggleHelp, &["?", "f1"]),
+    (Route::Confirm, Action::Quit, &["ctrl-c"]),
+    (Route::Confirm, Action::Confirm, &["y", "enter"]),
+    (Route::Confirm, Action::Cancel, &["n", "esc"]),
+    (Route::Edit, Action::Quit, &["ctrl-c"]),
+    (Route::Edit, Action::Confirm, &["enter"]),
+    (Route::Edit, Action::Cancel, &["ctrl-g", "esc"]),
+    (Route::History, Action::Quit, &["ctrl-c"]),
+    (Route::History, Action::NextItem, &["ctrl-n", "down"]),
+    (Route::History, Action::PreviousItem, &["ctrl-p", "up"]),
+    (Route::History, Action::Confirm, &["enter"]),
+    (Route::History, Action::Cancel, &["ctrl-g", "esc", "q"]),
+    (Route::Marks, Action::Quit, &["ctrl-c"]),
+    (Route::Marks, Action::NextItem, &["ctrl-n", "down"]),
+    (Route::Marks, Action::PreviousItem, &["ctrl-p", "up"]),
+    (Route::Marks, Action::Confirm, &["enter"]),
+    (Route::Marks, Action::Delete, &["ctrl-d", "delete"]),
+    (Route::Marks, Action::Cancel, &["ctrl-g", "esc", "q"]),
+    (Route::Queries, Action::Quit, &["ctrl-c"]),
+    (Route::Queries, Action::NextItem, &["ctrl-n", "down"]),
+    (Route::Queries, Action::PreviousItem, &["ctrl-p", "up"]),
+    (Route::Queries, Action::Confirm, &["enter"]),
+    (Route::Queries, Action::Cancel, &["ctrl-g", "esc", "q"]),
+    (Route::Detail, Action::Quit, &["ctrl-c"]),
+    (Route::Detail, Action::NextItem, &["ctrl-n", "down"]),
+    (Route::Detail, Action::PreviousItem, &["ctrl-p", "up"]),
+    (Route::Detail, Action::PageDown, &["ctrl-v", "pagedown"]),
+    (Route::Detail, Action::PageUp, &["alt-v", "pageup"]),
+    (Route::Detail, Action::Cancel, &["ctrl-g", "esc", "q"]),
+];
+
+/// Maps keys to actions, separately for every route.
+///
+/// The bindings start from a preset (`default` or `emacs`) and are then
+/// overridden per action by the user's keymap file, e.g.
+///
+/// ```toml
+/// preset = "emacs"
+///
+/// [main]
+/// next_item = ["j", "down"]
+/// quit = "q"
+/// ```
+pub struct KeyMap {
+    bindings: HashMap<Route, HashMap<Key, Action>>,
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        KeyMap::from_preset(DEFAULT_PRESET)
+    }
+}
+
+impl KeyMap {
+    fn from_preset(preset: Preset) -> KeyMap {
+        let mut bindings: HashMap<Route, HashMap<Key, Action>> = HashMap::new();
+        for (route, action, keys) in preset {
+            let route_bindings = bindings.entry(*route).or_default();
+            for key in keys.iter() {
+                // Presets are written by hand and are known to parse.
+                route_bindings.insert(key.parse().unwrap(), *action);
+            }
+        }
+        KeyMap { bindings }
+    }
+
+    /// Where the keymap file is read from: `$JSON_TUI_KEYMAP` if set, else
+    /// `keymap.toml` in the platform config directory.
+    pub fn config_path() -> Option<PathBuf> {
+        match env::var_os("JSON_TUI_KEYMAP") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => dirs::config_dir().map(|dir| dir.join("json_tui").join("keymap.toml")),
+        }
+    }
+
+    /// Loads the user's keymap. A missing file is not an error. Anything wrong
+    /// with the file (bad keys, unknown actions, conflicting bindings) is
+    /// returned as a list of problems instead of aborting, so the app can still
+    /// start and report them.
+    pub fn load() -> (KeyMap, Vec<String>) {
+        let path = match KeyMap::config_path() {
+            Some(path) if path.exists() => path,
+            _ => return (KeyMap::default(), Vec::new()),
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => KeyMap::from_toml(&contents),
+            Err(e) => (
+                KeyMap::default(),
+                vec![format!("could not read {}: {}", path.display(), e)],
+            ),
+        }
+    }
+
+    pub fn from_toml(contents: &str) -> (KeyMap, Vec<String>) {
+        let mut problems = Vec::new();
+        let table: toml::Table = match contents.parse() {
+            Ok(table) => table,
+            Err(e) => {
+                problems.push(format!("keymap: {}", e));
+                return (KeyMap::default(), problems);
+            }
+        };
+
+        let preset = match table.get("preset").map(|p| p.as_str()) {
+            None | Some(Some("default")) => DEFAULT_PRESET,
+            Some(Some("emacs")) => EMACS_PRESET,
+            Some(other) => {
+                problems.push(format!("keymap: unknown preset {:?}", other.unwrap_or("?")));
+                DEFAULT_PRESET
+            }
+        };
+        let mut keymap = KeyMap::from_preset(preset);
+
+        for (section, value) in table.iter() {
+            if section == "preset" {
+                continue;
+            }
+            let route = match Route::ALL.iter().find(|r| r.name() == section) {
+                Some(route) => *route,
+                None => {
+                    problems.push(format!("keymap: unknown section [{}]", section));
+                    continue;
+                }
+            };
+            let overrides = match value.as_table() {
+                Some(t) => t,
+                None => {
+                    problems.push(format!("keymap: [{}] must be a table", section));
+                    continue;
+                }
+            };
+            keymap.apply_overrides(route, overrides, &mut problems);
+        }
+
+        (keymap, problems)
+    }
+
+    fn apply_overrides(&mut self, route: Route, overrides: &toml::Table, problems: &mut Vec<String>) {
+        let mut user: Vec<(Key, Action)> = Vec::new();
+        for (name, keys) in overrides.iter() {
+            let action = match Action::from_name(name) {
+                Some(action) => action,
+                None => {
+                    problems.push(format!("keymap: [{}] unknown action `{}`", route.name(), name));
+                    continue;
+                }
+            };
+            let keys: Vec<&str> = match keys {
+                toml::Value::String(k) => vec![k.as_str()],
+                toml::Value::Array(ks) => ks.iter().filter_map(|k| k.as_str()).collect(),
+                _ => {
+                    problems.push(format!("keymap: [{}] {} must be a key or a list of keys", route.name(), name));
+                    continue;
+                }
+            };
+            for key in keys {
+                match key.parse::<Key>() {
+                    Ok(key) => user.push((key, action)),
+                    Err(e) => problems.push(format!("keymap: [{}] {}: {}", route.name(), name, e)),
+                }
+            }
+        }
+
+        let route_bindings = self.bindings.entry(route).or_default();
+        // An action mentioned in the file loses all of its preset keys.
+        route_bindings.retain(|_, action| !user.iter().any(|(_, a)| a == action));
+
+        let mut from_file: HashMap<Key, Action> = HashMap::new();
+        for (key, action) in user {
+            if let Some(existing) = from_file.get(&key) {
+                if *existing != action {
+                    problems.push(format!(
+                        "keymap: [{}] {} is bound to both {} and {}; keeping {}",
+                        route.name(),
+                        key,
+                        existing.name(),
+                        action.name(),
+                        existing.name()
+                    ));
+                }
+                continue;
+            }
+            if let Some(previous) = route_bindings.get(&key) {
+                problems.push(format!(
+                    "keymap: [{}] {} overrides the preset binding for {}",
+                    route.name(),
+                    key,
+                    previous.name()
+                ));
+            }
+            if route.takes_text() {
+                if let Key::Char(_) = key {
+                    problems.push(format!(
+                        "keymap: [{}] {} shadows typing it into the prompt",
+                        route.name(),
+                        key
+                    ));
+                }
+            }
+            from_file.insert(key, action);
+            route_bindings.insert(key, action);
+        }
+    }
+
+    pub fn action(&self, route: Route, key: Key) -> Option<Action> {
+        self.bindings.get(&route).and_then(|b| b.get(&key)).copied()
+    }
+
+    /// The keys bound to `action` in `route`, in a stable order.
+    pub fn keys_for(&self, route: Route, action: Action) -> Vec<Key> {
+        let mut keys: Vec<Key> = self
+            .bindings
+            .get(&route)
+            .map(|b| b.iter().filter(|(_, a)| **a == action).map(|(k, _)| *k).collect())
+            .unwrap_or_default();
+        keys.sort_by_key(|k| k.to_string());
+        keys
+    }
+
+    /// Every bound action of `route` with its keys, in `Action::ALL` order.
+    /// This is what the help screen is generated from.
+    pub fn bindings(&self, route: Route) -> Vec<(Action, Vec<Key>)> {
+        Action::ALL
+            .iter()
+            .map(|a| (*a, self.keys_for(route, *a)))
+            .filter(|(_, keys)| !keys.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn action_names_are_unique() {
+        let mut names = HashSet::new();
+        for action in Action::ALL {
+            assert!(names.insert(action.name()), "`{}` names two actions", action.name());
+            assert_eq!(Action::from_name(action.name()), Some(*action));
+        }
+    }
+}
@@ -0,0 +1,262 @@
+// A log view of the records `tracing-subscriber`'s JSON formatter writes,
+// one object per line:
+//
+// {"timestamp":"2024-05-01T12:00:01.123Z","level":"INFO","target":"api::http",
+//  "fields":{"message":"request done","status":200},
+//  "spans":[{"name":"request","request_id":"7f3a"}]}
+
+use crate::app::scroll_to;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tui::widgets::ListState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub const ALL: [Level; 5] = [Level::Trace, Level::Debug, Level::Info, Level::Warn, Level::Error];
+
+    pub fn parse(name: &str) -> Option<Level> {
+        Level::ALL.iter().copied().find(|l| l.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+
+    // The next stricter level, wrapping around to TRACE after ERROR
+    pub fn next(self) -> Level {
+        Level::ALL[(self as usize + 1) % Level::ALL.len()]
+    }
+}
+
+/// A span a record was logged in, with its fields written out.
+pub struct SpanContext {
+    pub name: String,
+    pub fields: Vec<(String, String)>,
+}
+
+pub struct Record {
+    // Position in the array of records, for going to it in the tree
+    pub index: usize,
+    pub timestamp: String,
+    pub level: Option<Level>,
+    pub target: String,
+    pub message: String,
+    // Fields other than the message
+    pub fields: Vec<(String, String)>,
+    // Outermost first
+    pub spans: Vec<SpanContext>,
+}
+
+impl Record {
+    fn from_value(index: usize, value: &Value) -> Record {
+        let text = |key: &str| value.get(key).map(display).unwrap_or_default();
+        let mut fields = members(value.get("fields"));
+        let message = match fields.iter().position(|(k, _)| k == "message") {
+            Some(i) => fields.remove(i).1,
+            None => String::new(),
+        };
+        let spans = match value.get("spans") {
+            Some(Value::Array(spans)) => spans.iter().map(span_context).collect(),
+            // Without the span list there is at most the current span
+            _ => value.get("span").map(span_context).into_iter().collect(),
+        };
+        Record {
+            index,
+            timestamp: text("timestamp"),
+            level: value.get("level").and_then(Value::as_str).and_then(Level::parse),
+            target: text("target"),
+            message,
+            fields,
+            spans,
+        }
+    }
+
+    /// The value of `name` in the innermost span that has it, or else in the
+    /// record's own fields.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.spans
+            .iter()
+            .rev()
+            .flat_map(|span| span.fields.iter())
+            .chain(self.fields.iter())
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn span_context(span: &Value) -> SpanContext {
+    let mut fields = members(Some(span));
+    let name = match fields.iter().position(|(k, _)| k == "name") {
+        Some(i) => fields.remove(i).1,
+        None => String::from("?"),
+    };
+    SpanContext { name, fields }
+}
+
+fn members(object: Option<&Value>) -> Vec<(String, String)> {
+    match object {
+        Some(Value::Object(map)) => map.iter().map(|(k, v)| (k.clone(), display(v))).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Strings without their quotes, anything else as JSON
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Whether `value` is an array of tracing records, judged by its first few.
+pub fn looks_like_logs(value: &Value) -> bool {
+    match value {
+        Value::Array(items) if !items.is_empty() => items.iter().take(10).all(|item| {
+            item.get("level").map_or(false, Value::is_string)
+                && (item.get("fields").map_or(false, Value::is_object) || item.get("timestamp").is_some())
+        }),
+        _ => false,
+    }
+}
+
+/// A row of the log view.
+pub enum Row {
+    // The start of a group: the value of the field, and how many records
+    Group(Option<String>, usize),
+    // A record, by index into `LogView::records`
+    Record(usize),
+}
+
+/// The records of one array in the document, with the filters applied.
+pub struct LogView {
+    pub path: Vec<String>,
+    pub records: Vec<Record>,
+    pub min_level: Level,
+    // Only records whose target starts with this, as in `RUST_LOG`
+    pub target: Option<String>,
+    pub group_by: Option<String>,
+    pub rows: Vec<Row>,
+    pub shown: usize,
+    pub list: ListState,
+    // First visible row; only the rows on screen are drawn
+    pub offset: usize,
+    // Records whose span context is written out in full
+    pub expanded: HashSet<usize>,
+}
+
+impl LogView {
+    pub fn new(path: Vec<String>, records: &[Value]) -> LogView {
+        let mut view = LogView {
+            path,
+            records: records.iter().enumerate().map(|(i, v)| Record::from_value(i, v)).collect(),
+            min_level: Level::Trace,
+            target: None,
+            group_by: None,
+            rows: Vec::new(),
+            shown: 0,
+            list: ListState::default(),
+            offset: 0,
+            expanded: HashSet::new(),
+        };
+        view.refresh();
+        view
+    }
+
+    /// Builds the rows again after a filter changed, keeping the highlighted
+    /// record if it is still shown.
+    pub fn refresh(&mut self) {
+        let selected = self.selected().unwrap_or(0);
+        let shown: Vec<usize> = (0..self.records.len())
+            .filter(|i| {
+                let record = &self.records[*i];
+                record.level.map_or(true, |l| l >= self.min_level)
+                    && self.target.as_ref().map_or(true, |t| record.target.starts_with(t.as_str()))
+            })
+            .collect();
+        self.shown = shown.len();
+        self.rows = match self.group_by.as_ref() {
+            None => shown.into_iter().map(Row::Record).collect(),
+            Some(field) => {
+                // Groups in the order they first appear, records without the
+                // field last
+                let mut order: Vec<Option<&str>> = Vec::new();
+                let mut groups: HashMap<Option<&str>, Vec<usize>> = HashMap::new();
+                for i in shown {
+                    let key = self.records[i].field(field);
+                    groups.entry(key).or_insert_with(|| {
+                        order.push(key);
+                        Vec::new()
+                    });
+                    groups.get_mut(&key).unwrap().push(i);
+                }
+                order.sort_by_key(|key| key.is_none());
+                let mut rows = Vec::new();
+                for key in order {
+                    let records = &groups[&key];
+                    rows.push(Row::Group(key.map(str::to_owned), records.len()));
+                    rows.extend(records.iter().copied().map(Row::Record));
+                }
+                rows
+            }
+        };
+        self.select(selected);
+    }
+
+    /// Highlights `record`, or the first record shown if it is filtered out.
+    pub fn select(&mut self, record: usize) {
+        let row = self
+            .rows
+            .iter()
+            .position(|r| matches!(r, Row::Record(i) if *i == record))
+            .or_else(|| self.rows.iter().position(|r| matches!(r, Row::Record(_))));
+        self.list.select(row);
+    }
+
+    /// The highlighted record, by index into `records`.
+    pub fn selected(&self) -> Option<usize> {
+        match self.rows.get(self.list.selected()?) {
+            Some(Row::Record(i)) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn sync_offset(&mut self, height: usize) {
+        self.offset = scroll_to(self.offset, self.list.selected(), self.rows.len(), height);
+    }
+
+    /// Moves to the next or previous record, passing over group headers.
+    pub fn step(&mut self, forward: bool) {
+        let current = self.list.selected().unwrap_or(0);
+        let is_record = |i: &usize| matches!(self.rows[*i], Row::Record(_));
+        let next = if forward {
+            (current + 1..self.rows.len()).find(is_record)
+        } else {
+            (0..current).rev().find(is_record)
+        };
+        if let Some(next) = next {
+            self.list.select(Some(next));
+        }
+    }
+
+    pub fn toggle_expanded(&mut self) {
+        if let Some(i) = self.selected() {
+            if !self.expanded.remove(&i) {
+                self.expanded.insert(i);
+            }
+        }
+    }
+}
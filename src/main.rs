@@ -1,11 +1,33 @@
 mod app;
 mod banner;
+mod command;
+mod diff;
+mod edit;
 mod event;
+mod filter;
+mod handler;
+mod history;
+mod input_history;
+mod jq;
+mod jsonpath;
+mod marks;
+mod patch;
+mod queries;
+mod search;
+mod source;
+mod sql;
+mod stitch;
+mod keymap;
+mod logs;
 mod ui;
-use crate::event::Key;
-use app::{App, Route};
+mod write;
+use app::{App, Confirmation};
+use diff::Comparison;
+use keymap::{Action, KeyMap};
+use input_history::InputHistory;
+use queries::SavedQueries;
 
-use clap::Args;
+use clap::{Arg, Command};
 use clipboard::{ClipboardContext, ClipboardProvider};
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
@@ -21,9 +43,10 @@ use emoji::symbols::other_symbol::CHECK_MARK;
 use serde_json::{Result as Rs, Value};
 use std::{
     backtrace::Backtrace,
-    env,
     error::Error,
-    io::{self},
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
     process,
 };
 use tui::{
@@ -37,12 +60,47 @@ pub const CHK: &'static str = CHECK_MARK.glyph;
 fn main() -> Result<(), Box<dyn Error>> {
     // println!("Custom backtrace: {}", Backtrace::capture());
 
-    // From Args
-    let args: Vec<String> = env::args().collect();
+    let matches = Command::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Command Line utility to view Json Objects")
+        .after_help("Have Fun!!")
+        .arg(
+            Arg::new("input")
+                .num_args(0..)
+                .help("A JSON file or JSON text; stdin or the clipboard is read without it"),
+        )
+        .arg(
+            Arg::new("query")
+                .long("query")
+                .short('q')
+                .value_name("NAME")
+                .help("Run a query saved in .json_tui.toml once the document is open"),
+        )
+        .arg(
+            Arg::new("stitch")
+                .long("stitch")
+                .value_name("PATH")
+                .help("Join the arrays at PATH in each input file, the pages of a paginated API, into one"),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Compare two JSON files node by node, whatever their layout and key order")
+                .arg(Arg::new("old").required(true).help("The file to compare from"))
+                .arg(Arg::new("new").required(true).help("The file to compare to")),
+        )
+        .args_conflicts_with_subcommands(true)
+        .get_matches();
 
-    // From ClipBoard
-    let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-    let contents = ctx.get_contents().unwrap();
+    // Saved queries come from the project the command is run in
+    let (saved_queries, query_problems) = SavedQueries::load();
+    let query = matches.get_one::<String>("query");
+    if let Some(name) = query {
+        if saved_queries.get(name).is_none() {
+            let file = saved_queries.file.as_ref().map_or(String::from(queries::FILE_NAME), |f| f.display().to_string());
+            eprintln!("json_tui: no query named `{}` in {}", name, file);
+            process::exit(2);
+        }
+    }
 
     // As Strem from another process
     let mut input: String = String::new();
@@ -57,17 +115,13 @@ fn main() -> Result<(), Box<dyn Error>> {
     //     }
     // });
 
-    if !io::stdin().is_tty() {
+    // Two files being compared leave stdin alone
+    if !io::stdin().is_tty() && matches.subcommand_matches("diff").is_none() {
         //TODO : THIS IS IMP
         // input = fs::read_to_string("/dev/stdin")?.parse()?;
 
-        for line in io::stdin().lines() {
-            if let Ok(s) = line {
-                input.push_str(&s)
-            } else {
-                break;
-            }
-        }
+        // Read everything as is, the Input pane shows the original line breaks
+        io::stdin().read_to_string(&mut input)?;
     }
 
     // println!("items is {:#?}",input);
@@ -82,12 +136,71 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Displays Command in command line - jt
     // todo()
 
-    // let matches = Command::new(env!("CARGO_PKG_NAME"))
-    //     .version(env!("CARGO_PKG_VERSION"))
-    //     .author(env!("CARGO_PKG_AUTHORS"))
-    //     .about("Command Line utility to view Json Objects")
-    //     .before_help(BANNER)
-    //     .after_help("Have Fun!!");
+    // Pages of an API are read and joined before anything else
+    let inputs: Vec<&String> = matches.get_many::<String>("input").map_or(Vec::new(), |i| i.collect());
+    let stitched = match matches.get_one::<String>("stitch") {
+        Some(items) => {
+            let files: Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+            match stitch::stitch(&files, items) {
+                Ok(stitched) => Some(stitched),
+                Err(e) => {
+                    eprintln!("json_tui: {}", e);
+                    process::exit(2);
+                }
+            }
+        }
+        None if inputs.len() > 1 => {
+            eprintln!("json_tui: give one input, or --stitch PATH to join pages");
+            process::exit(2);
+        }
+        None => None,
+    };
+
+    // `json_tui diff a.json b.json` shows how two files differ instead
+    let compared = match matches.subcommand_matches("diff") {
+        Some(files) => {
+            let read = |arg: &str| {
+                let name = files.get_one::<String>(arg).unwrap();
+                fs::read_to_string(name)
+                    .map_err(|e| e.to_string())
+                    .and_then(|data| source::parse(&data).map_err(|e| e.to_string()))
+                    .map(|value| (name.clone(), value))
+                    .unwrap_or_else(|e| {
+                        eprintln!("json_tui: {}: {}", name, e);
+                        process::exit(2);
+                    })
+            };
+            let ((old, before), (new, after)) = (read("old"), read("new"));
+            Some(Comparison {
+                old,
+                new,
+                diff: diff::diff(&before, &after),
+                hide_unchanged: false,
+            })
+        }
+        None => None,
+    };
+
+    // A file name argument is read from disk, anything else is taken as JSON
+    let (data, source) = match inputs.first().copied() {
+        _ if stitched.is_some() || compared.is_some() => (String::new(), None),
+        Some(arg) => {
+            if Path::new(arg).is_file() {
+                (fs::read_to_string(arg)?, Some(PathBuf::from(arg)))
+            } else {
+                (arg.to_owned(), None)
+            }
+        }
+        None => {
+            if input.len() > 0 {
+                (input, None)
+            } else {
+                // From ClipBoard
+                let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
+                (ctx.get_contents().unwrap(), None)
+            }
+        }
+    };
 
     // setup terminal
     enable_raw_mode()?;
@@ -100,25 +213,50 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     //Initialize app and Draw
 
-    let mut app = match args.len() {
-        2 => App::init(args.get(1).unwrap()),
-        _ => {
-            if input.len() > 0 {
-                App::init(&input)
-            } else {
-                App::init(&contents)
-            }
-        }
-    };
+    let mut app = App::init(data);
+    app.set_source(source);
+
+    // Config problems are shown in the status line once the UI is up, and
+    // stay listed in the help after the next key clears it
+    let (keymap, mut problems) = KeyMap::load();
+    app.keymap = keymap;
+    problems.extend(query_problems);
+    if !problems.is_empty() {
+        app.status = Some(format!("{}; {} lists them again", problems.join("; "), app.keys(Action::ToggleHelp)));
+    }
+    app.config_problems = problems;
+    app.input_history = InputHistory::load();
+    app.saved_queries = saved_queries;
 
     //Set Json
-    let js = Some(get_json_from_string(&app)?);
-    app.set_json(js);
+    match (stitched, compared) {
+        (Some((value, stitch)), _) => {
+            app.status = Some(stitch.summary());
+            app.show_stitched(value, stitch)?;
+        }
+        (None, Some(comparison)) => {
+            app.status = Some(comparison.summary(&app.keys(Action::NextChange), &app.keys(Action::ToggleUnchanged)));
+            app.show_comparison(comparison)?;
+        }
+        (None, None) => {
+            let js = Some(get_json_from_string(&app)?);
+            app.set_json(js);
+        }
+    }
 
     //Set Display Elements
     // process::exit(1);
     app.set_elements();
 
+    if let Some(name) = query {
+        if let Err(e) = command::run_saved_query(name, &mut app) {
+            app.status = Some(e);
+        }
+    } else if app.compare.is_none() && app.json.as_deref().map_or(false, logs::looks_like_logs) {
+        // Structured logs open straight in the log view
+        let _ = app.show_logs();
+    }
+
     // process::exit(1);
     let events = event::Events::new(200);
 
@@ -144,16 +282,26 @@ fn main() -> Result<(), Box<dyn Error>> {
 }
 
 // Runs the App
-fn run_app<B: Backend>(
+fn run_app<B: Backend + io::Write>(
     terminal: &mut Terminal<B>,
     app: &mut App,
     events: event::Events,
 ) -> Result<(), Box<dyn Error>> {
     terminal.hide_cursor()?;
     // terminal.set_cursor(2,2).unwrap();
+    let mut mouse_captured = true;
 
     loop {
-        let nav_stack = app.get_current_navigation_stack();
+        if app.mouse_capture != mouse_captured {
+            mouse_captured = app.mouse_capture;
+            if mouse_captured {
+                execute!(terminal.backend_mut(), EnableMouseCapture)?;
+            } else {
+                execute!(terminal.backend_mut(), DisableMouseCapture)?;
+            }
+        }
+        app.poll_search();
+        app.poll_jq();
         let current_route = app.get_current_route();
         terminal.draw(|f| ui::draw_ui(f, app))?;
             // terminal.draw(|f| ui::draw_routed_ui(f,  app))?;
@@ -162,30 +310,26 @@ fn run_app<B: Backend>(
         // }
         match events.next()? {
             event::Event::Input(key) => {
-                if current_route == Route::Search {
-                    if key == Key::Ctrl('c') {
-                        break Ok(());
-                    }
-                    handle_input(key, app);
-                } else {
-                    if key == Key::Ctrl('c') {
-                        break Ok(());
-                    } else if key == Key::Char('/') {
-                        app.set_current_route(Route::Search);
-                        app.set_fuzzy_elements();
-                    } else if key == Key::Down {
-                        app.elements.as_mut().unwrap().next();
-                    } else if key == Key::Up {
-                        app.elements.as_mut().unwrap().previous();
-                    } else if key == Key::Enter {
-                        app.set_route();
-                        app.set_elements();
-                    } else if key == Key::Esc && nav_stack.len() > 0 {
-                        app.pop_route();
-                        app.set_elements();
+                app.status = None;
+                if let Some(pending) = app.pending.take() {
+                    handler::handle_pending(pending, key, app);
+                    continue;
+                }
+                match app.keymap.action(current_route, key) {
+                    // Quitting again at the prompt quits
+                    Some(Action::Quit) if !app.modified.is_empty() && !matches!(app.confirming, Some(Confirmation::Quit)) => {
+                        app.confirm(Confirmation::Quit)
                     }
+                    Some(Action::Quit) => break Ok(()),
+                    Some(action) => handler::handle_action(action, app),
+                    None if current_route.takes_text() => handler::handle_input(key, app),
+                    None => {}
+                }
+                if app.quitting {
+                    break Ok(());
                 }
             }
+            event::Event::Mouse(mouse) => handler::handle_mouse(mouse, app),
             event::Event::Tick => {} // }
         }
     }
@@ -195,31 +339,7 @@ fn run_app<B: Backend>(
 
 fn get_json_from_string(app: &App) -> Rs<Value> {
     // Parse the string of data into serde_json::Value.
-    let v: Value = serde_json::from_str(app.data)?;
+    let v: Value = source::parse(&app.data)?;
     Ok(v)
     // eprint!("Value is {}  " , v);
 }
-fn handle_input(key: Key, app: &mut App) {
-    //Set input
-    //Fuzzy match
-    //Set data
-    
-    // terminal.show_cursor().unwrap();
-    if let Key::Char(charac) = key {
-        app.user_input.push(charac);
-        // terminal.set_cursor(x+1, y).unwrap();
-        app.search_and_set_fuzzy_data();
-    } else if key == Key::Down {
-        app.fuzzy_elements.as_mut().unwrap().next();
-    } else if key == Key::Up {
-        app.fuzzy_elements.as_mut().unwrap().previous();
-    } else if key == Key::Backspace {
-        app.user_input.pop();
-        app.search_after_pop();
-    } else {
-        match key {
-            Key::Ctrl(c) => if c == 'q' {app.user_input.clear(); app.set_current_route(Route::Main)}
-            _ => {}
-        }
-    }
-}
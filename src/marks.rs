@@ -0,0 +1,105 @@
+use crate::app::{from_pointer, to_pointer};
+use crate::write;
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// Where marks of every file are stored, in the state directory
+const STATE_FILE: &str = "marks.json";
+
+/// Vim-style marks: a letter pointing at a node path.
+///
+/// Marks are kept per input file in a small JSON state file, keyed by the
+/// file's canonical path. Documents that did not come from a file (stdin,
+/// clipboard, an inline argument) still get marks, they just aren't saved.
+pub struct Marks {
+    marks: BTreeMap<char, Vec<String>>,
+    file: Option<PathBuf>,
+}
+
+impl Marks {
+    pub fn new() -> Marks {
+        Marks {
+            marks: BTreeMap::new(),
+            file: None,
+        }
+    }
+
+    /// Loads the saved marks of `source`, if any.
+    pub fn load(source: &Path) -> Marks {
+        let file = fs::canonicalize(source).ok();
+        let mut marks = BTreeMap::new();
+        if let (Some(file), Some(state)) = (file.as_ref(), write::read_state(STATE_FILE)) {
+            if let Some(Value::Object(saved)) = state.get(file.to_string_lossy().as_ref()) {
+                for (letter, pointer) in saved.iter() {
+                    if let (Some(letter), Some(pointer)) = (letter.chars().next(), pointer.as_str()) {
+                        marks.insert(letter, from_pointer(pointer));
+                    }
+                }
+            }
+        }
+        Marks { marks, file }
+    }
+
+    pub fn set(&mut self, letter: char, path: Vec<String>) -> io::Result<()> {
+        self.marks.insert(letter, path);
+        self.save()
+    }
+
+    pub fn get(&self, letter: char) -> Option<&Vec<String>> {
+        self.marks.get(&letter)
+    }
+
+    pub fn remove(&mut self, letter: char) -> io::Result<()> {
+        self.marks.remove(&letter);
+        self.save()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&char, &Vec<String>)> {
+        self.marks.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.marks.len()
+    }
+
+    /// Drops marks whose path no longer exists in `json`, returning their
+    /// letters. Used after the document is reloaded.
+    pub fn retain_existing(&mut self, json: &Value) -> io::Result<Vec<char>> {
+        let dropped: Vec<char> = self
+            .marks
+            .iter()
+            .filter(|(_, path)| json.pointer(&to_pointer(path)).is_none())
+            .map(|(letter, _)| *letter)
+            .collect();
+        if dropped.is_empty() {
+            return Ok(dropped);
+        }
+        self.marks.retain(|letter, _| !dropped.contains(letter));
+        self.save()?;
+        Ok(dropped)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let file = match self.file.as_ref() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let mut state = write::read_state(STATE_FILE).unwrap_or_default();
+        let key = file.to_string_lossy().into_owned();
+        if self.marks.is_empty() {
+            state.remove(&key);
+        } else {
+            let saved: Map<String, Value> = self
+                .marks
+                .iter()
+                .map(|(letter, path)| (letter.to_string(), Value::String(to_pointer(path))))
+                .collect();
+            state.insert(key, Value::Object(saved));
+        }
+        write::write_state(STATE_FILE, &state)
+    }
+}
@@ -0,0 +1,329 @@
+// Applying a JSON Patch (RFC 6902) or a JSON Merge Patch (RFC 7386) to the
+// document, to preview before it is accepted
+
+use crate::app::{from_pointer, to_pointer};
+use crate::diff::{self, Diff};
+use serde_json::{Map, Value};
+
+pub enum Patch {
+    // The operations, as written
+    Json(Vec<Value>),
+    Merge(Value),
+}
+
+impl Patch {
+    /// Tells the two kinds apart: a JSON Patch is an array of operations, a
+    /// merge patch is an object of the members to change.
+    pub fn parse(value: Value) -> Result<Patch, String> {
+        match value {
+            Value::Array(ops) if ops.iter().all(|op| op.get("op").is_some()) => Ok(Patch::Json(ops)),
+            Value::Array(_) => Err(String::from("an array is read as a JSON Patch, but not every element has an `op`")),
+            value => Ok(Patch::Merge(value)),
+        }
+    }
+
+    /// `doc` with the patch applied. Operations that fail are left out and
+    /// described, by their index in the patch.
+    pub fn apply(&self, doc: &Value) -> (Value, Vec<String>) {
+        let mut doc = doc.clone();
+        let mut problems = Vec::new();
+        match self {
+            Patch::Json(ops) => {
+                for (index, op) in ops.iter().enumerate() {
+                    if let Err(e) = apply_op(&mut doc, op) {
+                        let name = op.get("op").and_then(Value::as_str).unwrap_or("?");
+                        let path = op.get("path").and_then(Value::as_str).unwrap_or("");
+                        problems.push(format!("op {} ({} {}): {}", index, name, path, e));
+                    }
+                }
+            }
+            Patch::Merge(patch) => merge(&mut doc, patch),
+        }
+        (doc, problems)
+    }
+}
+
+/// A patch applied to the document but not yet accepted.
+#[derive(Debug)]
+pub struct Preview {
+    pub file: String,
+    pub result: Value,
+    pub problems: Vec<String>,
+    // The document against the result
+    pub diff: Diff,
+}
+
+fn apply_op(doc: &mut Value, op: &Value) -> Result<(), String> {
+    let pointer = |name: &str| match op.get(name) {
+        Some(Value::String(p)) if p.is_empty() || p.starts_with('/') => Ok(from_pointer(p)),
+        Some(Value::String(p)) => Err(format!("`{}` is not a JSON pointer", p)),
+        _ => Err(format!("there is no `{}`", name)),
+    };
+    let value = || op.get("value").cloned().ok_or("there is no `value`");
+    let path = pointer("path")?;
+    match op.get("op").and_then(Value::as_str) {
+        Some("add") => add(doc, &path, value()?),
+        Some("remove") => remove(doc, &path).map(|_| ()),
+        Some("replace") => {
+            *get_mut(doc, &path)? = value()?;
+            Ok(())
+        }
+        Some("move") => {
+            let from = pointer("from")?;
+            if path.len() > from.len() && path.starts_with(&from) {
+                return Err(String::from("a value can't be moved into itself"));
+            }
+            // Moving a value onto itself leaves it where it is
+            if path == from {
+                return get_mut(doc, &from).map(|_| ());
+            }
+            let moved = remove(doc, &from)?;
+            add(doc, &path, moved)
+        }
+        Some("copy") => {
+            let copied = get_mut(doc, &pointer("from")?)?.clone();
+            add(doc, &path, copied)
+        }
+        Some("test") => {
+            let expected = value()?;
+            let found = get_mut(doc, &path)?;
+            match diff::same(found, &expected) {
+                true => Ok(()),
+                false => Err(format!("the value is {}, not {}", found, expected)),
+            }
+        }
+        Some(other) => Err(format!("unknown op `{}`", other)),
+        None => Err(String::from("`op` is not a string")),
+    }
+}
+
+fn get_mut<'v>(doc: &'v mut Value, path: &[String]) -> Result<&'v mut Value, String> {
+    let mut value = doc;
+    for (depth, token) in path.iter().enumerate() {
+        value = match value {
+            Value::Object(map) => map.get_mut(token),
+            Value::Array(items) => index(token, items.len()).ok().and_then(move |i| items.get_mut(i)),
+            _ => None,
+        }
+        .ok_or_else(|| format!("there is no {}", pointer(&path[..=depth])))?;
+    }
+    Ok(value)
+}
+
+// The parent of the node at `path`, and the last token
+fn parent<'v>(doc: &'v mut Value, path: &'v [String]) -> Result<(&'v mut Value, &'v String), String> {
+    match path.split_last() {
+        Some((last, rest)) => Ok((get_mut(doc, rest)?, last)),
+        None => Err(String::from("the whole document can't be removed")),
+    }
+}
+
+fn add(doc: &mut Value, path: &[String], value: Value) -> Result<(), String> {
+    if path.is_empty() {
+        *doc = value;
+        return Ok(());
+    }
+    let (container, token) = parent(doc, path)?;
+    match container {
+        Value::Object(map) => {
+            map.insert(token.clone(), value);
+            Ok(())
+        }
+        Value::Array(items) if token == "-" => {
+            items.push(value);
+            Ok(())
+        }
+        Value::Array(items) => {
+            let i = index(token, items.len() + 1)?;
+            items.insert(i, value);
+            Ok(())
+        }
+        _ => Err(format!("{} is not an object or array", pointer(&path[..path.len() - 1]))),
+    }
+}
+
+fn remove(doc: &mut Value, path: &[String]) -> Result<Value, String> {
+    let (container, token) = parent(doc, path)?;
+    match container {
+        // Rebuilt rather than removed from, so the other keys keep their order
+        Value::Object(map) if map.contains_key(token) => {
+            let mut removed = Value::Null;
+            *map = std::mem::take(map)
+                .into_iter()
+                .filter_map(|(k, v)| match k == *token {
+                    true => {
+                        removed = v;
+                        None
+                    }
+                    false => Some((k, v)),
+                })
+                .collect();
+            Ok(removed)
+        }
+        Value::Array(items) => {
+            let i = index(token, items.len())?;
+            Ok(items.remove(i))
+        }
+        _ => Err(format!("there is no {}", pointer(path))),
+    }
+}
+
+// An array index in a pointer, which must be below `len`
+fn index(token: &str, len: usize) -> Result<usize, String> {
+    let i = match token.parse::<usize>() {
+        Ok(i) if token == "0" || !token.starts_with('0') => i,
+        _ => return Err(format!("`{}` is not an array index", token)),
+    };
+    match i < len {
+        true => Ok(i),
+        false => Err(format!("index {} is past the end of the array", i)),
+    }
+}
+
+fn pointer(path: &[String]) -> String {
+    match path.is_empty() {
+        true => String::from("root"),
+        false => to_pointer(path),
+    }
+}
+
+// RFC 7386: objects are merged member by member, null removes a member, and
+// anything else replaces what is there
+fn merge(target: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        other => {
+            *target = other.clone();
+            return;
+        }
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(map) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                if map.contains_key(key) {
+                    *map = std::mem::take(map).into_iter().filter(|(k, _)| k != key).collect();
+                }
+            } else {
+                merge(map.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // `doc` with the patch applied, compared as written so key order counts
+    fn apply(doc: Value, patch: Value) -> (String, Vec<String>) {
+        let (result, problems) = Patch::parse(patch).unwrap().apply(&doc);
+        (result.to_string(), problems)
+    }
+
+    fn problems(doc: Value, patch: Value) -> Vec<String> {
+        apply(doc, patch).1
+    }
+
+    #[test]
+    fn operations() {
+        let doc = json!({"a": 1, "b": [1, 2], "c": 3});
+        let patch = json!([
+            {"op": "add", "path": "/b/1", "value": 9},
+            {"op": "add", "path": "/b/-", "value": 4},
+            {"op": "remove", "path": "/a"},
+            {"op": "replace", "path": "/c", "value": "x"},
+            {"op": "add", "path": "/d", "value": null},
+            {"op": "test", "path": "/b/0", "value": 1.0}
+        ]);
+        assert_eq!(apply(doc, patch), (String::from(r#"{"b":[1,9,2,4],"c":"x","d":null}"#), vec![]));
+    }
+
+    #[test]
+    fn move_and_copy() {
+        let doc = json!({"a": {"x": 1}, "b": 2});
+        assert_eq!(
+            apply(doc.clone(), json!([{"op": "move", "from": "/b", "path": "/a/y"}])),
+            (String::from(r#"{"a":{"x":1,"y":2}}"#), vec![])
+        );
+        // A copy may go into what it copies; a move can't
+        assert_eq!(
+            apply(doc.clone(), json!([{"op": "copy", "from": "/a", "path": "/a/self"}])),
+            (String::from(r#"{"a":{"x":1,"self":{"x":1}},"b":2}"#), vec![])
+        );
+        assert_eq!(
+            problems(doc.clone(), json!([{"op": "move", "from": "/a", "path": "/a/x/y"}])),
+            ["op 0 (move /a/x/y): a value can't be moved into itself"]
+        );
+    }
+
+    #[test]
+    fn move_onto_itself_keeps_the_key_order() {
+        let doc = json!({"a": {"x": 1}, "b": 2});
+        assert_eq!(
+            apply(doc.clone(), json!([{"op": "move", "from": "/a", "path": "/a"}])),
+            (String::from(r#"{"a":{"x":1},"b":2}"#), vec![])
+        );
+        assert_eq!(
+            problems(doc, json!([{"op": "move", "from": "/c", "path": "/c"}])).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn failed_operations_are_named_by_index() {
+        let doc = json!({"items": [1, 2], "n": 9007199254740993u64});
+        let patch = json!([
+            {"op": "remove", "path": "/nope"},
+            {"op": "add", "path": "/items/01", "value": 0},
+            {"op": "add", "path": "/items/3", "value": 0},
+            {"op": "test", "path": "/n", "value": 9007199254740992u64},
+            {"op": "replace", "path": "/items/0"},
+            {"op": "remove", "path": ""},
+            {"op": "rename", "path": "/n"},
+            {"op": "add", "path": "items", "value": 0},
+            {"op": "add", "path": "/items/-", "value": 3}
+        ]);
+        assert_eq!(
+            apply(doc, patch),
+            (
+                String::from(r#"{"items":[1,2,3],"n":9007199254740993}"#),
+                vec![
+                    String::from("op 0 (remove /nope): there is no /nope"),
+                    String::from("op 1 (add /items/01): `01` is not an array index"),
+                    String::from("op 2 (add /items/3): index 3 is past the end of the array"),
+                    String::from("op 3 (test /n): the value is 9007199254740993, not 9007199254740992"),
+                    String::from("op 4 (replace /items/0): there is no `value`"),
+                    String::from("op 5 (remove ): the whole document can't be removed"),
+                    String::from("op 6 (rename /n): unknown op `rename`"),
+                    String::from("op 7 (add items): `items` is not a JSON pointer"),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn kinds_of_patch() {
+        assert!(matches!(Patch::parse(json!([])), Ok(Patch::Json(_))));
+        assert!(matches!(Patch::parse(json!({"a": null})), Ok(Patch::Merge(_))));
+        assert!(Patch::parse(json!([{"path": "/a"}])).is_err());
+    }
+
+    #[test]
+    fn merge_patch() {
+        // The example from RFC 7386
+        let doc = json!({"title": "Goodbye!", "author": {"givenName": "John", "familyName": "Doe"},
+                         "tags": ["example", "sample"], "content": "This will be unchanged"});
+        let patch = json!({"title": "Hello!", "phoneNumber": "+01-123-456-7890",
+                           "author": {"familyName": null}, "tags": ["example"]});
+        assert_eq!(
+            apply(doc, patch).0,
+            r#"{"title":"Hello!","author":{"givenName":"John"},"tags":["example"],"content":"This will be unchanged","phoneNumber":"+01-123-456-7890"}"#
+        );
+        assert_eq!(apply(json!({"a": 1}), json!({"a": {"b": null}})).0, r#"{"a":{}}"#);
+        assert_eq!(apply(json!({"a": 1}), json!("x")).0, r#""x""#);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::search::SearchMode;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// The name of the project file with saved queries.
+pub const FILE_NAME: &str = ".json_tui.toml";
+
+/// A query a team has saved under a name in the project file, e.g.
+///
+/// ```toml
+/// [query.failed-jobs]
+/// description = "Jobs that did not finish"
+/// search = "status=failed"
+///
+/// [query.slow]
+/// sql = "SELECT id, latency_ms FROM .requests WHERE latency_ms > 200"
+/// ```
+pub struct SavedQuery {
+    pub name: String,
+    pub description: Option<String>,
+    pub kind: QueryKind,
+}
+
+#[derive(Clone)]
+pub enum QueryKind {
+    Search(String, SearchMode),
+    Jq(String),
+    Sql(String),
+    Goto(String),
+}
+
+impl QueryKind {
+    // The key the query is written under in the file
+    pub fn name(&self) -> &'static str {
+        match self {
+            QueryKind::Search(_, SearchMode::JsonPath) => "jsonpath",
+            QueryKind::Search(..) => "search",
+            QueryKind::Jq(_) => "jq",
+            QueryKind::Sql(_) => "sql",
+            QueryKind::Goto(_) => "goto",
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        match self {
+            QueryKind::Search(text, _) | QueryKind::Jq(text) | QueryKind::Sql(text) | QueryKind::Goto(text) => text,
+        }
+    }
+}
+
+const KINDS: &[&str] = &["search", "jsonpath", "jq", "sql", "goto"];
+
+/// The saved queries of the project, in the order of their names.
+#[derive(Default)]
+pub struct SavedQueries {
+    pub queries: Vec<SavedQuery>,
+    pub file: Option<PathBuf>,
+}
+
+impl SavedQueries {
+    /// The nearest project file, looking in the working directory and then
+    /// in each of its parents, the way git finds its repository.
+    pub fn find_file() -> Option<PathBuf> {
+        let cwd = env::current_dir().ok()?;
+        cwd.ancestors().map(|dir| dir.join(FILE_NAME)).find(|path| path.is_file())
+    }
+
+    /// Loads the project's queries. Like the keymap, a missing file is not
+    /// an error and problems in it are returned rather than aborting.
+    pub fn load() -> (SavedQueries, Vec<String>) {
+        let path = match SavedQueries::find_file() {
+            Some(path) => path,
+            None => return (SavedQueries::default(), Vec::new()),
+        };
+        let (queries, problems) = match fs::read_to_string(&path) {
+            Ok(contents) => SavedQueries::from_toml(&contents, &path),
+            Err(e) => (Vec::new(), vec![format!("could not read {}: {}", path.display(), e)]),
+        };
+        (
+            SavedQueries {
+                queries,
+                file: Some(path),
+            },
+            problems,
+        )
+    }
+
+    pub fn from_toml(contents: &str, path: &Path) -> (Vec<SavedQuery>, Vec<String>) {
+        let file = path.display();
+        let mut problems = Vec::new();
+        let table: toml::Table = match contents.parse() {
+            Ok(table) => table,
+            Err(e) => return (Vec::new(), vec![format!("{}: {}", file, e)]),
+        };
+        let saved = match table.get("query") {
+            Some(toml::Value::Table(saved)) => saved,
+            Some(_) => return (Vec::new(), vec![format!("{}: [query] must be a table", file)]),
+            None => return (Vec::new(), Vec::new()),
+        };
+
+        let mut queries = Vec::new();
+        for (name, query) in saved.iter() {
+            let query = match query.as_table() {
+                Some(query) => query,
+                None => {
+                    problems.push(format!("{}: [query.{}] must be a table", file, name));
+                    continue;
+                }
+            };
+            let kinds: Vec<&str> = KINDS.iter().copied().filter(|k| query.contains_key(*k)).collect();
+            let (kind, text) = match (kinds.as_slice(), kinds.first().and_then(|k| query[*k].as_str())) {
+                ([kind], Some(text)) => (*kind, text.to_owned()),
+                ([kind], None) => {
+                    problems.push(format!("{}: [query.{}] {} must be a string", file, name, kind));
+                    continue;
+                }
+                _ => {
+                    problems.push(format!(
+                        "{}: [query.{}] needs exactly one of {}",
+                        file,
+                        name,
+                        KINDS.join(", ")
+                    ));
+                    continue;
+                }
+            };
+            let mode = match query.get("mode").map(|m| m.as_str()) {
+                None => SearchMode::Fuzzy,
+                Some(Some("fuzzy")) => SearchMode::Fuzzy,
+                Some(Some("substring")) => SearchMode::Substring,
+                Some(Some("regex")) => SearchMode::Regex,
+                Some(_) => {
+                    problems.push(format!("{}: [query.{}] mode is fuzzy, substring or regex", file, name));
+                    continue;
+                }
+            };
+            let kind = match kind {
+                "search" => QueryKind::Search(text, mode),
+                "jsonpath" => QueryKind::Search(text, SearchMode::JsonPath),
+                "jq" => QueryKind::Jq(text),
+                "sql" => QueryKind::Sql(text),
+                _ => QueryKind::Goto(text),
+            };
+            queries.push(SavedQuery {
+                name: name.to_owned(),
+                description: query.get("description").and_then(|d| d.as_str()).map(str::to_owned),
+                kind,
+            });
+        }
+        (queries, problems)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SavedQuery> {
+        self.queries.iter().find(|q| q.name == name)
+    }
+}
@@ -0,0 +1,624 @@
+// What the search bar matches against and how
+
+use crate::filter::{Filter, Node};
+use crate::jsonpath;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use regex::{Regex, RegexBuilder};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// One searchable node: its path from the root and the `path : value` text
+// that is matched and shown. Objects and arrays are only there for key search.
+#[derive(Debug)]
+pub struct SearchEntry {
+    pub path: Vec<String>,
+    pub text: String,
+    // Where the node's own key and its value are in `text`
+    key_start: usize,
+    value_start: usize,
+    container: bool,
+}
+
+impl SearchEntry {
+    fn new(path: Vec<String>, value: &str, container: bool) -> SearchEntry {
+        let joined = path.join("/");
+        let key_start = 1 + joined.len() - path.last().map_or(0, |k| k.len());
+        let text = format!("/{} : {}", joined, value);
+        let value_start = text.len() - value.len();
+        SearchEntry {
+            path,
+            text,
+            key_start,
+            value_start,
+            container,
+        }
+    }
+
+    /// The part of `text` that `scope` searches, or `None` if the entry is not
+    /// searched at all.
+    pub fn haystack(&self, scope: SearchScope) -> Option<&str> {
+        self.haystack_range(scope).map(|(start, end)| &self.text[start..end])
+    }
+
+    /// Byte range of `haystack` in `text`.
+    pub fn haystack_range(&self, scope: SearchScope) -> Option<(usize, usize)> {
+        match scope {
+            SearchScope::Keys => Some((self.key_start, self.key_end())),
+            _ if self.container => None,
+            SearchScope::Values => Some((self.value_start, self.text.len())),
+            SearchScope::Both => Some((0, self.text.len())),
+        }
+    }
+
+    pub fn key_start(&self) -> usize {
+        self.key_start
+    }
+
+    pub fn key_end(&self) -> usize {
+        self.value_start - " : ".len()
+    }
+
+    pub fn value_start(&self) -> usize {
+        self.value_start
+    }
+}
+
+// Collects every node below `val` as a search entry, depth first
+pub fn flatten(val: &Value, path: &mut Vec<String>, entries: &mut Vec<SearchEntry>) {
+    let mut visit = |key: String, v: &Value, path: &mut Vec<String>| {
+        path.push(key);
+        match v {
+            Value::Object(_) => {
+                entries.push(SearchEntry::new(path.clone(), "{...}", true));
+                flatten(v, path, entries)
+            }
+            Value::Array(_) => {
+                entries.push(SearchEntry::new(path.clone(), "[...]", true));
+                flatten(v, path, entries)
+            }
+            Value::Null => entries.push(SearchEntry::new(path.clone(), "NULL", false)),
+            other => entries.push(SearchEntry::new(path.clone(), &other.to_string(), false)),
+        }
+        path.pop();
+    };
+    match val {
+        Value::Object(map) => map.iter().for_each(|(k, v)| visit(k.to_owned(), v, path)),
+        Value::Array(items) => items.iter().enumerate().for_each(|(i, v)| visit(i.to_string(), v, path)),
+        _ => {}
+    }
+}
+
+// The values below `val` in the same order as `flatten` lists them
+fn collect_values<'a>(val: &'a Value, values: &mut Vec<&'a Value>) {
+    let children: Box<dyn Iterator<Item = &Value>> = match val {
+        Value::Object(map) => Box::new(map.values()),
+        Value::Array(items) => Box::new(items.iter()),
+        _ => return,
+    };
+    for v in children {
+        values.push(v);
+        collect_values(v, values);
+    }
+}
+
+/// Which part of an entry the query is matched against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchScope {
+    Both,
+    Keys,
+    Values,
+}
+
+impl SearchScope {
+    pub fn next(self) -> SearchScope {
+        match self {
+            SearchScope::Both => SearchScope::Keys,
+            SearchScope::Keys => SearchScope::Values,
+            SearchScope::Values => SearchScope::Both,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SearchScope::Both => "keys and values",
+            SearchScope::Keys => "keys",
+            SearchScope::Values => "values",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchMode {
+    Fuzzy,
+    Substring,
+    Regex,
+    // The query is a JSONPath expression such as `$.items[?@.price > 10]`
+    JsonPath,
+}
+
+impl SearchMode {
+    pub fn next(self) -> SearchMode {
+        match self {
+            SearchMode::Fuzzy => SearchMode::Substring,
+            SearchMode::Substring => SearchMode::Regex,
+            SearchMode::Regex => SearchMode::JsonPath,
+            SearchMode::JsonPath => SearchMode::Fuzzy,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SearchMode::Fuzzy => "fuzzy",
+            SearchMode::Substring => "substring",
+            SearchMode::Regex => "regex",
+            SearchMode::JsonPath => "JSONPath",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaseMode {
+    // Ignore case unless the query has an upper case letter
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+impl CaseMode {
+    pub fn next(self) -> CaseMode {
+        match self {
+            CaseMode::Smart => CaseMode::Sensitive,
+            CaseMode::Sensitive => CaseMode::Insensitive,
+            CaseMode::Insensitive => CaseMode::Smart,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CaseMode::Smart => "smart case",
+            CaseMode::Sensitive => "case sensitive",
+            CaseMode::Insensitive => "ignore case",
+        }
+    }
+
+    fn ignores_case(self, query: &str) -> bool {
+        match self {
+            CaseMode::Smart => !query.chars().any(char::is_uppercase),
+            CaseMode::Sensitive => false,
+            CaseMode::Insensitive => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SearchOptions {
+    pub mode: SearchMode,
+    pub case: CaseMode,
+    pub scope: SearchScope,
+    // Only search below the level being shown
+    pub subtree: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> SearchOptions {
+        SearchOptions {
+            mode: SearchMode::Fuzzy,
+            case: CaseMode::Smart,
+            scope: SearchScope::Both,
+            subtree: false,
+        }
+    }
+}
+
+/// A query compiled for the chosen mode.
+pub enum Matcher {
+    Fuzzy(SkimMatcherV2, String),
+    // Case-sensitive; ignoring case is an escaped, case-insensitive regex,
+    // so matches are found in the text as it is
+    Substring(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    /// Fails for a regex that does not compile, with the message to show, and
+    /// for JSONPath, which selects nodes rather than matching text.
+    pub fn new(query: &str, options: SearchOptions) -> Result<Matcher, String> {
+        let ignore_case = options.case.ignores_case(query);
+        Ok(match options.mode {
+            SearchMode::Fuzzy => {
+                let matcher = SkimMatcherV2::default();
+                let matcher = if ignore_case { matcher.ignore_case() } else { matcher.respect_case() };
+                Matcher::Fuzzy(matcher, query.to_owned())
+            }
+            SearchMode::Substring if !ignore_case => Matcher::Substring(query.to_owned()),
+            SearchMode::Substring | SearchMode::Regex => {
+                let pattern = match options.mode {
+                    SearchMode::Substring => regex::escape(query),
+                    _ => query.to_owned(),
+                };
+                Matcher::Regex(
+                    RegexBuilder::new(&pattern)
+                        .case_insensitive(ignore_case)
+                        .build()
+                        // Syntax errors point at the pattern over several lines;
+                        // the last one says what is wrong
+                        .map_err(|e| e.to_string().lines().last().unwrap_or_default().trim().to_owned())?,
+                )
+            }
+            SearchMode::JsonPath => return Err(String::from("JSONPath is not matched as text")),
+        })
+    }
+
+    /// How well `text` matches, higher is better, or `None` if it does not.
+    /// Only fuzzy matching scores; other modes keep document order.
+    pub fn score(&self, text: &str) -> Option<i64> {
+        match self {
+            Matcher::Fuzzy(_, query) if query.is_empty() => Some(0),
+            Matcher::Fuzzy(matcher, query) => matcher.fuzzy_match(text, query),
+            Matcher::Substring(needle) => text.contains(needle.as_str()).then_some(0),
+            Matcher::Regex(regex) => regex.is_match(text).then_some(0),
+        }
+    }
+
+    /// Indices of the chars of `text` that the query matched, in order.
+    pub fn indices(&self, text: &str) -> Vec<usize> {
+        let ranges: Vec<(usize, usize)> = match self {
+            Matcher::Fuzzy(_, query) if query.is_empty() => return Vec::new(),
+            Matcher::Fuzzy(matcher, query) => {
+                return matcher.fuzzy_indices(text, query).map(|(_, i)| i).unwrap_or_default()
+            }
+            Matcher::Substring(needle) if needle.is_empty() => return Vec::new(),
+            Matcher::Substring(needle) => text.match_indices(needle.as_str()).map(|(at, m)| (at, at + m.len())).collect(),
+            Matcher::Regex(regex) => regex.find_iter(text).map(|m| (m.start(), m.end())).collect(),
+        };
+        text.char_indices()
+            .enumerate()
+            .filter(|(_, (byte, _))| ranges.iter().any(|(start, end)| byte >= start && byte < end))
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+/// Searches on a worker thread, against an index of the document that the
+/// worker builds once, so neither blocks the UI on a big document.
+///
+/// Every query gets a new generation. The worker drops a query as soon as a
+/// newer one has been asked for, and streams results while it goes.
+pub struct SearchWorker {
+    requests: mpsc::Sender<Request>,
+    updates: mpsc::Receiver<Update>,
+    latest: Arc<AtomicU64>,
+    // Set once the index is built
+    indexed: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+struct Request {
+    generation: u64,
+    text: String,
+    options: SearchOptions,
+    subtree: Vec<String>,
+}
+
+pub enum Update {
+    /// The index is built; results refer to entries in it.
+    Ready(Arc<Vec<SearchEntry>>),
+    Results {
+        generation: u64,
+        /// Matches that made the best `MAX_RESULTS` since the last update,
+        /// with their scores, best first
+        added: Vec<(i64, usize)>,
+        /// The normalized path of each added match, for JSONPath queries
+        locations: Vec<String>,
+        /// Matches so far, including those not kept
+        matched: usize,
+        /// Entries looked at so far
+        total: usize,
+        done: bool,
+    },
+}
+
+// Entries matched between checks for a newer query
+const CHUNK: usize = 20_000;
+// How often partial results are sent while a query runs
+const STREAM_INTERVAL: Duration = Duration::from_millis(100);
+// How many of the best matches are kept and listed
+pub const MAX_RESULTS: usize = 10_000;
+
+impl SearchWorker {
+    pub fn new(json: Arc<Value>) -> SearchWorker {
+        let (requests, queries) = mpsc::channel::<Request>();
+        let (sender, updates) = mpsc::channel();
+        let latest = Arc::new(AtomicU64::new(0));
+        let current = latest.clone();
+        let indexed = Arc::new(AtomicBool::new(false));
+        let built = indexed.clone();
+        let thread = thread::spawn(move || {
+            let mut entries = Vec::new();
+            flatten(&json, &mut Vec::new(), &mut entries);
+            // The value of every entry, for structured queries
+            let mut values = Vec::with_capacity(entries.len());
+            collect_values(&json, &mut values);
+            let entries = Arc::new(entries);
+            if sender.send(Update::Ready(entries.clone())).is_err() {
+                return;
+            }
+            built.store(true, Ordering::SeqCst);
+            // Where each path is in the index, built for the first JSONPath query
+            let mut positions = None;
+            while let Ok(mut query) = queries.recv() {
+                // Only the newest query is worth running
+                while let Ok(newer) = queries.try_recv() {
+                    query = newer;
+                }
+                let sent = if query.options.mode == SearchMode::JsonPath {
+                    let positions = positions.get_or_insert_with(|| {
+                        entries.iter().enumerate().map(|(i, e)| (e.path.as_slice(), i)).collect()
+                    });
+                    run_jsonpath(&json, &entries, positions, &query, &sender)
+                } else {
+                    run(&entries, &values, &query, &current, &sender)
+                };
+                if sent.is_err() {
+                    return;
+                }
+            }
+        });
+        SearchWorker {
+            requests,
+            updates,
+            latest,
+            indexed,
+            thread,
+        }
+    }
+
+    /// Stops the worker. Unless it is still building the index, this waits
+    /// for it to let go of the document, which is at most one chunk of a
+    /// query away.
+    pub fn stop(self) {
+        self.latest.store(u64::MAX, Ordering::SeqCst);
+        drop(self.requests);
+        if self.indexed.load(Ordering::SeqCst) {
+            let _ = self.thread.join();
+        }
+    }
+
+    /// Asks for a search, returning its generation. Results of earlier
+    /// generations may still arrive and should be ignored.
+    pub fn query(&self, text: &str, options: SearchOptions, subtree: &[String]) -> u64 {
+        let generation = self.latest.fetch_add(1, Ordering::SeqCst) + 1;
+        // A worker that is gone has nothing to report anyway
+        let _ = self.requests.send(Request {
+            generation,
+            text: text.to_owned(),
+            options,
+            subtree: subtree.to_vec(),
+        });
+        generation
+    }
+
+    pub fn try_recv(&self) -> Option<Update> {
+        self.updates.try_recv().ok()
+    }
+}
+
+fn run(
+    entries: &[SearchEntry],
+    values: &[&Value],
+    query: &Request,
+    latest: &AtomicU64,
+    sender: &mpsc::Sender<Update>,
+) -> Result<(), mpsc::SendError<Update>> {
+    // The UI has compiled the query already and reported any error
+    let filter = match Filter::parse(&query.text, query.options) {
+        Ok(filter) => filter,
+        Err(_) => return Ok(()),
+    };
+    let matcher = match (filter.is_none(), Matcher::new(&query.text, query.options)) {
+        (true, Err(_)) => return Ok(()),
+        (_, matcher) => matcher.ok(),
+    };
+    let scope = query.options.scope;
+    // Whether the entry is searched at all, and its score if it matches
+    let score = |index: usize, entry: &SearchEntry| -> Option<Option<i64>> {
+        match filter.as_ref() {
+            Some(filter) => {
+                // Every node counts here; objects and arrays match plain
+                // words by their key
+                let text = entry.haystack(scope).or_else(|| entry.haystack(SearchScope::Keys))?;
+                let node = Node {
+                    path: &entry.path,
+                    value: values[index],
+                    text,
+                };
+                Some(filter.matches(&node).then(|| filter.score(&node)))
+            }
+            None => Some(matcher.as_ref()?.score(entry.haystack(scope)?)),
+        }
+    };
+    // The best matches so far, worst on top; a later entry loses a tie
+    let mut best: BinaryHeap<Reverse<(i64, Reverse<usize>)>> = BinaryHeap::new();
+    // Those that made it since the last update
+    let mut added: Vec<(i64, usize)> = Vec::new();
+    let mut matched = 0;
+    let mut total = 0;
+    let mut last_sent = Instant::now();
+    for (chunk, batch) in entries.chunks(CHUNK).enumerate() {
+        if latest.load(Ordering::SeqCst) != query.generation {
+            return Ok(());
+        }
+        for (offset, entry) in batch.iter().enumerate() {
+            if !below(&entry.path, &query.subtree) {
+                continue;
+            }
+            let index = chunk * CHUNK + offset;
+            if let Some(result) = score(index, entry) {
+                total += 1;
+                if let Some(score) = result {
+                    matched += 1;
+                    let key = Reverse((score, Reverse(index)));
+                    if best.len() < MAX_RESULTS {
+                        best.push(key);
+                    } else if best.peek().map_or(false, |worst| key < *worst) {
+                        best.pop();
+                        best.push(key);
+                    } else {
+                        continue;
+                    }
+                    added.push((score, index));
+                }
+            }
+        }
+        let done = (chunk + 1) * CHUNK >= entries.len();
+        if done || last_sent.elapsed() >= STREAM_INTERVAL {
+            // Best first; equal scores stay in document order
+            added.sort_by_key(|&(score, _)| Reverse(score));
+            sender.send(Update::Results {
+                generation: query.generation,
+                added: std::mem::take(&mut added),
+                locations: Vec::new(),
+                matched,
+                total,
+                done,
+            })?;
+            last_sent = Instant::now();
+        }
+    }
+    if entries.is_empty() {
+        sender.send(Update::Results {
+            generation: query.generation,
+            added: Vec::new(),
+            locations: Vec::new(),
+            matched: 0,
+            total: 0,
+            done: true,
+        })?;
+    }
+    Ok(())
+}
+
+// Whether `path` is inside `subtree`; the node searched in is not a result
+// of its own
+fn below(path: &[String], subtree: &[String]) -> bool {
+    path.len() > subtree.len() && path.starts_with(subtree)
+}
+
+/// Merges matches `added` into the list `items`, best first, whose scores
+/// are `scores`, keeping the best `MAX_RESULTS`. Equal scores keep the
+/// matches already listed first.
+pub fn merge(items: &mut Vec<usize>, scores: &mut Vec<i64>, added: Vec<(i64, usize)>) {
+    if added.is_empty() {
+        return;
+    }
+    let mut merged = Vec::with_capacity((items.len() + added.len()).min(MAX_RESULTS));
+    let mut listed = scores.iter().copied().zip(items.iter().copied()).peekable();
+    let mut added = added.into_iter().peekable();
+    while merged.len() < MAX_RESULTS {
+        let next = match (listed.peek(), added.peek()) {
+            (Some(a), Some(b)) if b.0 > a.0 => added.next(),
+            (Some(_), _) => listed.next(),
+            (None, _) => added.next(),
+        };
+        match next {
+            Some(pair) => merged.push(pair),
+            None => break,
+        }
+    }
+    let (new_scores, new_items) = merged.into_iter().unzip();
+    *scores = new_scores;
+    *items = new_items;
+}
+
+// JSONPath selects its nodes in one go, in the order the query gives them
+fn run_jsonpath(
+    json: &Value,
+    entries: &[SearchEntry],
+    positions: &HashMap<&[String], usize>,
+    query: &Request,
+    sender: &mpsc::Sender<Update>,
+) -> Result<(), mpsc::SendError<Update>> {
+    let (mut matches, mut locations): (Vec<usize>, Vec<String>) = if query.text.trim().is_empty() {
+        (Vec::new(), Vec::new())
+    } else {
+        match jsonpath::parse(&query.text) {
+            // The root has no entry of its own, so it is never listed
+            Ok(path) => jsonpath::locate(&path, json)
+                .into_iter()
+                .filter(|(path, _)| below(path, &query.subtree))
+                .filter_map(|(path, location)| Some((*positions.get(path.as_slice())?, location)))
+                .unzip(),
+            // The UI has reported the error already
+            Err(_) => return Ok(()),
+        }
+    };
+    let matched = matches.len();
+    matches.truncate(MAX_RESULTS);
+    locations.truncate(MAX_RESULTS);
+    sender.send(Update::Results {
+        generation: query.generation,
+        // One score for all keeps the query's order
+        added: matches.into_iter().map(|index| (0, index)).collect(),
+        locations,
+        matched,
+        total: entries.iter().filter(|e| below(&e.path, &query.subtree)).count(),
+        done: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_keeps_the_best_first() {
+        let mut items = vec![4, 7];
+        let mut scores = vec![30, 10];
+        merge(&mut items, &mut scores, vec![(40, 9), (20, 11), (10, 12)]);
+        assert_eq!(items, vec![9, 4, 11, 7, 12]);
+        assert_eq!(scores, vec![40, 30, 20, 10, 10]);
+    }
+
+    #[test]
+    fn merge_keeps_at_most_max_results() {
+        let mut items = Vec::new();
+        let mut scores = Vec::new();
+        merge(&mut items, &mut scores, (0..MAX_RESULTS).map(|i| (1, i)).collect());
+        merge(&mut items, &mut scores, vec![(2, MAX_RESULTS), (1, MAX_RESULTS + 1)]);
+        assert_eq!(items.len(), MAX_RESULTS);
+        assert_eq!(items[0], MAX_RESULTS);
+        assert_eq!(items[MAX_RESULTS - 1], MAX_RESULTS - 2);
+    }
+
+    #[test]
+    fn substrings_ignoring_case_highlight_the_text_as_it_is() {
+        let options = SearchOptions {
+            mode: SearchMode::Substring,
+            ..SearchOptions::default()
+        };
+        let matcher = Matcher::new("ist", options).unwrap();
+        // `İ` lower cases to two chars, which must not shift what follows
+        assert_eq!(matcher.indices("İst IST"), vec![4, 5, 6]);
+        assert_eq!(matcher.score("İst IST"), Some(0));
+        let matcher = Matcher::new("a.b", options).unwrap();
+        assert_eq!(matcher.score("axb"), None);
+        assert_eq!(matcher.indices("xA.B"), vec![1, 2, 3]);
+        let matcher = Matcher::new("Ist", options).unwrap();
+        assert_eq!(matcher.indices("ist Ist"), vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn the_subtree_searched_is_not_a_result() {
+        let subtree = [String::from("items")];
+        assert!(!below(&subtree, &subtree));
+        assert!(below(&[String::from("items"), String::from("0")], &subtree));
+        assert!(!below(&[String::from("other")], &subtree));
+        assert!(below(&subtree, &[]));
+    }
+}
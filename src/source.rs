@@ -0,0 +1,301 @@
+// Byte spans of every value in the raw input, so the Input pane can show
+// and highlight exactly where the selected node came from
+
+use serde_json::Value;
+
+/// Where one value sits in the input, `start..end` in bytes, with the spans
+/// of its children.
+pub struct SpanNode {
+    pub start: usize,
+    pub end: usize,
+    children: Children,
+}
+
+enum Children {
+    None,
+    // Keys in document order, unescaped so they match `serde_json::Map` keys
+    Object(Vec<(String, SpanNode)>),
+    Array(Vec<SpanNode>),
+}
+
+/// The raw input together with the span of every value and where each line
+/// starts.
+pub struct SourceMap {
+    root: SpanNode,
+    line_starts: Vec<usize>,
+}
+
+/// The document in `data`: one JSON value, or a stream of them such as
+/// NDJSON logs, which becomes an array of the values.
+pub fn parse(data: &str) -> serde_json::Result<Value> {
+    let error = match serde_json::from_str(data) {
+        Ok(value) => return Ok(value),
+        Err(error) => error,
+    };
+    let values: serde_json::Result<Vec<Value>> = serde_json::Deserializer::from_str(data).into_iter().collect();
+    match values {
+        Ok(values) if values.len() > 1 => Ok(Value::Array(values)),
+        // The error of the first reading says more about a broken document
+        _ => Err(error),
+    }
+}
+
+impl SourceMap {
+    /// Scans `data`, which must already be known to be valid JSON (it has been
+    /// through `serde_json`). Returns `None` if it is not. A stream of values
+    /// maps to an array of them, as `parse` reads it.
+    pub fn new(data: &str) -> Option<SourceMap> {
+        let mut scanner = Scanner {
+            bytes: data.as_bytes(),
+            pos: 0,
+        };
+        let mut root = scanner.value()?;
+        scanner.skip_whitespace();
+        if scanner.pos < data.len() {
+            let mut items = vec![root];
+            while scanner.pos < data.len() {
+                items.push(scanner.value()?);
+                scanner.skip_whitespace();
+            }
+            root = SpanNode {
+                start: items[0].start,
+                end: scanner.pos,
+                children: Children::Array(items),
+            };
+        }
+        let line_starts = std::iter::once(0)
+            .chain(data.bytes().enumerate().filter(|(_, b)| *b == b'\n').map(|(i, _)| i + 1))
+            .collect();
+        Some(SourceMap { root, line_starts })
+    }
+
+    /// The span of the node at `path`. Later duplicates of a key win, as they
+    /// do in `serde_json`.
+    pub fn span(&self, path: &[String]) -> Option<(usize, usize)> {
+        let mut node = &self.root;
+        for token in path {
+            node = match &node.children {
+                Children::Object(entries) => &entries.iter().rev().find(|(k, _)| k == token)?.1,
+                Children::Array(items) => items.get(token.parse::<usize>().ok()?)?,
+                Children::None => return None,
+            };
+        }
+        Some((node.start, node.end))
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Zero based line of byte `offset`.
+    pub fn line_of(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        }
+    }
+
+    /// Byte range of `line`, without its line break.
+    pub fn line_range(&self, line: usize, data: &str) -> (usize, usize) {
+        let start = self.line_starts[line];
+        let mut end = self.line_starts.get(line + 1).map_or(data.len(), |next| next - 1);
+        if end > start && data.as_bytes()[end - 1] == b'\r' {
+            end -= 1;
+        }
+        (start, end)
+    }
+}
+
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn value(&mut self) -> Option<SpanNode> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let children = match *self.bytes.get(self.pos)? {
+            b'{' => {
+                self.pos += 1;
+                let mut entries = Vec::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        entries.push((key, self.value()?));
+                        self.skip_whitespace();
+                        match self.bytes.get(self.pos)? {
+                            b',' => self.pos += 1,
+                            b'}' => {
+                                self.pos += 1;
+                                break;
+                            }
+                            _ => return None,
+                        }
+                    }
+                }
+                Children::Object(entries)
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        items.push(self.value()?);
+                        self.skip_whitespace();
+                        match self.bytes.get(self.pos)? {
+                            b',' => self.pos += 1,
+                            b']' => {
+                                self.pos += 1;
+                                break;
+                            }
+                            _ => return None,
+                        }
+                    }
+                }
+                Children::Array(items)
+            }
+            b'"' => {
+                self.skip_string()?;
+                Children::None
+            }
+            _ => {
+                // Numbers and literals run until a delimiter
+                while let Some(b) = self.bytes.get(self.pos) {
+                    if matches!(b, b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r') {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    return None;
+                }
+                Children::None
+            }
+        };
+        Some(SpanNode {
+            start,
+            end: self.pos,
+            children,
+        })
+    }
+
+    fn skip_string(&mut self) -> Option<()> {
+        self.pos += 1;
+        loop {
+            match *self.bytes.get(self.pos)? {
+                b'\\' => self.pos += 2,
+                b'"' => {
+                    self.pos += 1;
+                    return Some(());
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    // Reads a string and unescapes it; only used for keys
+    fn string(&mut self) -> Option<String> {
+        let start = self.pos;
+        if self.bytes.get(self.pos) != Some(&b'"') {
+            return None;
+        }
+        self.skip_string()?;
+        let raw = std::str::from_utf8(&self.bytes[start..self.pos]).ok()?;
+        if !raw.contains('\\') {
+            return Some(raw[1..raw.len() - 1].to_owned());
+        }
+        serde_json::from_str(raw).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(tokens: &[&str]) -> Vec<String> {
+        tokens.iter().map(|t| t.to_string()).collect()
+    }
+
+    // The text of the node at `tokens`
+    fn text<'a>(data: &'a str, tokens: &[&str]) -> Option<&'a str> {
+        let (start, end) = SourceMap::new(data)?.span(&path(tokens))?;
+        Some(&data[start..end])
+    }
+
+    #[test]
+    fn escaped_keys_match_unescaped() {
+        let data = r#"{"a\"b": 1, "é": [true], "tab\t": "x\"y"}"#;
+        assert_eq!(text(data, &["a\"b"]), Some("1"));
+        assert_eq!(text(data, &["é", "0"]), Some("true"));
+        assert_eq!(text(data, &["tab\t"]), Some(r#""x\"y""#));
+    }
+
+    #[test]
+    fn later_duplicate_keys_win() {
+        let data = r#"{"a": 1, "b": {"c": 2}, "a": [3]}"#;
+        assert_eq!(text(data, &["a"]), Some("[3]"));
+        assert_eq!(text(data, &["a", "0"]), Some("3"));
+        assert_eq!(text(data, &["b", "c"]), Some("2"));
+    }
+
+    #[test]
+    fn array_indices() {
+        let data = "[ 1 , [ \"x\" , null ] , -2.5e3 ]";
+        assert_eq!(text(data, &["0"]), Some("1"));
+        assert_eq!(text(data, &["1", "1"]), Some("null"));
+        assert_eq!(text(data, &["2"]), Some("-2.5e3"));
+        assert_eq!(text(data, &["3"]), None);
+        assert_eq!(text(data, &["01x"]), None);
+        assert_eq!(text(data, &[]), Some(data));
+    }
+
+    #[test]
+    fn streams_are_arrays_of_their_values() {
+        let data = "{\"n\": 1}\n{\"n\": 2}\n\n{\"n\": 3}\n";
+        assert_eq!(parse(data).unwrap(), serde_json::json!([{"n": 1}, {"n": 2}, {"n": 3}]));
+        assert_eq!(text(data, &["1", "n"]), Some("2"));
+        assert_eq!(text(data, &["2"]), Some("{\"n\": 3}"));
+        let map = SourceMap::new(data).unwrap();
+        assert_eq!(map.line_of(map.span(&path(&["2"])).unwrap().0), 3);
+        assert!(parse("{\"n\": 1} {").is_err());
+    }
+
+    #[test]
+    fn lines_leave_out_their_breaks() {
+        let data = "{\r\n  \"a\": 1\r\n}\r\n";
+        let map = SourceMap::new(data).unwrap();
+        assert_eq!(map.line_count(), 4);
+        let (start, end) = map.line_range(1, data);
+        assert_eq!(&data[start..end], "  \"a\": 1");
+        assert_eq!(map.line_range(3, data), (data.len(), data.len()));
+        assert_eq!(map.line_of(map.span(&path(&["a"])).unwrap().0), 1);
+        let data = "[1,\n2]";
+        let map = SourceMap::new(data).unwrap();
+        assert_eq!(map.line_range(1, data), (4, 6));
+    }
+}
@@ -0,0 +1,818 @@
+// SQL style queries over arrays of records, such as
+// `SELECT id, status FROM .items WHERE latency_ms > 200 ORDER BY latency_ms DESC LIMIT 20`
+
+use crate::app::to_pointer;
+use crate::command::parse_path;
+use serde_json::{Map, Number, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Runs `query` on `json`, giving the selected rows as an array of objects.
+pub fn run(query: &str, json: &Value) -> Result<Value, String> {
+    let tokens = tokenize(query)?;
+    let query = Parser { tokens, at: 0 }.query()?;
+    query.run(json)
+}
+
+struct Query {
+    // `None` for `SELECT *`
+    select: Option<Vec<Selected>>,
+    from: Vec<String>,
+    from_text: String,
+    filter: Option<Expr>,
+    group_by: Vec<Column>,
+    order_by: Vec<(Item, bool)>,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+#[derive(Clone)]
+struct Column {
+    name: String,
+    path: Vec<String>,
+}
+
+enum Item {
+    Column(Column),
+    // `COUNT(*)` has no column
+    Aggregate(Aggregate, Option<Column>),
+}
+
+#[derive(Clone, Copy)]
+enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+// A column of the result and the key it is stored under
+struct Selected {
+    item: Item,
+    name: String,
+}
+
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, Op, Operand),
+    Like(Operand, Vec<char>),
+    IsNull(Operand),
+    In(Operand, Vec<Value>),
+}
+
+enum Operand {
+    Column(Column),
+    Literal(Value),
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    // Keywords, column names and paths such as `.items[0]`
+    Word(String),
+    // `"..."`, a column name that is not a path
+    Quoted(String),
+    // `'...'`
+    Text(String),
+    Number(Number),
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) | Token::Quoted(word) => format!("`{}`", word),
+            Token::Text(text) => format!("'{}'", text),
+            Token::Number(n) => format!("`{}`", n),
+            Token::Symbol(symbol) => format!("`{}`", symbol),
+        }
+    }
+}
+
+const SYMBOLS: &[&str] = &["<=", ">=", "<>", "!=", "==", ",", "(", ")", "*", "=", "<", ">"];
+
+fn tokenize(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = query;
+    loop {
+        rest = rest.trim_start();
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => return Ok(tokens),
+        };
+        let len = if c == '\'' || c == '"' {
+            // Quotes are escaped by doubling them
+            let mut text = String::new();
+            let mut chars = rest.char_indices().skip(1).peekable();
+            let end = loop {
+                match chars.next() {
+                    Some((_, q)) if q == c && chars.peek().map(|&(_, n)| n) == Some(c) => {
+                        chars.next();
+                        text.push(c);
+                    }
+                    Some((i, q)) if q == c => break i + 1,
+                    Some((_, other)) => text.push(other),
+                    None => return Err(format!("unclosed {}", c)),
+                }
+            };
+            tokens.push(if c == '\'' { Token::Text(text) } else { Token::Quoted(text) });
+            end
+        } else if c.is_ascii_digit() || (c == '-' && rest[1..].starts_with(|d: char| d.is_ascii_digit())) {
+            let mut end = 1;
+            for (i, d) in rest.char_indices().skip(1) {
+                let exponent_sign = matches!(d, '+' | '-') && rest[..i].ends_with(['e', 'E']);
+                if !(d.is_ascii_digit() || matches!(d, '.' | 'e' | 'E') || exponent_sign) {
+                    break;
+                }
+                end = i + 1;
+            }
+            let number = rest[..end].parse().map_err(|_| format!("`{}` is not a number", &rest[..end]))?;
+            tokens.push(Token::Number(number));
+            end
+        } else if c.is_alphabetic() || matches!(c, '_' | '.' | '/') {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '/' | '[' | ']' | '-' | '~')))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].to_owned()));
+            end
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| rest.starts_with(*s))
+                .ok_or_else(|| format!("unexpected `{}`", c))?;
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        };
+        rest = &rest[len..];
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    // Takes the keyword if it comes next
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.at += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.expected(&keyword.to_uppercase()))
+        }
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(Token::Symbol(s)) if *s == symbol => {
+                self.at += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        if self.symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.expected(&format!("`{}`", symbol)))
+        }
+    }
+
+    fn expected(&self, what: &str) -> String {
+        match self.peek() {
+            Some(token) => format!("expected {} before {}", what, token.describe()),
+            None => format!("expected {} at the end", what),
+        }
+    }
+
+    fn query(mut self) -> Result<Query, String> {
+        self.expect_keyword("select")?;
+        let select = if self.symbol("*") { None } else { Some(self.select_list()?) };
+        self.expect_keyword("from")?;
+        let from_text = match self.next() {
+            Some(Token::Word(path) | Token::Text(path) | Token::Quoted(path)) => path,
+            _ => {
+                self.at -= 1;
+                return Err(self.expected("a path after FROM"));
+            }
+        };
+        let from = parse_path(&from_text)?;
+        let filter = if self.keyword("where") { Some(self.or()?) } else { None };
+        let mut group_by = Vec::new();
+        if self.keyword("group") {
+            self.expect_keyword("by")?;
+            loop {
+                group_by.push(self.column()?);
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+        let mut order_by = Vec::new();
+        if self.keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                let item = self.item()?;
+                let descending = self.keyword("desc");
+                if !descending {
+                    self.keyword("asc");
+                }
+                order_by.push((item, descending));
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+        let limit = if self.keyword("limit") { Some(self.count("LIMIT")?) } else { None };
+        let offset = if self.keyword("offset") { self.count("OFFSET")? } else { 0 };
+        if self.peek().is_some() {
+            return Err(self.expected("the end of the query"));
+        }
+        Ok(Query {
+            select,
+            from,
+            from_text,
+            filter,
+            group_by,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    fn select_list(&mut self) -> Result<Vec<Selected>, String> {
+        let mut selected = Vec::new();
+        loop {
+            let item = self.item()?;
+            let name = if self.keyword("as") {
+                match self.next() {
+                    Some(Token::Word(name) | Token::Quoted(name)) => name,
+                    _ => {
+                        self.at -= 1;
+                        return Err(self.expected("a name after AS"));
+                    }
+                }
+            } else {
+                item.name()
+            };
+            selected.push(Selected { item, name });
+            if !self.symbol(",") {
+                return Ok(selected);
+            }
+        }
+    }
+
+    // A column or `COUNT(*)`, `SUM(price)` and the like
+    fn item(&mut self) -> Result<Item, String> {
+        let aggregate = match (self.peek(), self.tokens.get(self.at + 1)) {
+            (Some(Token::Word(word)), Some(Token::Symbol("("))) => match word.to_ascii_lowercase().as_str() {
+                "count" => Aggregate::Count,
+                "sum" => Aggregate::Sum,
+                "avg" => Aggregate::Avg,
+                "min" => Aggregate::Min,
+                "max" => Aggregate::Max,
+                _ => return Err(format!("unknown function `{}`, try COUNT, SUM, AVG, MIN or MAX", word)),
+            },
+            _ => return Ok(Item::Column(self.column()?)),
+        };
+        self.at += 2;
+        let column = match aggregate {
+            Aggregate::Count if self.symbol("*") => None,
+            _ => Some(self.column()?),
+        };
+        self.expect_symbol(")")?;
+        Ok(Item::Aggregate(aggregate, column))
+    }
+
+    fn column(&mut self) -> Result<Column, String> {
+        match self.next() {
+            Some(Token::Word(name)) if !is_keyword(&name) => Ok(Column {
+                path: parse_path(&name)?,
+                name,
+            }),
+            Some(Token::Quoted(name)) => Ok(Column {
+                path: vec![name.clone()],
+                name,
+            }),
+            _ => {
+                self.at -= 1;
+                Err(self.expected("a column"))
+            }
+        }
+    }
+
+    fn count(&mut self, clause: &str) -> Result<usize, String> {
+        match self.next() {
+            Some(Token::Number(n)) if n.is_u64() => Ok(n.as_u64().unwrap() as usize),
+            _ => {
+                self.at -= 1;
+                Err(self.expected(&format!("a count after {}", clause)))
+            }
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.symbol("(") {
+            let expr = self.or()?;
+            self.expect_symbol(")")?;
+            return Ok(expr);
+        }
+        self.predicate()
+    }
+
+    fn predicate(&mut self) -> Result<Expr, String> {
+        let left = self.operand()?;
+        if self.keyword("is") {
+            let negated = self.keyword("not");
+            self.expect_keyword("null")?;
+            return Ok(negate(Expr::IsNull(left), negated));
+        }
+        let negated = self.keyword("not");
+        if self.keyword("like") {
+            return match self.next() {
+                Some(Token::Text(pattern)) => Ok(negate(Expr::Like(left, pattern.chars().collect()), negated)),
+                _ => {
+                    self.at -= 1;
+                    Err(self.expected("a quoted pattern after LIKE"))
+                }
+            };
+        }
+        if self.keyword("in") {
+            self.expect_symbol("(")?;
+            let mut values = Vec::new();
+            loop {
+                match self.operand()? {
+                    Operand::Literal(value) => values.push(value),
+                    Operand::Column(column) => return Err(format!("IN takes values, not the column `{}`", column.name)),
+                }
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+            self.expect_symbol(")")?;
+            return Ok(negate(Expr::In(left, values), negated));
+        }
+        if negated {
+            return Err(self.expected("LIKE or IN after NOT"));
+        }
+        let op = match self.next() {
+            Some(Token::Symbol("=" | "==")) => Op::Equal,
+            Some(Token::Symbol("!=" | "<>")) => Op::NotEqual,
+            Some(Token::Symbol("<")) => Op::Less,
+            Some(Token::Symbol("<=")) => Op::LessOrEqual,
+            Some(Token::Symbol(">")) => Op::Greater,
+            Some(Token::Symbol(">=")) => Op::GreaterOrEqual,
+            _ => {
+                self.at -= 1;
+                return Err(self.expected("a comparison"));
+            }
+        };
+        Ok(Expr::Compare(left, op, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let literal = match self.peek() {
+            Some(Token::Text(text)) => Value::String(text.clone()),
+            Some(Token::Number(n)) => Value::Number(n.clone()),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("true") => Value::Bool(true),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("false") => Value::Bool(false),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("null") => Value::Null,
+            _ => return Ok(Operand::Column(self.column()?)),
+        };
+        self.at += 1;
+        Ok(Operand::Literal(literal))
+    }
+}
+
+// Words that end a column list or a condition; quote a column to use one
+const KEYWORDS: &[&str] = &[
+    "select", "from", "where", "group", "order", "by", "limit", "offset", "and", "or", "not", "is", "null", "like",
+    "in", "as", "asc", "desc", "true", "false",
+];
+
+fn is_keyword(word: &str) -> bool {
+    KEYWORDS.iter().any(|k| word.eq_ignore_ascii_case(k))
+}
+
+fn negate(expr: Expr, negated: bool) -> Expr {
+    if negated {
+        Expr::Not(Box::new(expr))
+    } else {
+        expr
+    }
+}
+
+impl Item {
+    // The key a result gets when it is not named with AS
+    fn name(&self) -> String {
+        match self {
+            Item::Column(column) => column.name.clone(),
+            Item::Aggregate(aggregate, column) => format!(
+                "{}({})",
+                aggregate.name(),
+                column.as_ref().map_or("*", |c| c.name.as_str())
+            ),
+        }
+    }
+}
+
+impl Aggregate {
+    fn name(self) -> &'static str {
+        match self {
+            Aggregate::Count => "count",
+            Aggregate::Sum => "sum",
+            Aggregate::Avg => "avg",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+        }
+    }
+
+    fn apply(self, column: Option<&Column>, rows: &[&Value]) -> Value {
+        let column = match column {
+            Some(column) => column,
+            None => return Value::from(rows.len()),
+        };
+        let values = rows.iter().map(|row| column.value(row)).filter(|v| !v.is_null());
+        match self {
+            Aggregate::Count => Value::from(values.count()),
+            Aggregate::Sum | Aggregate::Avg => {
+                let numbers: Vec<&Number> = values.filter_map(|v| v.as_number()).collect();
+                if numbers.is_empty() {
+                    return Value::Null;
+                }
+                let sum: f64 = numbers.iter().filter_map(|n| n.as_f64()).sum();
+                match self {
+                    Aggregate::Avg => float(sum / numbers.len() as f64),
+                    // Whole numbers add up to a whole number while they fit
+                    _ => numbers
+                        .iter()
+                        .try_fold(0i64, |total, n| total.checked_add(n.as_i64()?))
+                        .map_or_else(|| float(sum), Value::from),
+                }
+            }
+            Aggregate::Min => values.min_by(|a, b| order(a, b)).cloned().unwrap_or(Value::Null),
+            Aggregate::Max => values.max_by(|a, b| order(a, b)).cloned().unwrap_or(Value::Null),
+        }
+    }
+}
+
+fn float(n: f64) -> Value {
+    Number::from_f64(n).map_or(Value::Null, Value::Number)
+}
+
+impl Column {
+    fn value<'a>(&self, row: &'a Value) -> &'a Value {
+        row.pointer(&to_pointer(&self.path)).unwrap_or(&Value::Null)
+    }
+}
+
+impl Operand {
+    fn value<'a>(&'a self, row: &'a Value) -> &'a Value {
+        match self {
+            Operand::Column(column) => column.value(row),
+            Operand::Literal(value) => value,
+        }
+    }
+}
+
+impl Expr {
+    fn holds(&self, row: &Value) -> bool {
+        match self {
+            Expr::Or(left, right) => left.holds(row) || right.holds(row),
+            Expr::And(left, right) => left.holds(row) && right.holds(row),
+            Expr::Not(expr) => !expr.holds(row),
+            Expr::Compare(left, op, right) => compare(left.value(row), *op, right.value(row)),
+            Expr::Like(operand, pattern) => match operand.value(row) {
+                Value::String(s) => like(&s.chars().collect::<Vec<_>>(), pattern),
+                _ => false,
+            },
+            Expr::IsNull(operand) => operand.value(row).is_null(),
+            Expr::In(operand, values) => values.iter().any(|v| compare(operand.value(row), Op::Equal, v)),
+        }
+    }
+}
+
+// Like SQL, nothing is equal or unequal to null; values of different types
+// are only ever unequal
+fn compare(left: &Value, op: Op, right: &Value) -> bool {
+    if left.is_null() || right.is_null() {
+        return false;
+    }
+    let ordering = match (left, right) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (a, b) => (a == b).then_some(Ordering::Equal),
+    };
+    match (op, ordering) {
+        (Op::Equal, ordering) => ordering == Some(Ordering::Equal),
+        (Op::NotEqual, ordering) => ordering != Some(Ordering::Equal),
+        (_, None) => false,
+        (Op::Less, Some(o)) => o == Ordering::Less,
+        (Op::LessOrEqual, Some(o)) => o != Ordering::Greater,
+        (Op::Greater, Some(o)) => o == Ordering::Greater,
+        (Op::GreaterOrEqual, Some(o)) => o != Ordering::Less,
+    }
+}
+
+// `%` matches any run of chars and `_` any one char
+fn like(text: &[char], pattern: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('%', rest)) => (0..=text.len()).any(|skip| like(&text[skip..], rest)),
+        Some((&p, rest)) => match text.split_first() {
+            Some((&c, text)) => (p == '_' || p == c) && like(text, rest),
+            None => false,
+        },
+    }
+}
+
+// Sort order for ORDER BY, MIN and MAX: nulls, booleans, numbers, strings,
+// then arrays and objects
+fn order(a: &Value, b: &Value) -> Ordering {
+    fn rank(v: &Value) -> u8 {
+        match v {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Object(_) => 5,
+        }
+    }
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => {
+            a.as_f64().partial_cmp(&b.as_f64()).unwrap_or(Ordering::Equal)
+        }
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (a, b) => rank(a).cmp(&rank(b)),
+    }
+}
+
+impl Query {
+    fn run(&self, json: &Value) -> Result<Value, String> {
+        let rows = match json.pointer(&to_pointer(&self.from)) {
+            Some(Value::Array(rows)) => rows,
+            Some(_) => return Err(format!("FROM {} is not an array", self.from_text)),
+            None => return Err(format!("FROM {}: no value there", self.from_text)),
+        };
+        let rows: Vec<&Value> = rows
+            .iter()
+            .filter(|row| self.filter.as_ref().map_or(true, |f| f.holds(row)))
+            .collect();
+
+        let grouped = !self.group_by.is_empty()
+            || self.select.iter().flatten().any(|s| matches!(s.item, Item::Aggregate(..)));
+        // Each result with the row it came from, which ORDER BY can also use
+        // while rows are not grouped
+        let mut results: Vec<(Option<&Value>, Map<String, Value>)> = if grouped {
+            self.group(&rows)?
+        } else {
+            rows.iter().map(|row| (Some(*row), self.project(row))).collect()
+        };
+
+        if !self.order_by.is_empty() {
+            let mut keyed = Vec::with_capacity(results.len());
+            for (row, result) in results {
+                let keys = self
+                    .order_by
+                    .iter()
+                    .map(|(item, _)| self.sort_key(item, row, &result))
+                    .collect::<Result<Vec<Value>, String>>()?;
+                keyed.push((keys, row, result));
+            }
+            keyed.sort_by(|(a, ..), (b, ..)| {
+                a.iter()
+                    .zip(b)
+                    .zip(&self.order_by)
+                    .map(|((a, b), (_, descending))| if *descending { order(b, a) } else { order(a, b) })
+                    .find(|o| o.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+            results = keyed.into_iter().map(|(_, row, result)| (row, result)).collect();
+        }
+
+        Ok(Value::Array(
+            results
+                .into_iter()
+                .skip(self.offset)
+                .take(self.limit.unwrap_or(usize::MAX))
+                .map(|(row, result)| match (&self.select, row) {
+                    (None, Some(row)) => row.clone(),
+                    _ => Value::Object(result),
+                })
+                .collect(),
+        ))
+    }
+
+    // The selected columns of one row; `SELECT *` keeps the row as it is
+    fn project(&self, row: &Value) -> Map<String, Value> {
+        let mut result = Map::new();
+        for selected in self.select.iter().flatten() {
+            if let Item::Column(column) = &selected.item {
+                result.insert(selected.name.clone(), column.value(row).clone());
+            }
+        }
+        result
+    }
+
+    // One result per group, in the order the groups first appear
+    fn group<'a>(&self, rows: &[&'a Value]) -> Result<Vec<(Option<&'a Value>, Map<String, Value>)>, String> {
+        let selected = self
+            .select
+            .as_ref()
+            .ok_or("SELECT * cannot be grouped; name the columns")?;
+        for s in selected {
+            if let Item::Column(column) = &s.item {
+                if !self.group_by.iter().any(|g| g.path == column.path) {
+                    return Err(format!("`{}` must be in GROUP BY or inside COUNT, SUM, AVG, MIN or MAX", column.name));
+                }
+            }
+        }
+        let mut groups: Vec<Vec<&Value>> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for row in rows {
+            let key: Vec<&Value> = self.group_by.iter().map(|g| g.value(row)).collect();
+            let key = serde_json::to_string(&key).map_err(|e| e.to_string())?;
+            let position = *positions.entry(key).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[position].push(row);
+        }
+        // Aggregates over no rows at all still give one result, as in SQL
+        if groups.is_empty() && self.group_by.is_empty() {
+            groups.push(Vec::new());
+        }
+        Ok(groups
+            .iter()
+            .map(|rows| {
+                let mut result = Map::new();
+                for s in selected {
+                    let value = match &s.item {
+                        Item::Column(column) => rows.first().map_or(Value::Null, |row| column.value(row).clone()),
+                        Item::Aggregate(aggregate, column) => aggregate.apply(column.as_ref(), rows),
+                    };
+                    result.insert(s.name.clone(), value);
+                }
+                (None, result)
+            })
+            .collect())
+    }
+
+    // What a result is sorted by: a selected column by its name or alias, or
+    // any column of the row while rows are not grouped
+    fn sort_key(&self, item: &Item, row: Option<&Value>, result: &Map<String, Value>) -> Result<Value, String> {
+        let name = item.name();
+        if let Some(value) = result.get(&name) {
+            return Ok(value.clone());
+        }
+        match (item, row) {
+            (Item::Column(column), Some(row)) => Ok(column.value(row).clone()),
+            _ => Err(format!("ORDER BY {} must be one of the selected columns", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn doc() -> Value {
+        json!({"items": [
+            {"id": 1, "status": "ok", "latency_ms": 120},
+            {"id": 2, "status": "error", "latency_ms": 340},
+            {"id": 3, "status": "ok", "latency_ms": 560, "user": null},
+            {"id": 4, "status": "it's", "latency_ms": 80}
+        ]})
+    }
+
+    // The ids of the rows a query selects, in order
+    fn ids(query: &str) -> Vec<i64> {
+        let rows = run(query, &doc()).unwrap();
+        rows.as_array().unwrap().iter().map(|row| row["id"].as_i64().unwrap()).collect()
+    }
+
+    fn error(query: &str) -> String {
+        run(query, &doc()).err().unwrap()
+    }
+
+    #[test]
+    fn select_where_order_limit() {
+        assert_eq!(
+            run("SELECT id, latency_ms FROM .items WHERE latency_ms > 200 ORDER BY latency_ms DESC LIMIT 1", &doc()),
+            Ok(json!([{"id": 3, "latency_ms": 560}]))
+        );
+        assert_eq!(ids("select * from items order by status, id desc offset 1"), [4, 3, 1]);
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(ids("SELECT id FROM .items WHERE status = 'ok' OR status = 'error' AND latency_ms < 100"), [1, 3]);
+        assert_eq!(ids("SELECT id FROM .items WHERE (status = 'ok' OR status = 'error') AND latency_ms < 200"), [1]);
+        assert_eq!(ids("SELECT id FROM .items WHERE NOT status = 'ok' AND id > 2"), [4]);
+    }
+
+    #[test]
+    fn quoting() {
+        // Quotes are escaped by doubling them; double quotes name a column
+        assert_eq!(ids("SELECT id FROM .items WHERE status = 'it''s'"), [4]);
+        assert_eq!(
+            run(r#"SELECT "status" AS "the status" FROM .items WHERE id = 4"#, &doc()),
+            Ok(json!([{"the status": "it's"}]))
+        );
+    }
+
+    #[test]
+    fn like_in_and_null() {
+        assert_eq!(ids("SELECT id FROM .items WHERE status LIKE 'e%'"), [2]);
+        assert_eq!(ids("SELECT id FROM .items WHERE status NOT LIKE '_k'"), [2, 4]);
+        assert_eq!(ids("SELECT id FROM .items WHERE id IN (1, 4)"), [1, 4]);
+        assert_eq!(ids("SELECT id FROM .items WHERE user IS NULL"), [1, 2, 3, 4]);
+        // Nothing compares with null, not even unequal
+        assert_eq!(ids("SELECT id FROM .items WHERE user != 1"), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn group_by() {
+        assert_eq!(
+            run("SELECT status, COUNT(*) AS n, SUM(latency_ms) FROM .items GROUP BY status ORDER BY n DESC, status", &doc()),
+            Ok(json!([
+                {"status": "ok", "n": 2, "sum(latency_ms)": 680},
+                {"status": "error", "n": 1, "sum(latency_ms)": 340},
+                {"status": "it's", "n": 1, "sum(latency_ms)": 80}
+            ]))
+        );
+        assert_eq!(run("SELECT COUNT(*) FROM .items WHERE id > 9", &doc()), Ok(json!([{"count(*)": 0}])));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("SELECT id FROM .items WHERE"), "expected a column at the end");
+        assert_eq!(error("SELECT id FROM .items LIMIT x"), "expected a count after LIMIT before `x`");
+        assert_eq!(error("SELECT id FROM .items WHERE id NOT = 1"), "expected LIKE or IN after NOT before `=`");
+        assert_eq!(error("SELECT id FROM .items WHERE status = 'ok"), "unclosed '");
+        assert_eq!(error("SELECT id FROM .items id"), "expected the end of the query before `id`");
+        assert_eq!(error("SELECT id FROM .missing"), "FROM .missing: no value there");
+        assert_eq!(
+            error("SELECT status, id FROM .items GROUP BY status"),
+            "`id` must be in GROUP BY or inside COUNT, SUM, AVG, MIN or MAX"
+        );
+        assert_eq!(error("SELECT id FROM .items WHERE id IN (id)"), "IN takes values, not the column `id`");
+    }
+}
@@ -0,0 +1,371 @@
+// Joining the pages of a paginated API, saved one response per file, into
+// one array
+
+use crate::app::to_pointer;
+use crate::command::parse_path;
+use crate::source;
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// One file's share of the stitched array.
+pub struct Page {
+    pub path: PathBuf,
+    // The index of its first element in the array, and how many it gave
+    pub first: usize,
+    pub len: usize,
+}
+
+/// Where each element of a stitched document came from.
+pub struct Stitch {
+    pub items: String,
+    pub pages: Vec<Page>,
+    // Whether the order came from the pages' `next` cursors rather than
+    // their file names
+    pub by_cursor: bool,
+}
+
+impl Stitch {
+    /// The page element `index` came from, numbered from 1, and the page.
+    pub fn page_of(&self, index: usize) -> Option<(usize, &Page)> {
+        let page = self.pages.iter().position(|p| index >= p.first && index < p.first + p.len)?;
+        Some((page + 1, &self.pages[page]))
+    }
+
+    pub fn summary(&self) -> String {
+        let elements = self.pages.iter().map(|p| p.len).sum::<usize>();
+        let order = if self.by_cursor { "following next cursors" } else { "by file name" };
+        format!("Stitched {} elements of `{}` from {} pages, {}", elements, self.items, self.pages.len(), order)
+    }
+}
+
+// A page as read, before it is put in order
+struct Loaded {
+    path: PathBuf,
+    items: Vec<Value>,
+    next: Option<String>,
+    // Every other scalar of the page, one of which a `next` may name
+    keys: Vec<String>,
+}
+
+/// Reads `files` and joins the arrays at `items` in each of them.
+pub fn stitch(files: &[PathBuf], items: &str) -> Result<(Value, Stitch), String> {
+    let path = parse_path(items)?;
+    if files.is_empty() {
+        return Err(String::from("no pages to stitch"));
+    }
+    let mut pages = Vec::new();
+    for file in files {
+        let data = fs::read_to_string(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+        let mut json = source::parse(&data).map_err(|e| format!("{}: {}", file.display(), e))?;
+        let items = match json.pointer_mut(&to_pointer(&path)).map(Value::take) {
+            Some(Value::Array(items)) => items,
+            Some(_) => return Err(format!("{}: `{}` is not an array", file.display(), items)),
+            None => return Err(format!("{}: there is no `{}`", file.display(), items)),
+        };
+        let mut keys = Vec::new();
+        let mut next = None;
+        scalars(&json, 0, &mut |key, value| match next {
+            None if is_next(key) => next = Some(value),
+            _ => keys.push(value),
+        });
+        pages.push(Loaded {
+            path: file.clone(),
+            items,
+            next,
+            keys,
+        });
+    }
+    pages.sort_by(|a, b| natural_cmp(&a.path.to_string_lossy(), &b.path.to_string_lossy()));
+    let by_cursor = match cursor_order(&pages) {
+        Some(order) => {
+            let mut slots: Vec<Option<Loaded>> = pages.into_iter().map(Some).collect();
+            pages = order.into_iter().filter_map(|i| slots[i].take()).collect();
+            true
+        }
+        None => false,
+    };
+
+    let mut all = Vec::new();
+    let mut stitch = Stitch {
+        items: items.to_owned(),
+        pages: Vec::new(),
+        by_cursor,
+    };
+    for page in pages {
+        stitch.pages.push(Page {
+            path: page.path,
+            first: all.len(),
+            len: page.items.len(),
+        });
+        all.extend(page.items);
+    }
+    Ok((Value::Array(all), stitch))
+}
+
+// Calls `f` with the key and text of the scalars of a page's metadata, a
+// few levels deep; the stitched array has been taken out already
+fn scalars(value: &Value, depth: usize, f: &mut impl FnMut(&str, String)) {
+    if let Value::Object(map) = value {
+        for (key, value) in map {
+            match value {
+                Value::String(text) => f(key, text.clone()),
+                Value::Number(n) => f(key, n.to_string()),
+                Value::Object(_) if depth < 3 => scalars(value, depth + 1, f),
+                _ => {}
+            }
+        }
+    }
+}
+
+// Keys such as `next`, `next_cursor`, `nextPageToken` or `next_page`
+fn is_next(key: &str) -> bool {
+    let key = key.to_ascii_lowercase().replace(['_', '-'], "");
+    key == "next" || (key.starts_with("next") && ["cursor", "page", "token", "url", "link"].iter().any(|k| key.contains(k)))
+}
+
+// The order the pages link up in, if every page but the last names exactly
+// one other page as the next, by file name or by a cursor it carries
+fn cursor_order(pages: &[Loaded]) -> Option<Vec<usize>> {
+    if pages.len() < 2 {
+        return None;
+    }
+    // What every page has, such as a total, names none of them
+    let common = |key: &String| pages.iter().all(|page| page.keys.contains(key));
+    let follows = |from: &Loaded, to: &Loaded| match from.next.as_deref() {
+        Some(next) => {
+            let name = to.path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            next.ends_with(&name) || to.keys.iter().any(|key| names(next, key) && !common(key))
+        }
+        None => false,
+    };
+    let mut next_of = Vec::new();
+    for (i, from) in pages.iter().enumerate() {
+        let targets: Vec<usize> = (0..pages.len()).filter(|j| *j != i && follows(from, &pages[*j])).collect();
+        match targets.as_slice() {
+            [] => next_of.push(None),
+            [j] => next_of.push(Some(*j)),
+            _ => return None,
+        }
+    }
+    let first = (0..pages.len()).find(|i| !next_of.contains(&Some(*i)))?;
+    let mut order = vec![first];
+    while let Some(next) = next_of[*order.last()?] {
+        if order.contains(&next) {
+            return None;
+        }
+        order.push(next);
+    }
+    Some(order).filter(|order| order.len() == pages.len())
+}
+
+// Whether a `next` value names a page by `key`: as it is, or at the end of
+// a URL such as `...?cursor=c2`
+fn names(next: &str, key: &str) -> bool {
+    match next.strip_suffix(key) {
+        Some(rest) => !key.is_empty() && !rest.ends_with(|c: char| c.is_alphanumeric()),
+        None => false,
+    }
+}
+
+// Compares names with runs of digits as numbers, so page2 comes before page10
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (x, y) = match (a.chars().next(), b.chars().next()) {
+            (Some(x), Some(y)) => (x, y),
+            _ => return a.len().cmp(&b.len()),
+        };
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let a_end = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+            let b_end = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+            let (x, y) = (a[..a_end].trim_start_matches('0'), b[..b_end].trim_start_matches('0'));
+            let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(y));
+            if ordering.is_ne() {
+                return ordering;
+            }
+            a = &a[a_end..];
+            b = &b[b_end..];
+        } else {
+            if x != y {
+                return x.cmp(&y);
+            }
+            a = &a[x.len_utf8()..];
+            b = &b[y.len_utf8()..];
+        }
+    }
+}
+
+/// The files matching `pattern`, in which `*` and `?` may stand for parts of
+/// the file name, as the shell would expand it.
+pub fn glob(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let path = Path::new(pattern);
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    if !name.contains(['*', '?']) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let entries = fs::read_dir(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_file())
+        .filter(|entry| wildcard_match(&name, &entry.file_name().to_string_lossy()))
+        .map(|entry| match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.join(entry.file_name()),
+            _ => PathBuf::from(entry.file_name()),
+        })
+        .collect();
+    if files.is_empty() {
+        return Err(format!("no files match `{}`", pattern));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The pages next to `file` named like it, with the numbers in the name
+/// free to differ: `page1.json` finds `page2.json` and `page10.json`.
+pub fn siblings(file: &Path) -> Result<Vec<PathBuf>, String> {
+    let name = file.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut pattern = String::new();
+    for c in name.chars() {
+        match c {
+            '0'..='9' if pattern.ends_with('*') => {}
+            '0'..='9' => pattern.push('*'),
+            c => pattern.push(c),
+        }
+    }
+    if !pattern.contains('*') {
+        return Err(format!("{} has no page number in its name; list the pages", name));
+    }
+    let pattern = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.join(pattern).to_string_lossy().into_owned(),
+        _ => pattern,
+    };
+    // `*` matches letters too; keep the names that only differ in digits
+    let shape = |name: &str| name.chars().filter(|c| !c.is_ascii_digit()).collect::<String>();
+    Ok(glob(&pattern)?
+        .into_iter()
+        .filter(|p| p.file_name().map_or(false, |n| shape(&n.to_string_lossy()) == shape(&name)))
+        .collect())
+}
+
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    match pattern.chars().next() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len())
+            .filter(|i| name.is_char_boundary(*i))
+            .any(|i| wildcard_match(&pattern[1..], &name[i..])),
+        Some('?') => match name.chars().next() {
+            Some(c) => wildcard_match(&pattern[1..], &name[c.len_utf8()..]),
+            None => false,
+        },
+        Some(p) => name.starts_with(p) && wildcard_match(&pattern[p.len_utf8()..], &name[p.len_utf8()..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    fn page(name: &str, next: Option<&str>, keys: &[&str]) -> Loaded {
+        Loaded {
+            path: PathBuf::from(name),
+            items: Vec::new(),
+            next: next.map(String::from),
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn numbers_in_names_compare_as_numbers() {
+        assert_eq!(natural_cmp("page2.json", "page10.json"), Ordering::Less);
+        assert_eq!(natural_cmp("page10.json", "page9.json"), Ordering::Greater);
+        assert_eq!(natural_cmp("page002.json", "page2.json"), Ordering::Equal);
+        assert_eq!(natural_cmp("page.json", "page1.json"), Ordering::Less);
+        assert_eq!(natural_cmp("a9", "b1"), Ordering::Less);
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("page*.json", "page12.json"));
+        assert!(wildcard_match("page*.json", "page.json"));
+        assert!(wildcard_match("p?ge.json", "päge.json"));
+        assert!(!wildcard_match("page?.json", "page.json"));
+        assert!(!wildcard_match("page*.json", "page1.jsonl"));
+        assert!(wildcard_match("*", ""));
+    }
+
+    #[test]
+    fn next_keys_and_cursors() {
+        assert!(is_next("next"));
+        assert!(is_next("nextPageToken"));
+        assert!(is_next("next_cursor"));
+        assert!(!is_next("next_id"));
+        assert!(names("https://api/items?cursor=c2", "c2"));
+        assert!(names("c2", "c2"));
+        assert!(!names("https://api/items?cursor=abc2", "c2"));
+        assert!(!names("c2", ""));
+    }
+
+    #[test]
+    fn pages_follow_their_cursors() {
+        // Sorted by name the pages would be a, b, c
+        let pages = [
+            page("a.json", None, &["c3", "30"]),
+            page("b.json", Some("/items?cursor=c3"), &["c1", "30"]),
+            page("c.json", Some("b.json"), &["c2", "30"]),
+        ];
+        assert_eq!(cursor_order(&pages), Some(vec![2, 1, 0]));
+        // A total every page has names none of them
+        let pages = [page("a.json", Some("30"), &["30"]), page("b.json", None, &["30"])];
+        assert_eq!(cursor_order(&pages), None);
+        // Two pages following the same one leave the order to the names
+        let pages = [
+            page("a.json", Some("x"), &[]),
+            page("b.json", None, &["x"]),
+            page("c.json", None, &["x"]),
+        ];
+        assert_eq!(cursor_order(&pages), None);
+    }
+
+    #[test]
+    fn elements_name_their_page() {
+        let stitch = Stitch {
+            items: String::from("data"),
+            pages: vec![
+                Page { path: PathBuf::from("page1.json"), first: 0, len: 2 },
+                Page { path: PathBuf::from("page2.json"), first: 2, len: 0 },
+                Page { path: PathBuf::from("page3.json"), first: 2, len: 1 },
+            ],
+            by_cursor: false,
+        };
+        assert_eq!(stitch.page_of(1).map(|(n, p)| (n, p.path.clone())), Some((1, PathBuf::from("page1.json"))));
+        assert_eq!(stitch.page_of(2).map(|(n, p)| (n, p.path.clone())), Some((3, PathBuf::from("page3.json"))));
+        assert!(stitch.page_of(3).is_none());
+    }
+
+    #[test]
+    fn siblings_differ_only_in_digits() {
+        let dir = std::env::temp_dir().join(format!("json_tui_siblings_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["page1.json", "page2.json", "page10.json", "pageA.json", "page1.json.bak"] {
+            fs::write(dir.join(name), "[]").unwrap();
+        }
+        let found = siblings(&dir.join("page1.json"));
+        let none = siblings(&dir.join("pageA.json"));
+        fs::remove_dir_all(&dir).unwrap();
+        let names: Vec<String> = found
+            .unwrap()
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["page1.json", "page10.json", "page2.json"]);
+        assert!(none.is_err());
+    }
+}
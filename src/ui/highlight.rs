@@ -0,0 +1,89 @@
+use tui::{
+    style::{Color, Modifier, Style},
+    text::Span,
+};
+
+// Background of the selected node's span in the Input pane
+const SELECTION: Color = Color::DarkGray;
+
+/// Colours one line of raw JSON, using the same palette as the preview.
+///
+/// `start` is the byte offset of `line` in the whole input; the part of the
+/// line that falls inside `selection` (byte offsets too) gets a background.
+/// JSON has no tokens that span lines, so each line can be lexed on its own.
+pub fn json_line(line: &str, start: usize, selection: Option<(usize, usize)>) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    for (from, to, style) in tokens(line) {
+        let (token_start, token_end) = (start + from, start + to);
+        match selection {
+            Some((sel_start, sel_end)) if sel_start < token_end && token_start < sel_end => {
+                let inner_from = sel_start.saturating_sub(start).clamp(from, to);
+                let inner_to = sel_end.saturating_sub(start).clamp(from, to);
+                push(&mut spans, &line[from..inner_from], style);
+                push(&mut spans, &line[inner_from..inner_to], style.bg(SELECTION));
+                push(&mut spans, &line[inner_to..to], style);
+            }
+            _ => push(&mut spans, &line[from..to], style),
+        }
+    }
+    spans
+}
+
+fn push(spans: &mut Vec<Span<'static>>, text: &str, style: Style) {
+    if !text.is_empty() {
+        spans.push(Span::styled(text.to_owned(), style));
+    }
+}
+
+// Splits a line into (start, end, style) runs covering all of it
+fn tokens(line: &str) -> Vec<(usize, usize, Style)> {
+    let bytes = line.as_bytes();
+    let mut runs = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let start = pos;
+        let style = match bytes[pos] {
+            b'"' => {
+                pos += 1;
+                while pos < bytes.len() && bytes[pos] != b'"' {
+                    pos += if bytes[pos] == b'\\' { 2 } else { 1 };
+                }
+                pos = (pos + 1).min(bytes.len());
+                // A string followed by a colon is a key
+                let is_key = line[pos..].trim_start().starts_with(':');
+                Style::default().fg(if is_key { Color::Cyan } else { Color::Green })
+            }
+            b'{' | b'}' | b'[' | b']' | b',' | b':' => {
+                pos += 1;
+                Style::default().fg(Color::Gray)
+            }
+            b' ' | b'\t' | b'\r' => {
+                while pos < bytes.len() && matches!(bytes[pos], b' ' | b'\t' | b'\r') {
+                    pos += 1;
+                }
+                Style::default()
+            }
+            _ => {
+                while pos < bytes.len() && !b"{}[],:\" \t\r".contains(&bytes[pos]) {
+                    pos += 1;
+                }
+                let style = match &line[start..pos] {
+                    "true" | "false" => Style::default().fg(Color::Yellow),
+                    "null" => Style::default().fg(Color::LightYellow),
+                    word if word.starts_with(|c: char| c == '-' || c.is_ascii_digit()) => {
+                        Style::default().fg(Color::Blue)
+                    }
+                    // Not JSON, but keep it visible
+                    _ => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                };
+                // Stray multi-byte characters end up here; keep on a char boundary
+                while !line.is_char_boundary(pos) {
+                    pos += 1;
+                }
+                style
+            }
+        };
+        runs.push((start, pos, style));
+    }
+    runs
+}
@@ -1,4 +1,13 @@
-use crate::app::{Element, Index, Route};
+mod highlight;
+mod preview;
+
+use crate::app::{to_pointer, Areas, Confirmation, Derivation, Editing, Element, Index, Route};
+use crate::diff::{Diff, Mark};
+use crate::keymap::Action;
+use crate::logs::{Level, Row};
+use crate::queries;
+use crate::search::{Matcher, SearchEntry, SearchMode, SearchScope};
+use crate::stitch::Stitch;
 
 use super::app::App;
 use tui::{
@@ -6,10 +15,12 @@ use tui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
     Frame,
 };
 
+use serde_json::Value;
+
 use emoji::symbols::math::PLUS;
 use emoji::symbols::other_symbol::CHECK_MARK;
 pub const PL: &'static str = PLUS.glyph;
@@ -29,6 +40,7 @@ pub fn draw_ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
             [
                 Constraint::Length(3),
                 Constraint::Min(7),
+                Constraint::Length(if app.status.is_some() { 1 } else { 0 }),
             ]
             .as_ref(),
         )
@@ -39,6 +51,11 @@ pub fn draw_ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
     draw_search_ui(f, app, parent_layout[0]);
     // Draw Route
     draw_routes(f, app, parent_layout[1]);
+    // Draw Status
+    if let Some(status) = app.status.as_ref() {
+        let status_para = Paragraph::new(status.as_str()).style(Style::default().fg(Color::Yellow));
+        f.render_widget(status_para, parent_layout[2]);
+    }
 
     //Todo : Draw Route UI
 }
@@ -48,67 +65,470 @@ fn draw_routes<B: Backend>(
     app: &mut App<'_>,
     parent_layout: tui::layout::Rect,
 ) -> () {
+    if app.current_route == Route::Detail || app.current_route == Route::Logs {
+        let area = Layout::default().margin(1).constraints([Constraint::Min(0)].as_ref()).split(parent_layout)[0];
+        app.areas.output = area;
+        app.areas.breadcrumbs.clear();
+        app.areas.tabs.clear();
+        if app.current_route == Route::Logs {
+            draw_logs_route(f, app, area);
+        } else {
+            draw_detail_route(f, app, area);
+        }
+        return;
+    }
+
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
         .margin(1)
         .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
         .split(parent_layout);
 
-    let input = Block::default().title("Input").borders(Borders::ALL);
-    let inputpara = Paragraph::new(app.data)
-        .wrap(Wrap { trim: true })
-        .block(input);
-
-    f.render_widget(inputpara, chunks[0]);
+    if app.show_preview {
+        draw_preview(f, app, chunks[0]);
+    } else {
+        draw_source(f, app, chunks[0]);
+    }
+    app.areas.input = chunks[0];
+    app.areas.output = chunks[1];
+    app.areas.breadcrumbs.clear();
 
     match app.current_route {
         Route::Main => draw_main_routes(f, app, chunks[1]),
-        Route::Search => draw_search_route(f, app, chunks[1])
+        Route::Search => draw_search_route(f, app, chunks[1]),
+        Route::Help => draw_help_route(f, app, chunks[1]),
+        Route::Command => draw_main_routes(f, app, chunks[1]),
+        Route::Jq => draw_jq_route(f, app, chunks[1]),
+        Route::Edit | Route::Confirm => draw_main_routes(f, app, chunks[1]),
+        Route::History => draw_history_route(f, app, chunks[1]),
+        Route::Marks => draw_marks_route(f, app, chunks[1]),
+        Route::Queries => draw_queries_route(f, app, chunks[1]),
+        Route::Detail | Route::Logs => {}
     }
 
     // DRAW Output
 
 }
+// The raw input with line numbers, scrolled to the span of the highlighted
+// node. Only the visible lines are built.
+fn draw_source<B: Backend>(f: &mut Frame<'_, B>, app: &mut App, area: Rect) {
+    let map = match app.source_map.as_ref() {
+        Some(map) => map,
+        None => {
+            let input = Block::default().title(pane_tabs(&mut app.areas, area, false, "")).borders(Borders::ALL);
+            f.render_widget(Paragraph::new(app.data.as_str()).block(input), area);
+            return;
+        }
+    };
+    let height = area.height.saturating_sub(2) as usize;
+    let path = app.selected_path();
+    // The spans are of the text as read; after an edit they may be anywhere
+    let stale = !app.modified.is_empty();
+    let span = if stale { None } else { map.span(&path) };
+    let span_line = span.map(|(start, _)| map.line_of(start));
+    // Follow the selection, but leave manual scrolling alone until it moves.
+    // Its column is only counted then, lines of minified input can be huge.
+    if app.source_path.as_ref() != Some(&path) {
+        if let (Some((start, _)), Some(line)) = (span, span_line) {
+            if line < app.input_scroll || line >= app.input_scroll + height {
+                app.input_scroll = line.saturating_sub(height / 3);
+            }
+            let (line_start, _) = map.line_range(line, &app.data);
+            app.source_column = app.data[line_start..start].chars().count() + 1;
+        }
+        app.source_path = Some(path);
+    }
+    app.input_scroll = app.input_scroll.min(map.line_count().saturating_sub(1));
+
+    let gutter = map.line_count().to_string().len();
+    let text_width = (area.width as usize).saturating_sub(2 + gutter + 1);
+    let end = (app.input_scroll + height).min(map.line_count());
+    let lines: Vec<Spans> = (app.input_scroll..end)
+        .map(|line| {
+            let (mut start, line_end) = map.line_range(line, &app.data);
+            let mut spans = vec![Span::styled(
+                format!("{:>width$} ", line + 1, width = gutter),
+                Style::default().fg(Color::DarkGray),
+            )];
+            // Minified input is one long line: start it at the selection
+            if let (Some((span_start, _)), Some(true)) = (span, span_line.map(|l| l == line)) {
+                if app.data[start..span_start].char_indices().nth(text_width.saturating_sub(1)).is_some() {
+                    spans.push(Span::styled("…", Style::default().fg(Color::DarkGray)));
+                    start = span_start;
+                }
+            }
+            let text = &app.data[start..line_end];
+            let visible = text.char_indices().nth(text_width).map_or(text.len(), |(i, _)| i);
+            spans.extend(highlight::json_line(&text[..visible], start, span));
+            Spans::from(spans)
+        })
+        .collect();
+
+    let title = match span_line {
+        _ if stale => String::from("as read, without the unsaved edits"),
+        Some(line) => format!("line {}, column {}", line + 1, app.source_column),
+        None => String::new(),
+    };
+    let input = Block::default().title(pane_tabs(&mut app.areas, area, false, &title)).borders(Borders::ALL);
+    f.render_widget(Paragraph::new(lines).block(input), area);
+}
+
+// The left pane's title: its Input and Preview tabs, the one shown in bold,
+// then `detail`. Records where the tabs are for clicks.
+fn pane_tabs(areas: &mut Areas, area: Rect, preview: bool, detail: &str) -> Spans<'static> {
+    let style = |active: bool| match active {
+        true => Style::default().add_modifier(Modifier::BOLD),
+        false => Style::default().fg(Color::DarkGray),
+    };
+    let mut x = area.x + 1;
+    areas.tabs.clear();
+    let mut spans = Vec::new();
+    for (i, name) in ["Input", "Preview"].iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::styled(" │ ", Style::default().fg(Color::DarkGray)));
+            x += 3;
+        }
+        let width = (name.len() as u16).min(area.right().saturating_sub(x));
+        areas.tabs.push(Rect::new(x, area.y, width, 1));
+        spans.push(Span::styled(*name, style(preview == (i == 1))));
+        x += name.len() as u16;
+    }
+    if !detail.is_empty() {
+        spans.push(Span::raw(format!("  {}", detail)));
+    }
+    Spans::from(spans)
+}
+
+// Never render more of a subtree than this, however big it is
+const PREVIEW_MAX_LINES: usize = 2000;
+
+// Pretty-printed, coloured JSON of the highlighted node
+fn draw_preview<B: Backend>(f: &mut Frame<'_, B>, app: &mut App, area: Rect) {
+    let path = app.selected_path();
+    if path != app.preview_path {
+        app.preview_scroll = 0;
+        app.preview_path = path;
+    }
+    let pointer = to_pointer(&app.preview_path);
+    let lines = match app.json.as_ref().and_then(|j| j.pointer(&pointer)) {
+        Some(value) => preview::json_lines(value, PREVIEW_MAX_LINES),
+        None => vec![],
+    };
+    let title = pane_tabs(&mut app.areas, area, true, if pointer.is_empty() { "/" } else { pointer.as_str() });
+    let preview = Block::default().title(title).borders(Borders::ALL);
+    let preview_para = Paragraph::new(lines)
+        .block(preview)
+        .scroll((app.preview_scroll, 0));
+    f.render_widget(preview_para, area);
+}
+
+// What the filter in the jq prompt gives, updated as it is typed
+fn draw_jq_route<B: Backend>(f: &mut Frame<'_, B>, app: &mut App, area: Rect) {
+    let (title, lines) = match app.jq_result.as_ref() {
+        _ if app.jq_input.trim().is_empty() => (
+            "jq result",
+            vec![Spans::from(Span::styled(
+                "No filter: Enter shows the original document",
+                Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC),
+            ))],
+        ),
+        Some(Ok(value)) => ("jq result", preview::json_lines(value, PREVIEW_MAX_LINES)),
+        Some(Err(e)) => (
+            "jq error",
+            vec![Spans::from(Span::styled(e.to_owned(), Style::default().fg(Color::Red)))],
+        ),
+        None => (
+            "jq result",
+            vec![Spans::from(Span::styled(
+                match app.jq_waiting {
+                    true => "running… it is shown when it finishes",
+                    false => "running…",
+                },
+                Style::default().fg(Color::DarkGray),
+            ))],
+        ),
+    };
+    let block = Block::default().title(title).borders(Borders::ALL);
+    f.render_widget(Paragraph::new(lines).block(block).wrap(Wrap { trim: false }), area);
+}
+
 fn draw_main_routes<B: Backend>(f: &mut Frame<'_, B>, app : &mut App, area: Rect) -> () {
-    let output = Block::default().title("Output").borders(Borders::ALL);
+    let mut title = breadcrumbs(app, area);
+    let height = area.height.saturating_sub(2) as usize;
+    let counts = app.changes().map(Diff::counts);
+    let (range, mut state) = match app.elements.as_mut() {
+        Some(level) => {
+            level.sync_offset(height);
+            title.0.push(position(level.state.selected(), level.len()));
+            if let Some(derivation) = app.derived.as_ref() {
+                title.0.push(Span::styled(format!("  {}", derivation.label()), Style::default().fg(Color::LightGreen)));
+            } else if let Some(comparison) = app.compare.as_ref() {
+                title.0.push(Span::styled(
+                    format!("  diff {} → {}", comparison.old, comparison.new),
+                    Style::default().fg(Color::LightGreen),
+                ));
+            }
+            // The page the highlighted element, or the one we are in, came from
+            let element = match app.navigation_stack.get(1) {
+                Some(index) => index.parse().ok(),
+                None => level.state.selected(),
+            };
+            if let Some(page) = element.and_then(|i| page_label(app.stitched.as_ref(), i)) {
+                title.0.push(Span::styled(format!("  {}", page), Style::default().fg(Color::LightBlue)));
+            }
+            if let Some((added, removed, changed)) = counts {
+                title.0.push(Span::styled(format!("  +{}", added), Style::default().fg(Color::Green)));
+                title.0.push(Span::styled(format!(" -{}", removed), Style::default().fg(Color::Red)));
+                title.0.push(Span::styled(format!(" ~{}", changed), Style::default().fg(Color::Yellow)));
+                if app.compare.as_ref().map_or(false, |c| c.hide_unchanged) && app.derived.is_none() {
+                    title.0.push(Span::styled("  unchanged hidden", Style::default().fg(Color::DarkGray)));
+                }
+            } else if !app.modified.is_empty() {
+                title.0.push(Span::styled(
+                    format!("  {} modified", app.modified.len()),
+                    Style::default().fg(Color::Yellow),
+                ));
+            }
+            (visible_rows(level.offset, height, level.len()), window_state(level.offset, level.state.selected()))
+        }
+        None => return,
+    };
+    let output = Block::default().title(title).borders(Borders::ALL);
 
-    match app.elements.as_mut() {
-        Some(v) => {
-            // let vec_list = Vec::new();
-            let vec_list: Vec<ListItem<'_>> = v.items.iter().map(|f| get_list_item(f)).collect();
+    // Operations of a patch that failed are listed under the rows
+    let area = match app.derived.as_ref() {
+        Some(Derivation::Patch(preview)) if !preview.problems.is_empty() => {
+            let height = (preview.problems.len() as u16 + 2).min(area.height / 3).max(3);
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(3), Constraint::Length(height)].as_ref())
+                .split(area);
+            let lines: Vec<Spans> = preview
+                .problems
+                .iter()
+                .map(|p| Spans::from(Span::styled(p.as_str(), Style::default().fg(Color::Red))))
+                .collect();
+            let block = Block::default().title("Failed operations").borders(Borders::ALL);
+            f.render_widget(Paragraph::new(lines).block(block).wrap(Wrap { trim: false }), chunks[1]);
+            chunks[0]
+        }
+        _ => area,
+    };
 
-            // println!("Vector is {:#?}", vec_list);
-            let out_put_list = List::new(vec_list)
-                .block(output)
-                .highlight_style(Style::default().add_modifier(Modifier::BOLD))
-                .highlight_symbol(">> ");
-            f.render_stateful_widget(out_put_list, area, &mut v.state)
+    // Only the rows on screen are built, however long the level is
+    let first = range.start;
+    let elements = app.visible_elements(range);
+    let changes = app.changes();
+    // Edited nodes, and the objects and arrays holding them, until saved
+    let mut path = app.navigation_stack[1..].to_vec();
+    let vec_list: Vec<ListItem<'_>> = elements
+        .iter()
+        .enumerate()
+        .map(|(i, element)| {
+            let mut spans = list_spans(element);
+            path.push(app.elements.as_ref().and_then(|l| l.key(first + i)).unwrap_or_default().into_owned());
+            let style = match changes {
+                Some(diff) => diff_style(diff, &path, &mut spans),
+                None if app.is_modified(&path) => Style::default().fg(Color::Yellow).add_modifier(Modifier::ITALIC),
+                None => Style::default(),
+            };
+            path.pop();
+            ListItem::new(spans).style(style)
+        })
+        .collect();
+    let out_put_list = List::new(vec_list)
+        .block(output)
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_symbol(">> ");
+    f.render_stateful_widget(out_put_list, area, &mut state)
+}
+
+// How a row of a document compared with another is shown: added nodes in
+// green, removed ones struck out in red, and changed ones, and anything
+// holding changes, in yellow, with what a changed value was
+fn diff_style(diff: &Diff, path: &[String], spans: &mut Spans) -> Style {
+    match diff.mark(path) {
+        Some(Mark::Added) => Style::default().fg(Color::Green),
+        Some(Mark::Removed) => Style::default().fg(Color::Red).add_modifier(Modifier::CROSSED_OUT),
+        Some(Mark::Changed(before)) => {
+            spans.0.push(Span::styled(
+                format!("  was {}", value_preview(before)),
+                Style::default().fg(Color::DarkGray),
+            ));
+            Style::default().fg(Color::Yellow)
         }
-        None => {
-            panic!("hereere")
+        None if diff.differs(path) => Style::default().fg(Color::Yellow),
+        None => Style::default(),
+    }
+}
+
+// Rows `offset..` that fit in `height`
+fn visible_rows(offset: usize, height: usize, len: usize) -> std::ops::Range<usize> {
+    offset.min(len)..(offset + height).min(len)
+}
+
+// A list state for a window starting at `offset`, selecting the same row
+fn window_state(offset: usize, selected: Option<usize>) -> ListState {
+    let mut state = ListState::default();
+    state.select(selected.and_then(|i| i.checked_sub(offset)));
+    state
+}
+
+// `  482,113 / 1,000,000` after a list title
+fn position<'a>(selected: Option<usize>, len: usize) -> Span<'a> {
+    let current = selected.map_or(String::from("-"), |i| thousands(i + 1));
+    Span::styled(
+        format!("  {} / {}", current, thousands(len)),
+        Style::default().fg(Color::DarkGray),
+    )
+}
+
+// 1000000 as `1,000,000`
+fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
         }
+        out.push(c);
     }
+    out
 }
+
+// Output title showing where we are, e.g. `Output  root › items › 3`.
+// The area of every crumb is recorded so a click can jump back to it.
+fn breadcrumbs<'a>(app: &mut App, area: Rect) -> Spans<'a> {
+    let mut spans = vec![Span::raw("Output  ")];
+    // Titles start one cell in, after the left border
+    let mut x = area.x + 1 + 8;
+    for (depth, key) in app.navigation_stack.iter().enumerate() {
+        if depth > 0 {
+            spans.push(Span::styled(" › ", Style::default().fg(Color::DarkGray)));
+            x += 3;
+        }
+        let crumb = if depth == 0 { String::from("root") } else { key.to_owned() };
+        let width = crumb.chars().count() as u16;
+        let style = if depth + 1 == app.navigation_stack.len() {
+            Style::default().add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::Cyan)
+        };
+        app.areas.breadcrumbs.push(Rect::new(x, area.y, width.min(area.right().saturating_sub(x)), 1));
+        spans.push(Span::styled(crumb, style));
+        x += width;
+    }
+    Spans::from(spans)
+}
+
 fn draw_search_route<B: Backend>(f: &mut Frame<'_, B>, app : &mut App, area: Rect)  {
-    let output = Block::default().title("Output").borders(Borders::ALL);
+    let height = area.height.saturating_sub(2) as usize;
 
     match app.fuzzy_elements.as_mut() {
         Some(v) => {
-            // let vec_list = Vec::new();
-            let vec_list: Vec<ListItem<'_>> = v.items.iter().map(|i| ListItem::new(vec![Spans::from(Span::raw(i))])).collect();
+            v.sync_offset(height);
+            let mut title = format!("Output ({} / {})", thousands(app.search_matched), thousands(app.search_total));
+            if app.search_matched > v.items.len() {
+                title.push_str(&format!("  best {} shown", thousands(v.items.len())));
+            }
+            if app.search_entries.is_empty() && app.search_busy {
+                title.push_str("  indexing…");
+            } else if app.search_busy {
+                title.push_str("  searching…");
+            }
+            let output = Block::default().title(title).borders(Borders::ALL);
+            let entries = &app.search_entries;
+            let matcher = app.search_matcher.as_ref();
+            let scope = app.search_options.scope;
+            let locations = Some(&app.search_locations).filter(|_| app.search_options.mode == SearchMode::JsonPath);
+            let stitched = app.stitched.as_ref();
+            let vec_list: Vec<ListItem<'_>> = v.items[visible_rows(v.offset, height, v.items.len())]
+                .iter()
+                .filter_map(|&i| Some((entries.get(i)?, locations.and_then(|l| l.get(&i)))))
+                .map(|(entry, location)| {
+                    let mut spans = match location {
+                        Some(location) => jsonpath_item(location, entry),
+                        None => search_item(entry, matcher, scope),
+                    };
+                    // Results from stitched pages say which page
+                    let page = entry.path.first().and_then(|i| i.parse().ok()).and_then(|i| page_label(stitched, i));
+                    if let Some(page) = page {
+                        spans.0.push(Span::styled(format!("  {}", page), Style::default().fg(Color::DarkGray)));
+                    }
+                    ListItem::new(spans)
+                })
+                .collect();
 
-            // println!("Vector is {:#?}", vec_list);
             let out_put_list = List::new(vec_list)
                 .block(output)
                 .highlight_style(Style::default().add_modifier(Modifier::BOLD))
                 .highlight_symbol(">> ");
-            f.render_stateful_widget(out_put_list, area, &mut v.state);
+            f.render_stateful_widget(out_put_list, area, &mut window_state(v.offset, v.state.selected()));
         }
         None => {
             panic!("hereere")
         }
     }
 }
+// A search result with its path, key and value in their own colours and the
+// matched characters picked out
+fn search_item<'a>(entry: &'a SearchEntry, matcher: Option<&Matcher>, scope: SearchScope) -> Spans<'a> {
+    let text = entry.text.as_str();
+    let matched = match (matcher, entry.haystack_range(scope)) {
+        (Some(matcher), Some((start, end))) => {
+            let skipped = text[..start].chars().count();
+            matcher.indices(&text[start..end]).into_iter().map(|i| i + skipped).collect()
+        }
+        _ => Vec::new(),
+    };
+    let style_at = |byte: usize, char_index: usize| {
+        let style = if byte < entry.key_start() {
+            Style::default().fg(Color::DarkGray)
+        } else if byte < entry.key_end() {
+            Style::default().fg(Color::Cyan)
+        } else if byte < entry.value_start() {
+            Style::default().fg(Color::Gray)
+        } else {
+            Style::default().fg(Color::Green)
+        };
+        if matched.binary_search(&char_index).is_ok() {
+            style.fg(Color::LightMagenta).add_modifier(Modifier::BOLD | Modifier::UNDERLINED)
+        } else {
+            style
+        }
+    };
+    // Runs of chars with the same style become one span
+    let mut spans = Vec::new();
+    let mut run_start = 0;
+    let mut run_style = style_at(0, 0);
+    for (char_index, (byte, _)) in text.char_indices().enumerate() {
+        let style = style_at(byte, char_index);
+        if style != run_style {
+            spans.push(Span::styled(&text[run_start..byte], run_style));
+            run_start = byte;
+            run_style = style;
+        }
+    }
+    spans.push(Span::styled(&text[run_start..], run_style));
+    Spans::from(spans)
+}
+// A JSONPath match under its normalized path, the form gateways print
+fn jsonpath_item<'a>(location: &'a str, entry: &'a SearchEntry) -> Spans<'a> {
+    Spans::from(vec![
+        Span::styled(location, Style::default().fg(Color::Cyan)),
+        Span::styled(" : ", Style::default().fg(Color::Gray)),
+        Span::styled(&entry.text[entry.value_start()..], Style::default().fg(Color::Green)),
+    ])
+}
+
+// Which stitched page element `index` of the document came from
+fn page_label(stitch: Option<&Stitch>, index: usize) -> Option<String> {
+    let stitch = stitch?;
+    let (number, page) = stitch.page_of(index)?;
+    let name = page.path.file_name().map_or(page.path.to_string_lossy(), |n| n.to_string_lossy());
+    Some(format!("page {}/{} {}", number, stitch.pages.len(), name))
+}
+
 fn draw_search_ui<B: Backend>(f: &mut Frame<B>, app: &App, layout_chunk: Rect) -> () {
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
@@ -116,16 +536,104 @@ fn draw_search_ui<B: Backend>(f: &mut Frame<B>, app: &App, layout_chunk: Rect) -
         .horizontal_margin(1)
         .split(layout_chunk);
 
-    let search = Block::default().title("Search").borders(Borders::ALL);
+    let options = app.search_options;
+    // Case and scope do not apply to JSONPath
+    let mut scope = match options.mode {
+        SearchMode::JsonPath => String::from(options.mode.name()),
+        mode => format!("{} · {} · {}", mode.name(), options.case.name(), options.scope.name()),
+    };
+    if options.subtree && app.navigation_stack.len() > 1 {
+        scope.push_str(&format!(" · in {}", to_pointer(&app.navigation_stack[1..])));
+    }
+    let search_title = Spans::from(vec![
+        Span::raw("Search  "),
+        Span::styled(scope, Style::default().fg(Color::DarkGray)),
+    ]);
+    let search = Block::default().title(search_title).borders(Borders::ALL);
     let searchpara;
-    if app.user_input.len() > 0  {
-        searchpara = Paragraph::new(app.user_input.to_owned())
-        .wrap(Wrap { trim: true })
+    let mut cursor_x = app.user_input.len() as u16;
+    if let Some(reverse) = app.reverse_search.as_ref().filter(|_| app.current_route.takes_text()) {
+        let lines = app.input_history.lines(app.current_route);
+        let found = reverse.found.and_then(|i| lines.get(i)).copied();
+        let prompt = format!(
+            "({}reverse-i-search)`{}': ",
+            if found.is_none() && !reverse.query.is_empty() { "failed " } else { "" },
+            reverse.query
+        );
+        cursor_x = (prompt.chars().count() - 3) as u16;
+        let history = Block::default().title("History  Enter to edit, Esc to cancel").borders(Borders::ALL);
+        searchpara = Paragraph::new(Spans::from(vec![
+            Span::styled(prompt, Style::default().fg(Color::Gray)),
+            Span::styled(found.unwrap_or_default().to_owned(), Style::default().fg(Color::LightMagenta)),
+        ]))
+        .block(history);
+    } else if app.current_route == Route::Command {
+        let command = Block::default().title("Command").borders(Borders::ALL);
+        searchpara = Paragraph::new(format!(":{}", app.command_input))
+        .style(Style::default().fg(Color::LightCyan))
+        .block(command);
+        cursor_x = app.command_input.len() as u16 + 1;
+    } else if app.current_route == Route::Edit {
+        // What the input will become, or why it can't
+        let (check, colour) = match (app.edit_problem(), app.parse_edit()) {
+            (Some(problem), _) => (format!("  {}", problem), Color::Red),
+            (None, Ok(value)) if matches!(app.editing, Editing::Value(_)) => {
+                (format!("  → {}", kind_name(&value)), Color::DarkGray)
+            }
+            (None, _) => (String::new(), Color::DarkGray),
+        };
+        let title = match &app.editing {
+            Editing::Value(path) => format!("Edit {}  as JSON: \"text\", 12, true, null", to_pointer(path)),
+            Editing::Rename(path, _) => format!("Rename a key of {}", to_pointer(path)),
+            Editing::NewKey(path, _) => format!("New key in {}", to_pointer(path)),
+        };
+        let edit = Block::default().title(title).borders(Borders::ALL);
+        searchpara = Paragraph::new(Spans::from(vec![
+            Span::styled(app.edit_input.clone(), Style::default().fg(Color::Yellow)),
+            Span::styled(check, Style::default().fg(colour)),
+        ]))
+        .block(edit);
+        cursor_x = app.edit_input.chars().count() as u16;
+    } else if app.current_route == Route::Confirm {
+        let confirm = Block::default().title("Confirm").borders(Borders::ALL);
+        let question = match app.confirming.as_ref() {
+            Some(Confirmation::Delete(size)) => {
+                format!("Delete {} and the {} values in it?", to_pointer(&app.selected_path()), size - 1)
+            }
+            Some(Confirmation::Reload) => String::from("Reload the file and lose the unsaved edits?"),
+            Some(Confirmation::Quit) => String::from("Quit and lose the unsaved edits?"),
+            Some(Confirmation::Stitch(stitched)) => {
+                format!("Show the {} stitched pages and lose the unsaved edits?", stitched.1.pages.len())
+            }
+            None => String::new(),
+        };
+        searchpara = Paragraph::new(format!("{}  y/n", question))
+        .style(Style::default().fg(Color::Red))
+        .block(confirm);
+        cursor_x = 0;
+    } else if app.current_route == Route::Jq {
+        let jq = Block::default().title("jq  Enter to browse the result, empty for the original").borders(Borders::ALL);
+        searchpara = Paragraph::new(app.jq_input.as_str())
+        .style(Style::default().fg(Color::LightGreen))
+        .block(jq);
+        cursor_x = app.jq_input.chars().count() as u16;
+    } else if app.user_input.len() > 0  {
+        let mut spans = vec![Span::raw(app.user_input.to_owned())];
+        if let Some(error) = app.search_error.as_ref() {
+            spans.push(Span::styled(format!("  {}", error), Style::default().fg(Color::Red)));
+        }
+        searchpara = Paragraph::new(Spans::from(spans))
         .style(Style::default().fg(Color::LightMagenta))
         .block(search);
 
     } else {
-        searchpara = Paragraph::new(Text::from("Type / to Search") )
+        let search_key = app
+            .keymap
+            .keys_for(Route::Main, Action::StartSearch)
+            .first()
+            .map(|k| k.to_string())
+            .unwrap_or_default();
+        searchpara = Paragraph::new(Text::from(format!("Type {} to Search", search_key)))
         .wrap(Wrap { trim: true })
         .style(Style::default().fg(Color::Gray).add_modifier(Modifier::ITALIC))
         .block(search);
@@ -135,17 +643,21 @@ fn draw_search_ui<B: Backend>(f: &mut Frame<B>, app: &App, layout_chunk: Rect) -
     f.render_widget(searchpara, chunks[0]);
 
     f.set_cursor(                
-        layout_chunk.x + app.user_input.len() as u16 + 2,
+        layout_chunk.x + cursor_x + 2,
     // Move one line down, from the border to the input line
         layout_chunk.y + 1,
     );
 
     let help = Block::default().title("Help").borders(Borders::ALL);
 
-    let text = vec![Spans::from(vec![
-        Span::raw("Type ?"),
-        Span::styled("line", Style::default().add_modifier(Modifier::ITALIC)),
-    ])];
+    let help_key = app.keymap.keys_for(app.current_route, Action::ToggleHelp);
+    let text = match help_key.first() {
+        Some(key) => vec![Spans::from(vec![
+            Span::raw(key.to_string()),
+            Span::styled(" help", Style::default().add_modifier(Modifier::ITALIC)),
+        ])],
+        None => vec![],
+    };
 
     // let block = Block::default()
     //     .title("Help")
@@ -160,10 +672,325 @@ fn draw_search_ui<B: Backend>(f: &mut Frame<B>, app: &App, layout_chunk: Rect) -
 
 }
 
-pub fn get_list_item(element: &Element) -> ListItem {
+// Every visited location, oldest first, with the current one marked
+fn draw_history_route<B: Backend>(f: &mut Frame<'_, B>, app: &mut App, area: Rect) {
+    let history = Block::default().title("History").borders(Borders::ALL);
+    let cursor = app.history.cursor();
+    let items: Vec<ListItem> = app
+        .history
+        .entries()
+        .iter()
+        .enumerate()
+        .map(|(i, location)| {
+            let pointer = location.pointer();
+            let mut spans = vec![
+                Span::styled(
+                    if i == cursor { "● " } else { "  " },
+                    Style::default().fg(Color::Cyan),
+                ),
+                Span::raw(if pointer.is_empty() { String::from("/") } else { pointer }),
+            ];
+            if let Some(selected) = location.selected {
+                spans.push(Span::styled(
+                    format!("  [{}]", selected),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            ListItem::new(Spans::from(spans))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(history)
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_symbol(">> ");
+    f.render_stateful_widget(list, area, &mut app.history_list);
+}
+
+// Marks with the value each one points at right now
+fn draw_marks_route<B: Backend>(f: &mut Frame<'_, B>, app: &mut App, area: Rect) {
+    let marks = Block::default().title("Marks").borders(Borders::ALL);
+    let json = app.json.as_ref().unwrap();
+    let items: Vec<ListItem> = app
+        .marks
+        .iter()
+        .map(|(letter, path)| {
+            let pointer = to_pointer(path);
+            let value = match json.pointer(&pointer) {
+                Some(v) => Span::raw(value_preview(v)),
+                None => Span::styled("(missing)", Style::default().fg(Color::Red)),
+            };
+            ListItem::new(Spans::from(vec![
+                Span::styled(format!("'{} ", letter), Style::default().fg(Color::Cyan)),
+                Span::raw(if pointer.is_empty() { String::from("/") } else { pointer }),
+                Span::styled(" : ", Style::default().fg(Color::DarkGray)),
+                value,
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(marks)
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_symbol(">> ");
+    f.render_stateful_widget(list, area, &mut app.marks_list);
+}
+
+// The project's saved queries, each with what it runs
+fn draw_queries_route<B: Backend>(f: &mut Frame<'_, B>, app: &mut App, area: Rect) {
+    let title = match app.saved_queries.file.as_ref() {
+        Some(file) => format!("Saved queries  {}", file.display()),
+        None => format!("Saved queries  no {} found here or above", queries::FILE_NAME),
+    };
+    let block = Block::default().title(title).borders(Borders::ALL);
+    let items: Vec<ListItem> = app
+        .saved_queries
+        .queries
+        .iter()
+        .map(|query| {
+            let mut spans = vec![
+                Span::styled(query.name.clone(), Style::default().fg(Color::Cyan)),
+                Span::styled(format!("  {} ", query.kind.name()), Style::default().fg(Color::DarkGray)),
+                Span::styled(query.kind.text().to_owned(), Style::default().fg(Color::Green)),
+            ];
+            if let Some(description) = query.description.as_ref() {
+                spans.push(Span::styled(
+                    format!("  {}", description),
+                    Style::default().fg(Color::Gray).add_modifier(Modifier::ITALIC),
+                ));
+            }
+            ListItem::new(Spans::from(spans))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_symbol(">> ");
+    f.render_stateful_widget(list, area, &mut app.queries_list);
+}
+
+// Tracing records as log lines: time, level, target, span names, message
+// and fields. Enter writes out a record's spans with their fields.
+fn draw_logs_route<B: Backend>(f: &mut Frame<'_, B>, app: &mut App, area: Rect) {
+    let view = match app.logs.as_mut() {
+        Some(view) => view,
+        None => return,
+    };
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut title = match view.min_level {
+        Level::Trace => String::from("Logs  all levels"),
+        level => format!("Logs  {} and above", level.name()),
+    };
+    if let Some(target) = view.target.as_ref() {
+        title.push_str(&format!(" · target {}", target));
+    }
+    if let Some(field) = view.group_by.as_ref() {
+        title.push_str(&format!(" · by {}", field));
+    }
+    title.push_str(&format!("  {} of {} records", view.shown, view.records.len()));
+    let block = Block::default().title(title).borders(Borders::ALL);
+
+    let height = area.height.saturating_sub(2) as usize;
+    view.sync_offset(height);
+    let range = visible_rows(view.offset, height, view.rows.len());
+    let items: Vec<ListItem> = view.rows[range]
+        .iter()
+        .map(|row| {
+            let index = match row {
+                Row::Group(value, count) => {
+                    let field = view.group_by.as_deref().unwrap_or_default();
+                    let heading = match value {
+                        Some(value) => format!("{} = {}", field, value),
+                        None => format!("no {}", field),
+                    };
+                    return ListItem::new(Spans::from(vec![
+                        Span::styled(heading, Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+                        Span::styled(format!("  {} record{}", count, if *count == 1 { "" } else { "s" }), dim),
+                    ]));
+                }
+                Row::Record(index) => *index,
+            };
+            let record = &view.records[index];
+            let (level, colour) = match record.level {
+                Some(level) => (level.name(), level_colour(level)),
+                None => ("?", Color::Gray),
+            };
+            let mut spans = vec![
+                Span::styled(format!("{} ", record.timestamp), dim),
+                Span::styled(format!("{:5} ", level), Style::default().fg(colour).add_modifier(Modifier::BOLD)),
+                Span::styled(format!("{} ", record.target), Style::default().fg(Color::Gray)),
+            ];
+            let expanded = view.expanded.contains(&index);
+            if !record.spans.is_empty() && !expanded {
+                let names: Vec<&str> = record.spans.iter().map(|s| s.name.as_str()).collect();
+                spans.push(Span::styled(format!("{}: ", names.join(" › ")), Style::default().fg(Color::LightCyan)));
+            }
+            spans.push(Span::raw(record.message.clone()));
+            for (key, value) in record.fields.iter() {
+                spans.push(Span::styled(format!(" {}=", key), dim));
+                spans.push(Span::raw(value.clone()));
+            }
+            let mut lines = vec![Spans::from(spans)];
+            if expanded {
+                let mut breadcrumb = vec![Span::styled("    ", dim)];
+                for (i, span) in record.spans.iter().enumerate() {
+                    if i > 0 {
+                        breadcrumb.push(Span::styled(" › ", dim));
+                    }
+                    breadcrumb.push(Span::styled(span.name.clone(), Style::default().fg(Color::LightCyan)));
+                    let fields: Vec<String> = span.fields.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                    breadcrumb.push(Span::styled(format!("{{{}}}", fields.join(", ")), dim));
+                }
+                if record.spans.is_empty() {
+                    breadcrumb.push(Span::styled("not in a span", dim));
+                }
+                lines.push(Spans::from(breadcrumb));
+            }
+            ListItem::new(lines)
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_symbol(">> ");
+    f.render_stateful_widget(list, area, &mut window_state(view.offset, view.list.selected()));
+}
+
+fn level_colour(level: Level) -> Color {
+    match level {
+        Level::Error => Color::Red,
+        Level::Warn => Color::Yellow,
+        Level::Info => Color::Green,
+        Level::Debug => Color::Blue,
+        Level::Trace => Color::Magenta,
+    }
+}
+
+// Largest integer a JavaScript number (an f64) holds exactly
+const MAX_SAFE_INTEGER: u64 = 9_007_199_254_740_991;
+
+// A scalar in full: strings unescaped and wrapped, numbers classified
+fn draw_detail_route<B: Backend>(f: &mut Frame<'_, B>, app: &mut App, area: Rect) {
+    let pointer = to_pointer(&app.detail_path);
+    let value = app.json.as_ref().and_then(|j| j.pointer(&pointer));
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut lines: Vec<Spans> = Vec::new();
+
+    let kind = match value {
+        Some(Value::String(text)) => {
+            lines.push(Spans::from(Span::styled(
+                format!("{} chars, {} bytes", text.chars().count(), text.len()),
+                dim,
+            )));
+            lines.push(Spans::from(""));
+            lines.extend(text.lines().map(|l| Spans::from(l.to_owned())));
+            "string"
+        }
+        Some(Value::Number(n)) => {
+            lines.push(Spans::from(Span::styled(n.to_string(), Style::default().fg(Color::Blue))));
+            lines.push(Spans::from(""));
+            // Classified as written, so integers too big for an i64 or u64
+            // are still integers
+            let text = n.to_string();
+            let (class, unsafe_int) = if text.contains(['.', 'e', 'E']) {
+                let x = n.as_f64().unwrap_or_default();
+                ("float", x.fract() == 0.0 && x.abs() > MAX_SAFE_INTEGER as f64)
+            } else {
+                let digits = text.trim_start_matches('-');
+                let max = MAX_SAFE_INTEGER.to_string();
+                ("integer", (digits.len(), digits) > (max.len(), max.as_str()))
+            };
+            lines.push(Spans::from(format!("Type: {}", class)));
+            lines.push(if unsafe_int {
+                Spans::from(Span::styled(
+                    "Outside JavaScript's safe integer range (±9007199254740991): JSON.parse would lose precision",
+                    Style::default().fg(Color::Yellow),
+                ))
+            } else {
+                Spans::from("Within JavaScript's safe integer range")
+            });
+            "number"
+        }
+        Some(Value::Bool(b)) => {
+            lines.push(Spans::from(b.to_string()));
+            "boolean"
+        }
+        Some(Value::Null) => {
+            lines.push(Spans::from(Span::styled("NULL", Style::default().fg(Color::LightYellow))));
+            "null"
+        }
+        Some(_) => "container",
+        None => {
+            lines.push(Spans::from(Span::styled("(missing)", Style::default().fg(Color::Red))));
+            "missing"
+        }
+    };
+
+    let title = format!(
+        "{} ({})",
+        if pointer.is_empty() { "/" } else { pointer.as_str() },
+        kind
+    );
+    let detail = Block::default().title(title).borders(Borders::ALL);
+    let detail_para = Paragraph::new(lines)
+        .block(detail)
+        .wrap(Wrap { trim: false })
+        .scroll((app.detail_scroll, 0));
+    f.render_widget(detail_para, area);
+}
+
+// One line summary of a value, short enough for a list row
+fn value_preview(value: &Value) -> String {
+    let preview = match value {
+        Value::Object(map) => format!("{{...}} ({} keys)", map.len()),
+        Value::Array(items) => format!("[...] ({} items)", items.len()),
+        Value::Null => String::from("NULL"),
+        other => other.to_string(),
+    };
+    if preview.chars().count() > 80 {
+        preview.chars().take(79).chain(std::iter::once('…')).collect()
+    } else {
+        preview
+    }
+}
+
+// Help is generated from the active keymap, so it always matches what the keys do
+fn draw_help_route<B: Backend>(f: &mut Frame<'_, B>, app: &mut App, area: Rect) {
+    let help = Block::default().title("Help").borders(Borders::ALL);
+
+    let mut lines: Vec<Spans> = Vec::new();
+    for problem in app.config_problems.iter() {
+        lines.push(Spans::from(Span::styled(
+            problem.as_str(),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+    for route in Route::ALL.iter().copied() {
+        if !lines.is_empty() {
+            lines.push(Spans::from(""));
+        }
+        lines.push(Spans::from(Span::styled(
+            route.name().to_uppercase(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        for (action, keys) in app.keymap.bindings(route) {
+            let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<String>>().join(", ");
+            lines.push(Spans::from(vec![
+                Span::styled(format!("  {:<28}", keys), Style::default().fg(Color::LightMagenta)),
+                Span::raw(action.description()),
+            ]));
+        }
+    }
+
+    let help_para = Paragraph::new(lines)
+        .block(help)
+        .scroll((app.help_scroll, 0));
+    f.render_widget(help_para, area);
+}
+
+// One row of the Output list
+fn list_spans<'a>(element: &'a Element<'a>) -> Spans<'a> {
     match element {
         Element::Array(k, v) => match k {
-            Index::Key(s) => ListItem::new(Spans::from(vec![
+            Index::Key(s) => Spans::from(vec![
                 Span::styled(PL, Style::default().fg(Color::Red)),
                 Span::raw(" "),
                 Span::styled(
@@ -180,10 +1007,10 @@ pub fn get_list_item(element: &Element) -> ListItem {
                 Span::raw(":"),
                 Span::raw(" "),
                 Span::raw("[...]"),
-            ])),
+            ]),
         },
         Element::Object(k, v) => match k {
-            Index::Key(s) => ListItem::new(Spans::from(vec![
+            Index::Key(s) => Spans::from(vec![
                 Span::styled(PL, Style::default().fg(Color::Red)),
                 Span::raw(" "),
                 Span::styled(
@@ -200,11 +1027,11 @@ pub fn get_list_item(element: &Element) -> ListItem {
                 Span::raw(":"),
                 Span::raw(" "),
                 Span::raw("{...}"),
-            ])),
+            ]),
         },
 
         Element::Bool(k, v) => match k {
-            Index::Key(s) => ListItem::new(Spans::from(vec![
+            Index::Key(s) => Spans::from(vec![
                 Span::styled(CHK, Style::default().fg(Color::Red)),
                 Span::raw(" "),
                 Span::raw(" "),
@@ -219,10 +1046,10 @@ pub fn get_list_item(element: &Element) -> ListItem {
                     Some(false) => "false",
                     None => "false",
                 }),
-            ])),
+            ]),
         },
         Element::Number(k, v) => match k {
-            Index::Key(s) => ListItem::new(Spans::from(vec![
+            Index::Key(s) => Spans::from(vec![
                 Span::styled(CHK, Style::default().fg(Color::Red)),
                 Span::raw(" "),
                 Span::raw(" "),
@@ -233,10 +1060,10 @@ pub fn get_list_item(element: &Element) -> ListItem {
                 Span::raw(":"),
                 Span::raw(" "),
                 Span::styled(v.to_string(), Style::default().fg(Color::Blue)),
-            ])),
+            ]),
         },
         Element::String(k, v) => match k {
-            Index::Key(s) => ListItem::new(Spans::from(vec![
+            Index::Key(s) => Spans::from(vec![
                 Span::styled(CHK, Style::default().fg(Color::Red)),
                 Span::raw(" "),
                 Span::raw(" "),
@@ -247,10 +1074,10 @@ pub fn get_list_item(element: &Element) -> ListItem {
                 Span::raw(":"),
                 Span::raw(" "),
                 Span::raw(v.to_string()),
-            ])),
+            ]),
         },
         Element::Null(k) => match k {
-            Index::Key(s) => ListItem::new(Spans::from(vec![
+            Index::Key(s) => Spans::from(vec![
                 Span::raw(" "),
                 Span::raw("(-)"),
                 Span::raw(" "),
@@ -259,7 +1086,18 @@ pub fn get_list_item(element: &Element) -> ListItem {
                 Span::raw(":"),
                 Span::raw(" "),
                 Span::styled("NULL", Style::default().fg(Color::LightYellow)),
-            ])),
+            ]),
         },
     }
-}
\ No newline at end of file
+}
+// The JSON type of a value, as the editor reports it
+fn kind_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
@@ -0,0 +1,114 @@
+use serde_json::Value;
+use tui::{
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+};
+
+const INDENT: &str = "  ";
+
+/// Pretty-prints `value` as coloured lines, stopping after `max_lines`.
+///
+/// The value is walked directly instead of going through
+/// `serde_json::to_string_pretty`, so a huge subtree costs no more than the
+/// lines that are actually shown. A truncated preview ends with a marker line.
+pub fn json_lines(value: &Value, max_lines: usize) -> Vec<Spans<'static>> {
+    let mut printer = Printer {
+        lines: Vec::new(),
+        current: Vec::new(),
+        max_lines,
+        truncated: false,
+    };
+    printer.value(value, 0);
+    printer.newline();
+    if printer.truncated {
+        printer.lines.truncate(max_lines);
+        printer.lines.push(Spans::from(Span::styled(
+            format!("… truncated after {} lines", max_lines),
+            Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC),
+        )));
+    }
+    printer.lines
+}
+
+struct Printer {
+    lines: Vec<Spans<'static>>,
+    current: Vec<Span<'static>>,
+    max_lines: usize,
+    truncated: bool,
+}
+
+impl Printer {
+    fn push(&mut self, text: String, style: Style) {
+        self.current.push(Span::styled(text, style));
+    }
+
+    fn punct(&mut self, text: &'static str) {
+        self.current.push(Span::styled(text, Style::default().fg(Color::Gray)));
+    }
+
+    fn newline(&mut self) {
+        if self.current.is_empty() {
+            return;
+        }
+        if self.lines.len() >= self.max_lines {
+            self.truncated = true;
+            self.current.clear();
+            return;
+        }
+        self.lines.push(Spans::from(std::mem::take(&mut self.current)));
+    }
+
+    fn indent(&mut self, depth: usize) {
+        self.current.push(Span::raw(INDENT.repeat(depth)));
+    }
+
+    fn value(&mut self, value: &Value, depth: usize) {
+        if self.truncated {
+            return;
+        }
+        match value {
+            Value::Object(map) if map.is_empty() => self.punct("{}"),
+            Value::Array(items) if items.is_empty() => self.punct("[]"),
+            Value::Object(map) => {
+                self.punct("{");
+                for (i, (key, v)) in map.iter().enumerate() {
+                    self.newline();
+                    if self.truncated {
+                        return;
+                    }
+                    self.indent(depth + 1);
+                    self.push(Value::String(key.to_owned()).to_string(), Style::default().fg(Color::Cyan));
+                    self.punct(": ");
+                    self.value(v, depth + 1);
+                    if i + 1 < map.len() {
+                        self.punct(",");
+                    }
+                }
+                self.newline();
+                self.indent(depth);
+                self.punct("}");
+            }
+            Value::Array(items) => {
+                self.punct("[");
+                for (i, v) in items.iter().enumerate() {
+                    self.newline();
+                    if self.truncated {
+                        return;
+                    }
+                    self.indent(depth + 1);
+                    self.value(v, depth + 1);
+                    if i + 1 < items.len() {
+                        self.punct(",");
+                    }
+                }
+                self.newline();
+                self.indent(depth);
+                self.punct("]");
+            }
+            Value::String(_) => self.push(value.to_string(), Style::default().fg(Color::Green)),
+            Value::Number(n) => self.push(n.to_string(), Style::default().fg(Color::Blue)),
+            Value::Bool(b) => self.push(b.to_string(), Style::default().fg(Color::Yellow)),
+            Value::Null => self.push(String::from("null"), Style::default().fg(Color::LightYellow)),
+        }
+    }
+}
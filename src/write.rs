@@ -0,0 +1,138 @@
+// Writing the document back to disk with `:w`
+
+use serde::Serialize;
+use serde_json::ser::{PrettyFormatter, Serializer};
+use serde_json::{Map, Value};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// How the written JSON is laid out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Layout {
+    // Indented with this string, one member per line
+    Pretty(String),
+    Minified,
+    // One minified value per line, for NDJSON input
+    Lines,
+}
+
+impl Layout {
+    /// The layout of `data`, the text the document was read from.
+    pub fn of(data: &str) -> Layout {
+        if serde_json::from_str::<Value>(data).is_err() {
+            return Layout::Lines;
+        }
+        // Nested members on lines of their own give the indent away; the
+        // first indented line is one level deep
+        let indent = data.trim().lines().skip(1).find_map(|line| {
+            let rest = line.trim_start_matches([' ', '\t']);
+            Some(&line[..line.len() - rest.len()]).filter(|indent| !indent.is_empty() && !rest.is_empty())
+        });
+        match indent {
+            Some(indent) => Layout::Pretty(indent.to_owned()),
+            None if data.trim().contains('\n') => Layout::Pretty(String::from("  ")),
+            None => Layout::Minified,
+        }
+    }
+
+    /// Reads `--pretty`, `--pretty=4`, `--pretty=tab`, `--minify` or
+    /// `--original`, which is the layout of `data`.
+    pub fn parse(option: &str, data: &str) -> Result<Layout, String> {
+        match option.split_once('=') {
+            None if option == "--pretty" => Ok(Layout::Pretty(String::from("  "))),
+            Some(("--pretty", "tab")) => Ok(Layout::Pretty(String::from("\t"))),
+            Some(("--pretty", n)) => match n.parse::<usize>() {
+                Ok(n) if n <= 16 => Ok(Layout::Pretty(" ".repeat(n))),
+                _ => Err(format!("--pretty takes a number of spaces or tab, not `{}`", n)),
+            },
+            None if option == "--minify" => Ok(Layout::Minified),
+            None if option == "--original" => Ok(Layout::of(data)),
+            _ => Err(format!("unknown option `{}`; try --pretty=N, --minify or --original", option)),
+        }
+    }
+}
+
+/// The text of `json` laid out as asked, ending in a newline.
+pub fn render(json: &Value, layout: &Layout) -> Result<String, String> {
+    let mut text = match layout {
+        Layout::Pretty(indent) => {
+            let mut out = Vec::new();
+            let mut serializer = Serializer::with_formatter(&mut out, PrettyFormatter::with_indent(indent.as_bytes()));
+            json.serialize(&mut serializer).map_err(|e| e.to_string())?;
+            String::from_utf8(out).map_err(|e| e.to_string())?
+        }
+        Layout::Minified => json.to_string(),
+        Layout::Lines => match json {
+            Value::Array(items) => items.iter().map(Value::to_string).collect::<Vec<_>>().join("\n"),
+            other => other.to_string(),
+        },
+    };
+    text.push('\n');
+    Ok(text)
+}
+
+/// Writes `text` to a file next to `path` and renames it over `path`, so
+/// the file is never left half written. Permissions of an existing file
+/// are kept.
+pub fn write_atomic(path: &Path, text: &str) -> io::Result<()> {
+    let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file name"))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(".{}.tmp", process::id()));
+    let temp = path.with_file_name(temp_name);
+    let written = fs::write(&temp, text).and_then(|_| match fs::metadata(path) {
+        Ok(metadata) => fs::set_permissions(&temp, metadata.permissions()),
+        Err(_) => Ok(()),
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&temp, path)) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Where the state file `name` is kept, e.g. `~/.local/state/json_tui/marks.json`
+/// on Linux.
+pub fn state_path(name: &str) -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join("json_tui").join(name))
+}
+
+/// The object saved in the state file `name`, if there is one.
+pub fn read_state(name: &str) -> Option<Map<String, Value>> {
+    let contents = fs::read_to_string(state_path(name)?).ok()?;
+    match serde_json::from_str(&contents).ok()? {
+        Value::Object(state) => Some(state),
+        _ => None,
+    }
+}
+
+/// Saves `state` as the state file `name`. Without a state directory
+/// nothing is saved.
+pub fn write_state(name: &str, state: &Map<String, Value>) -> io::Result<()> {
+    let path = match state_path(name) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_atomic(&path, &serde_json::to_string_pretty(state)?)
+}
+
+/// Splits the argument of `:w` into layout options and the path, which may
+/// contain spaces.
+pub fn parse_args(arg: &str) -> (Vec<&str>, Option<PathBuf>) {
+    let mut options = Vec::new();
+    let mut rest = arg.trim();
+    while rest.starts_with("--") {
+        let (option, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        options.push(option);
+        rest = after.trim_start();
+    }
+    (options, Some(rest).filter(|p| !p.is_empty()).map(PathBuf::from))
+}